@@ -0,0 +1,49 @@
+//! Detects the version of the ViennaRNA library that librna-sys generates its bindings from.
+//! Salt corrections only exist since ViennaRNA 2.6, so `vienna_salt` is set when they are there.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// First ViennaRNA version whose model details have a salt concentration
+const SALT_VERSION: (u32, u32) = (2, 6);
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(vienna_salt)");
+    println!("cargo:rerun-if-env-changed=DEP_RNA_INCLUDE");
+    if env::var_os("CARGO_FEATURE_VIENNA").is_none() {
+        return;
+    }
+
+    // librna-sys (links = "RNA") exports the ViennaRNA include directory it used
+    let include = env::var("DEP_RNA_INCLUDE").unwrap_or_else(|_| "/usr/include".to_string());
+    let version = [
+        Path::new(&include).join("version.h"),
+        Path::new(&include).join("ViennaRNA").join("version.h"),
+    ]
+    .iter()
+    .find_map(|header| fs::read_to_string(header).ok())
+    .and_then(|header| parse_version(&header));
+
+    match version {
+        Some(version) if version >= SALT_VERSION => println!("cargo:rustc-cfg=vienna_salt"),
+        Some((major, minor)) => println!(
+            "cargo:warning=ViennaRNA {}.{} has no salt corrections, only standard salt folds",
+            major, minor
+        ),
+        None => println!(
+            "cargo:warning=Could not find the ViennaRNA version in {}, salt corrections are off",
+            include
+        ),
+    }
+}
+
+/// Major and minor version from `#define VRNA_VERSION "X.Y.Z"`
+fn parse_version(header: &str) -> Option<(u32, u32)> {
+    let line = header
+        .lines()
+        .find(|line| line.trim_start().starts_with("#define VRNA_VERSION "))?;
+    let version = line.split('"').nth(1)?;
+    let mut numbers = version.split('.').map(|number| number.parse::<u32>().ok());
+    Some((numbers.next()??, numbers.next()??))
+}
@@ -0,0 +1,118 @@
+#ifndef OSTIR_H
+#define OSTIR_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define OSTIR_OK 0
+
+#define OSTIR_ERROR_NULL_POINTER -1
+
+#define OSTIR_ERROR_INVALID_ARGUMENT -2
+
+#define OSTIR_ERROR_PREDICTION -3
+
+#define OSTIR_ERROR_OUT_OF_RANGE -4
+
+// Opaque prediction context holding the settings and the rows of the last prediction
+typedef struct OstirContext OstirContext;
+
+// One prediction. String pointers are owned by the context and stay valid until the next
+// call to `ostir_predict` or `ostir_context_free`.
+typedef struct OstirRow {
+  const char *name;
+  const char *start_codon;
+  size_t start_position;
+  double expression;
+  int64_t rbs_distance_bp;
+  double dg_total;
+  double dg_rrna_mrna;
+  double dg_mrna;
+  double dg_spacing;
+  double dg_standby;
+  double dg_start_codon;
+  const char *mrna_structure;
+  const char *mrna_rrna_structure;
+} OstirRow;
+
+// Creates a context with the default OSTIR settings. Free it with `ostir_context_free`.
+OstirContext *ostir_context_new(void);
+
+// # Safety
+// `ctx` must come from `ostir_context_new` and must not be used afterwards.
+void ostir_context_free(OstirContext *ctx);
+
+// Message describing the last failed call, or an empty string
+//
+// # Safety
+// `ctx` must be a valid context.
+const char *ostir_last_error(const OstirContext *ctx);
+
+// Sets the anti-Shine-Dalgarno sequence
+//
+// # Safety
+// `ctx` must be a valid context and `asd` a NUL-terminated string.
+int ostir_set_asd(OstirContext *ctx, const char *asd);
+
+// Sets the start codons as a comma separated list, e.g. "ATG,GTG,TTG"
+//
+// # Safety
+// `ctx` must be a valid context and `start_codons` a NUL-terminated string.
+int ostir_set_start_codons(OstirContext *ctx, const char *start_codons);
+
+// Sets a numeric model parameter by name: beta, log_k, optimal_spacing, cutoff (both
+// upstream_cutoff and downstream_cutoff), upstream_cutoff, downstream_cutoff, full_transcript
+// (nonzero to fold whole transcripts), standby_site_length, footprint, energy_cutoff,
+// hybridization_penalty, temperature, monovalent or magnesium (salt concentrations in mol/L).
+// Lengths and cutoffs must be non-negative integers.
+//
+// # Safety
+// `ctx` must be a valid context and `name` a NUL-terminated string.
+int ostir_set_parameter(OstirContext *ctx, const char *name, double value);
+
+// # Safety
+// `ctx` must be a valid context.
+int ostir_set_threads(OstirContext *ctx, size_t threads);
+
+// # Safety
+// `ctx` must be a valid context.
+int ostir_set_circular(OstirContext *ctx, bool circular);
+
+// Only considers start codons between these 1-indexed positions (inclusive). Passing 0 for
+// both resets the range to the whole sequence.
+//
+// # Safety
+// `ctx` must be a valid context.
+int ostir_set_range(OstirContext *ctx, size_t start, size_t end);
+
+// Predicts every start codon of `sequence`, replacing the rows of the previous prediction
+//
+// # Safety
+// `ctx` must be a valid context. `name` may be null; otherwise it and `sequence` must be
+// NUL-terminated strings.
+int ostir_predict(OstirContext *ctx, const char *name, const char *sequence);
+
+// Number of rows produced by the last `ostir_predict`
+//
+// # Safety
+// `ctx` must be a valid context.
+size_t ostir_num_rows(const OstirContext *ctx);
+
+// Copies row `row` of the last prediction into `out`
+//
+// # Safety
+// `ctx` must be a valid context and `out` must point to writable memory for an `OstirRow`.
+int ostir_get_row(OstirContext *ctx, size_t row, OstirRow *out);
+
+// Reads any numeric output column by its CSV header name (e.g. "dG_rRNA:mRNA") for row `row`
+//
+// # Safety
+// `ctx` must be a valid context, `column` a NUL-terminated string and `out` writable.
+int ostir_get_value(OstirContext *ctx, size_t row, const char *column, double *out);
+
+#endif  /* OSTIR_H */
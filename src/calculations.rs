@@ -1,15 +1,19 @@
 use crate::constants::*;
-use crate::types::{CoFoldResult, DanglesSetting, FoldResult, MonoFoldResult};
-use crate::vienna_wrapper::*;
-use std::cmp::{max, min};
+use crate::folding::{coordinates_to_dots, dots_to_coordinates, FoldingBackend};
+use crate::types::{
+    CoFoldResult, DanglesSetting, Ensemble, FoldResult, ModelParameters, MonoFoldResult, MrnaState,
+};
+use std::cmp::min;
+use std::collections::HashSet;
 use std::error::Error;
+use std::ops::Range;
 
 ///Calculate a "kinetic score", a heuristic measure of the maximum time required for
 ///the mRNA secondary structure to form. This is related to the RNA polymer model by David et. al.
 ///This heuristic should not be used in any way to quantify the folding kinetics of an mRNA sequence
 ///because it completely ignores cooperative RNA folding mechanisms, such as zipping or strand
 ///displacement. Here, we use it to eliminate mRNA sequences that MAY fold slowly.
-fn calc_kinetic_score(fold: &MonoFoldResult) -> Result<(f64, f64), Box<dyn Error>> {
+pub(crate) fn calc_kinetic_score(fold: &MonoFoldResult) -> Result<(f64, f64), Box<dyn Error>> {
     let mrnalen = fold.seqs.len() as usize;
     let mut largest_range_helix = 0;
 
@@ -30,18 +34,334 @@ fn calc_kinetic_score(fold: &MonoFoldResult) -> Result<(f64, f64), Box<dyn Error
     Ok((kinetic_score, min_bp_prob))
 }
 
-fn calc_dg_mrna<'a>(
-    trimmed_mrna: &'a Vec<&'a str>,
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_dg_mrna<'a>(
+    trimmed_mrna: &'a [&'a str],
     start_pos: usize,
     dangles: &'_ DanglesSetting,
     constraints: &'_ str,
+    reactivities: &'_ [Option<f64>],
+    params: &'_ ModelParameters,
+    state: &'_ MrnaState,
+    backend: &'_ dyn FoldingBackend,
 ) -> Result<FoldResult<'a>, Box<dyn Error>> {
     // Calculates the dG_mRNA given the mRNA sequence
 
-    let constraints = &constraints[std::cmp::max(0, start_pos as isize - CUTOFF as isize) as usize
-        ..std::cmp::min(trimmed_mrna.len(), start_pos + CUTOFF)];
+    let begin = params.window_start(start_pos);
+    let window = begin..begin + trimmed_mrna[0].len();
+    let constraints = if constraints.is_empty() {
+        ""
+    } else {
+        &constraints[window.clone()]
+    };
+    // Nucleotides unwound by a terminating ribosome can not fold
+    let constraints = state.unwound_constraints(constraints, window.clone());
+
+    let window_reactivities = reactivity_window(reactivities, window);
+    fold_mrna(
+        trimmed_mrna,
+        &constraints,
+        &window_reactivities,
+        dangles,
+        params,
+        state,
+        backend,
+    )
+}
+
+///Calculates dG_mRNA when the ribosome loads on the nascent transcript. Successive prefixes of the
+///transcript, from the start of the folding window, are folded every COTRANSCRIPTIONAL_STEP nt,
+///from the one ending with the start codon to the one transcribed loading_time later at
+///elongation_rate, which can reach past the folding window up to the end of the mRNA. Pairs formed
+///in an earlier prefix are kept unless breaking them lowers the energy by more than
+///REFOLDING_BARRIER.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_dg_mrna_cotranscriptional(
+    mrna: &str,
+    start_pos: usize,
+    dangles: &DanglesSetting,
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Result<f64, Box<dyn Error>> {
+    let window = params.folding_window(start_pos, mrna.len());
+    let first_end = min(start_pos + 3, mrna.len());
+    let transcribed = (params.elongation_rate * params.loading_time)
+        .max(0.0)
+        .round() as usize;
+    let last_end = min(first_end + transcribed, mrna.len());
+    let mut ends: Vec<usize> = (first_end..last_end)
+        .step_by(COTRANSCRIPTIONAL_STEP)
+        .collect();
+    ends.push(last_end);
+
+    let mut previous = String::new();
+    let mut d_g = 0.0;
+    for end in ends {
+        let prefix = vec![&mrna[window.start..end]];
+        let prefix_constraints = if constraints.is_empty() {
+            ""
+        } else {
+            &constraints[window.start..end]
+        };
+        let prefix_constraints = state.unwound_constraints(prefix_constraints, window.start..end);
+        let prefix_reactivities = reactivity_window(reactivities, window.start..end);
+        let fold = |constraints: &str| {
+            fold_mrna(
+                &prefix,
+                constraints,
+                &prefix_reactivities,
+                dangles,
+                params,
+                state,
+                backend,
+            )
+            .map(|fold| (*fold.get_d_g(), fold.get_dots().clone()))
+        };
+
+        let mut current = fold(&prefix_constraints)?;
+        if !previous.is_empty() {
+            let trapped = trapped_constraints(&previous, &prefix_constraints, prefix[0].len())?;
+            if let Ok(kept) = fold(&trapped) {
+                if kept.0 - current.0 <= REFOLDING_BARRIER {
+                    current = kept;
+                }
+            }
+        }
+        (d_g, previous) = current;
+    }
+    Ok(d_g as f64)
+}
+
+// Constraints that keep the pairs of an earlier structure, where the user constraints leave both
+// nucleotides free. New nucleotides follow the user constraints.
+fn trapped_constraints(
+    previous: &str,
+    constraints: &str,
+    length: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut symbols: Vec<char> = constraints.chars().collect();
+    symbols.resize(length, '.');
+    let (bp_x, bp_y) = dots_to_coordinates(previous)?;
+    for (x, y) in bp_x.into_iter().zip(bp_y) {
+        if symbols[x - 1] == '.' && symbols[y - 1] == '.' {
+            symbols[x - 1] = '(';
+            symbols[y - 1] = ')';
+        }
+    }
+    Ok(symbols.into_iter().collect())
+}
+
+///Calculates the mfe structure of an mRNA segment, guided by its SHAPE reactivities when measured.
+///With a bound ligand, the segment is also folded into the aptamer motif at each of its sites and
+///the most stable structure is kept. Its energy includes the ligand binding energy.
+pub(crate) fn fold_mrna<'a>(
+    sequences: &'a [&'a str],
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    dangles: &DanglesSetting,
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Result<FoldResult<'a>, Box<dyn Error>> {
+    let fold = |constraints: &str| {
+        if reactivities.iter().any(Option::is_some) {
+            backend.mfe_with_reactivities(
+                sequences,
+                constraints,
+                reactivities,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        } else {
+            backend.mfe(
+                sequences,
+                constraints,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        }
+    };
+    let mut best = fold(constraints)?;
+    let Some(aptamer) = &state.ligand else {
+        return Ok(best);
+    };
+
+    let mrna = sequences[0];
+    let score =
+        |fold: &FoldResult| *fold.get_d_g() as f64 + aptamer.bound_energy(mrna, fold.get_dots());
+    let mut best_score = score(&best);
+    for site in aptamer.sites(mrna) {
+        let Some(site_constraints) = aptamer.site_constraints(mrna.len(), site, constraints) else {
+            continue;
+        };
+        // Sites the backend can not fold into the motif stay free
+        let Ok(candidate) = fold(&site_constraints) else {
+            continue;
+        };
+        let candidate_score = score(&candidate);
+        if candidate_score < best_score {
+            best = candidate;
+            best_score = candidate_score;
+        }
+    }
+
+    let (dots, bp_x, bp_y) = (
+        best.get_dots().clone(),
+        best.get_bp_x().clone(),
+        best.get_bp_y().clone(),
+    );
+    Ok(FoldResult::create(
+        Some(sequences),
+        best_score as f32,
+        dots,
+        bp_x,
+        bp_y,
+    ))
+}
+
+///Suboptimal structures of the mRNA within the energy cutoff, guided by the reactivities like
+///fold_mrna when they are measured. With a bound ligand, the structures with the aptamer folded
+///at each of its sites are added, and the energies include the ligand binding energy.
+pub(crate) fn subopt_mrna<'a>(
+    sequences: &'a [&'a str],
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    dangles: &DanglesSetting,
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Vec<FoldResult<'a>> {
+    let subopt = |constraints: &str| {
+        if reactivities.iter().any(Option::is_some) {
+            backend.subopt_with_reactivities(
+                sequences,
+                constraints,
+                reactivities,
+                params.energy_cutoff,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        } else {
+            backend.subopt(
+                sequences,
+                constraints,
+                params.energy_cutoff,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        }
+    };
+    let mut folds = subopt(constraints);
+    let Some(aptamer) = &state.ligand else {
+        return folds;
+    };
 
-    Ok(mfe(trimmed_mrna, constraints, DEFAULT_TEMP, dangles)?)
+    let mrna = sequences[0];
+    for site in aptamer.sites(mrna) {
+        if let Some(site_constraints) = aptamer.site_constraints(mrna.len(), site, constraints) {
+            folds.extend(subopt(&site_constraints));
+        }
+    }
+    let mut seen = HashSet::new();
+    folds
+        .into_iter()
+        .filter(|fold| seen.insert(fold.get_dots().clone()))
+        .map(|fold| {
+            let d_g = *fold.get_d_g() as f64 + aptamer.bound_energy(mrna, fold.get_dots());
+            let (dots, bp_x, bp_y) = (
+                fold.get_dots().clone(),
+                fold.get_bp_x().clone(),
+                fold.get_bp_y().clone(),
+            );
+            FoldResult::create(None, d_g as f32, dots, bp_x, bp_y)
+        })
+        .collect()
+}
+
+///Calculates the Boltzmann ensemble of an mRNA segment, guided by its reactivities like fold_mrna
+///when they are measured. With a bound ligand, the ensemble of the aptamer folded at each of its
+///sites is stabilized by the binding energy, on top of the free ensemble that already counts it.
+pub(crate) fn mrna_ensemble(
+    sequences: &[&str],
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    dangles: &DanglesSetting,
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Result<Ensemble, Box<dyn Error>> {
+    let fold = |constraints: &str| {
+        if reactivities.iter().any(Option::is_some) {
+            backend.partition_function_with_reactivities(
+                sequences,
+                constraints,
+                reactivities,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        } else {
+            backend.partition_function(
+                sequences,
+                constraints,
+                params.temperature,
+                &params.salt,
+                dangles,
+            )
+        }
+    };
+    let free = fold(constraints)?;
+    let Some(aptamer) = &state.ligand else {
+        return Ok(free);
+    };
+
+    // Partition function and pair probabilities relative to the free ensemble
+    let rt = params.rt();
+    let binding = (-aptamer.binding_energy / rt).exp() - 1.0;
+    let mut z = 1.0;
+    let mut probabilities = free.probabilities.clone();
+    let mrna = sequences[0];
+    for site in aptamer.sites(mrna) {
+        let Some(site_constraints) = aptamer.site_constraints(mrna.len(), site, constraints) else {
+            continue;
+        };
+        // Sites the backend can not fold into the motif stay free
+        let Ok(bound) = fold(&site_constraints) else {
+            continue;
+        };
+        let weight = binding * (-(bound.d_g - free.d_g) as f64 / rt).exp();
+        z += weight;
+        for (row, bound_row) in probabilities.iter_mut().zip(&bound.probabilities) {
+            for (probability, bound_probability) in row.iter_mut().zip(bound_row) {
+                *probability += weight * bound_probability;
+            }
+        }
+    }
+    let z = z.max(f64::MIN_POSITIVE);
+    for probability in probabilities.iter_mut().flatten() {
+        *probability /= z;
+    }
+    Ok(Ensemble {
+        d_g: (free.d_g as f64 - rt * z.ln()) as f32,
+        probabilities,
+    })
+}
+
+///Reactivities of the nucleotides in range, with None past the end of the measured profile
+pub(crate) fn reactivity_window(
+    reactivities: &[Option<f64>],
+    range: Range<usize>,
+) -> Vec<Option<f64>> {
+    range
+        .map(|i| reactivities.get(i).copied().flatten())
+        .collect()
 }
 
 ///Calculates the dG_standby given the structure of the mRNA:rRNA complex
@@ -49,64 +369,333 @@ fn calc_dg_mrna<'a>(
 ///we split the folded mRNA sequence into three parts: (i) a pre-sequence (before the standby
 ///site) that can fold; (ii) the standby site, which can not fold; (iii) the 16S rRNA binding
 ///site and downstream sequence, which has been previously folded.
-fn calc_dg_standby_site(fold: &CoFoldResult, dangles: &DanglesSetting, constraints: &str) -> f64 {
-    //todo
-    todo!();
+///Reactivities, when measured, guide the pre-sequence fold and cover the mRNA of the complex.
+pub(crate) fn calc_dg_standby_site(
+    fold: &CoFoldResult,
+    dangles: &DanglesSetting,
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Result<f64, Box<dyn Error>> {
+    let (mrna, rrna) = fold.seqs;
+    let energy_before = fold.d_g as f64; // without spacing effects
+
+    // Identify the most 5p mRNA nt that is bound to rRNA
+    let most_5p_mrna = fold
+        .bp_x
+        .iter()
+        .zip(fold.bp_y.iter())
+        .find(|(&nt_x, &nt_y)| nt_x <= mrna.len() && nt_y > mrna.len())
+        .map(|(&nt_x, _)| nt_x)
+        .unwrap_or(0);
+
+    // Extract the base pairings that are 3' of the most_5p_mrna base pairing
+    let mut bp_x_after: Vec<usize> = vec![];
+    let mut bp_y_after: Vec<usize> = vec![];
+
+    // Fold the pre-sequence and extract the base pairings
+    let subsequence_end = most_5p_mrna.saturating_sub(params.standby_site_length + 1);
+    if subsequence_end > 0 {
+        let mrna_subsequence = vec![&mrna[..subsequence_end]];
+        let constraint_subsequence = if constraints.is_empty() {
+            constraints
+        } else {
+            &constraints[..subsequence_end]
+        };
+        let reactivity_subsequence = reactivity_window(reactivities, 0..subsequence_end);
+        let pre_fold = fold_mrna(
+            &mrna_subsequence,
+            constraint_subsequence,
+            &reactivity_subsequence,
+            dangles,
+            params,
+            state,
+            backend,
+        )?;
+        bp_x_after.extend(pre_fold.get_bp_x());
+        bp_y_after.extend(pre_fold.get_bp_y());
+    }
+
     // Put the sets of base pairs together
+    for (&nt_x, &nt_y) in fold.bp_x.iter().zip(fold.bp_y.iter()) {
+        if nt_x >= most_5p_mrna {
+            bp_x_after.push(nt_x);
+            bp_y_after.push(nt_y);
+        }
+    }
 
     // Calculate its energy
+    let strands = vec![mrna, rrna];
+    let dots = coordinates_to_dots(&strands, &bp_x_after, &bp_y_after);
+    let energy_after =
+        backend.eval_structure(&strands, &dots, params.temperature, &params.salt, dangles) as f64
+            + state.ligand_energy(mrna, &dots);
+
+    Ok((energy_before - energy_after).min(0.0))
 }
 
-///Calculates a dG-like penalty for the ribosome binding away from the optimal start position
-fn calc_spacing_penalty(aligned_spacing: usize) -> f64 {
-    let ds: f64;
-    let spacing_penalty: f64;
-    ds = (aligned_spacing - OPTIMAL_SPACING) as f64;
-    if aligned_spacing < OPTIMAL_SPACING {
-        spacing_penalty = DG_SPACING_PUSH[0]
-            / (1.0 + (DG_SPACING_PUSH[1] * (ds + DG_SPACING_PUSH[2])).exp())
-                .powf(DG_SPACING_PUSH[3]);
+///Calculates the ensemble accessibility of the rRNA binding site and of the start codon, as the
+///mean unpaired probability of their nucleotides. The ensemble and the mRNA:rRNA complex share the
+///same mRNA window, and the start codon begins at start_in_window (1-indexed). A start codon
+///outside of the window has no accessibility.
+pub(crate) fn calc_accessibility(
+    ensemble: &Ensemble,
+    fold: &CoFoldResult,
+    start_in_window: usize,
+) -> (Option<f64>, Option<f64>) {
+    let mrna_len = fold.seqs.0.len();
+    let bound: Vec<usize> = fold
+        .bp_x
+        .iter()
+        .zip(fold.bp_y.iter())
+        .filter(|(_, &nt_y)| nt_y > mrna_len)
+        .map(|(&nt_x, _)| nt_x)
+        .collect();
+
+    let sd = match (bound.iter().min(), bound.iter().max()) {
+        (Some(&first), Some(&last)) => ensemble.accessibility(first, last),
+        _ => Some(1.0),
+    };
+    let start_codon_end = min(start_in_window + 2, ensemble.len());
+    let start_codon = ensemble.accessibility(start_in_window, start_codon_end);
+    (sd, start_codon)
+}
+
+///Calculates the local accessibility of the rRNA binding site and of the start codon from the
+///unpaired probabilities of every nucleotide of the mRNA, as their mean unpaired probability. Also
+///returns the energy to open the mRNA from the binding site through the start codon, -RT ln of the
+///lowest unpaired probability in it, which bounds the opening energy from below. The mRNA window of
+///the complex begins at window_begin and the start codon at start_pos (both 0-indexed in the mRNA).
+pub(crate) fn calc_local_accessibility(
+    unpaired: &[f64],
+    fold: &CoFoldResult,
+    window_begin: usize,
+    start_pos: usize,
+    params: &ModelParameters,
+) -> (f64, f64, f64) {
+    let mean = |region: &[f64]| region.iter().sum::<f64>() / region.len().max(1) as f64;
+    let mrna_len = fold.seqs.0.len();
+    let bound: Vec<usize> = fold
+        .bp_x
+        .iter()
+        .zip(fold.bp_y.iter())
+        .filter(|(_, &nt_y)| nt_y > mrna_len)
+        .map(|(&nt_x, _)| window_begin + nt_x - 1)
+        .collect();
+
+    let start_codon_end = min(start_pos + 3, unpaired.len());
+    let start_codon = mean(&unpaired[start_pos..start_codon_end]);
+    let (sd, first) = match (bound.iter().min(), bound.iter().max()) {
+        (Some(&first), Some(&last)) => (mean(&unpaired[first..=last]), first),
+        _ => (1.0, start_pos),
+    };
+    let lowest = unpaired[first.min(start_pos)..start_codon_end]
+        .iter()
+        .fold(1.0_f64, |lowest, &p| lowest.min(p));
+    let dg_open = params.rt() * (1.0 / lowest.max(f64::MIN_POSITIVE)).ln();
+    (sd, start_codon, dg_open)
+}
+
+///Calculates the interaction energy of an sRNA with the mRNA window of the mRNA:rRNA complex (the
+///folding window of dG_mRNA): their co-fold minus the separate folds of both, or zero when they do
+///not bind. Also returns the fraction of the rRNA binding site and of the start codon (at
+///start_in_window, 1-indexed) paired with the sRNA. The constraints and reactivities of the window
+///hold in the co-fold, as they do in dG_mRNA.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_dg_srna(
+    srna: &str,
+    dg_mrna: f64,
+    fold: &CoFoldResult,
+    start_in_window: usize,
+    dangles: &DanglesSetting,
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    params: &ModelParameters,
+    backend: &dyn FoldingBackend,
+) -> Result<(f64, f64, f64), Box<dyn Error>> {
+    let mrna = fold.seqs.0;
+    let strands = vec![mrna, srna];
+    // The sRNA strand is free to fold and has no measured reactivities
+    let cofold_constraints = if constraints.is_empty() {
+        String::new()
     } else {
-        spacing_penalty =
-            DG_SPACING_PULL[0] * ds * ds + DG_SPACING_PULL[1] * ds + DG_SPACING_PULL[2];
-    }
-    return spacing_penalty;
-}
-
-///Figure out where exactly the ribosome is binding
-fn find_binding_position(start_pos: usize, fold: &CoFoldResult) -> Result<usize, Box<dyn Error>> {
-    let seqs = fold.seqs;
-
-    let mut last_bound_rrna: Option<usize> = None;
-    let mut last_bound_mrna: Option<usize> = None;
-    let len_rrna = seqs.1.as_bytes().len() as usize;
-    let len_mrna = seqs.0.as_bytes().len() as usize;
-    let num_folded_bases = fold.bp_x.len();
-    let mut counter: usize = 1;
-
-    while last_bound_rrna.is_none() {
-        match (
-            fold.bp_y[num_folded_bases - counter],
-            fold.bp_x[num_folded_bases - counter],
-        ) {
-            (rrna, _) if rrna < len_mrna => (), // This case is mRNA backfolding
-            (_, mrna) if mrna >= start_pos as usize => {
-                return Err("Ribosome is sitting on the start codon")?
+        format!("{}&{}", constraints, ".".repeat(srna.len()))
+    };
+    let mut cofold_reactivities = reactivities.to_vec();
+    cofold_reactivities.resize(mrna.len() + srna.len(), None);
+    let cofold = fold_mrna(
+        &strands,
+        &cofold_constraints,
+        &cofold_reactivities,
+        dangles,
+        params,
+        &MrnaState::default(),
+        backend,
+    )?;
+    let srna_strand = vec![srna];
+    let srna_fold = backend.mfe(&srna_strand, "", params.temperature, &params.salt, dangles)?;
+    let dg_srna = *cofold.get_d_g() as f64 - dg_mrna - *srna_fold.get_d_g() as f64;
+    if dg_srna >= 0.0 {
+        return Ok((0.0, 0.0, 0.0));
+    }
+
+    let mrna_len = mrna.len();
+    let mut occluded = vec![false; mrna_len + 1];
+    for (&nt_x, &nt_y) in cofold.get_bp_x().iter().zip(cofold.get_bp_y().iter()) {
+        if nt_x <= mrna_len && nt_y > mrna_len {
+            occluded[nt_x] = true;
+        }
+    }
+    let occlusion = |first: usize, last: usize| {
+        (first..=last).filter(|&nt| occluded[nt]).count() as f64 / (last + 1 - first) as f64
+    };
+
+    let bound = fold
+        .bp_x
+        .iter()
+        .zip(fold.bp_y.iter())
+        .filter(|(_, &nt_y)| nt_y > mrna_len)
+        .map(|(&nt_x, _)| nt_x);
+    let sd = match (bound.clone().min(), bound.max()) {
+        (Some(first), Some(last)) => occlusion(first, last),
+        _ => 0.0,
+    };
+    let start_codon = occlusion(start_in_window, min(start_in_window + 2, mrna_len));
+    Ok((dg_srna, sd, start_codon))
+}
+
+///Calculates the low, median and high expression over the alternative structures of the mRNA and
+///of the mRNA:rRNA complex. Every pair of alternatives gives one dG_total, weighted by the
+///Boltzmann factors of both structures, and the interval is taken from the weighted quantiles.
+pub(crate) fn calc_expression_interval(
+    mrna_energies: &[f64],
+    site_energies: &[f64],
+    dg_offset: f64, // Everything else in dG_total
+    params: &ModelParameters,
+) -> (f64, f64, f64) {
+    let weight = |d_g: &f64| (-d_g / params.rt()).exp();
+    let z_mrna: f64 = mrna_energies.iter().map(weight).sum();
+    let z_site: f64 = site_energies.iter().map(weight).sum();
+
+    let mut totals = vec![];
+    for dg_mrna in mrna_energies {
+        for dg_site in site_energies {
+            let probability = weight(dg_mrna) / z_mrna * weight(dg_site) / z_site;
+            totals.push((dg_site - dg_mrna + dg_offset, probability));
+        }
+    }
+    // Highest dG_total first, so the quantiles run from low to high expression
+    totals.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let quantile = |q: f64| {
+        let mut cumulative = 0.0;
+        for &(dg_total, probability) in &totals {
+            cumulative += probability;
+            if cumulative >= q {
+                return calc_expression_level(dg_total, params);
             }
-            (rrna, mrna) => (last_bound_rrna, last_bound_mrna) = (Some(rrna), Some(mrna)),
-            _ => return Err("Ran out of bases")?,
-        };
+        }
+        calc_expression_level(totals.last().map_or(dg_offset, |t| t.0), params)
+    };
+    let (low, high) = EXPRESSION_INTERVAL;
+    (quantile(low), quantile(0.5), quantile(high))
+}
 
-        counter = counter + 1
+///Calculates a dG-like penalty for the ribosome binding away from the optimal start position
+pub(crate) fn calc_spacing_penalty(aligned_spacing: i64, params: &ModelParameters) -> f64 {
+    let push = params.dg_spacing_push;
+    let pull = params.dg_spacing_pull;
+    let ds = (aligned_spacing - params.optimal_spacing as i64) as f64;
+    if ds < 0.0 {
+        push[0] / (1.0 + (push[1] * (ds + push[2])).exp()).powf(push[3])
+    } else {
+        pull[0] * ds * ds + pull[1] * ds + pull[2]
     }
+}
 
-    let calibrated_position: usize = (start_pos - last_bound_mrna.unwrap() as usize)
-        - (last_bound_rrna.unwrap() as usize - len_rrna as usize);
+///Figure out where exactly the ribosome is binding. Returns the aligned spacing between the
+///farthest 3' rRNA nucleotide bound to the mRNA and the start codon, if the rRNA binds upstream
+///of the start codon
+pub(crate) fn find_binding_position(
+    start_pos: usize,
+    len_mrna: usize,
+    len_rrna: usize,
+    bp_x: &[usize],
+    bp_y: &[usize],
+) -> Option<i64> {
+    // The rRNA is concatenated to the end of the mRNA in the 5' to 3' direction
+    for rrna_nt in (len_mrna + 1..=len_mrna + len_rrna).rev() {
+        if let Some(index) = bp_y.iter().position(|&nt_y| nt_y == rrna_nt) {
+            let mrna_nt = bp_x[index];
+            if mrna_nt >= start_pos {
+                return None; // Ribosome is sitting on the start codon
+            }
+            let farthest_3p_rrna = (rrna_nt - len_mrna) as i64;
+            let distance_to_start = (start_pos - mrna_nt + 1) as i64;
+            return Some(distance_to_start - farthest_3p_rrna);
+        }
+    }
+    None
+}
 
-    Ok(calibrated_position)
+pub(crate) fn cutoff_mrna<'a>(
+    mrna: &'a str,
+    start_pos: usize,
+    params: &ModelParameters,
+) -> &'a str {
+    &mrna[params.folding_window(start_pos, mrna.len())]
+}
+
+/// Calculates the expression level of a given dG_total
+pub(crate) fn calc_expression_level(dg_total: f64, params: &ModelParameters) -> f64 {
+    params.k() * (-dg_total / params.rt_eff()).exp()
 }
 
-fn cutoff_mrna<'a>(mrna: &'a str, start_pos: usize) -> &'a str {
-    &mrna
-        [max(0, start_pos as isize - CUTOFF as isize) as usize..min(mrna.len(), start_pos + CUTOFF)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hairpin 1-10 in which the outer pairs are the least stable
+    fn hairpin_ensemble() -> Ensemble {
+        Ensemble::new(-2.0, 10, [(1, 10, 0.5), (2, 9, 0.2), (3, 8, 0.1)])
+    }
+
+    fn complex<'a>(mrna: &'a str, bp_x: Vec<usize>, bp_y: Vec<usize>) -> CoFoldResult<'a> {
+        let dots = coordinates_to_dots(&[mrna, "CCUC"], &bp_x, &bp_y);
+        CoFoldResult {
+            seqs: (mrna, "CCUC"),
+            d_g: -4.0,
+            dots,
+            bp_x,
+            bp_y,
+        }
+    }
+
+    #[test]
+    fn accessibility_is_the_mean_unpaired_probability() {
+        let ensemble = hairpin_ensemble();
+        let mrna = "GGAGAAAUCC";
+        // rRNA bound to the first three nucleotides, start codon in the loop
+        let fold = complex(mrna, vec![1, 2, 3], vec![14, 13, 12]);
+        let (sd, start_codon) = calc_accessibility(&ensemble, &fold, 5);
+        assert!((sd.unwrap() - (0.5 + 0.8 + 0.9) / 3.0).abs() < 1e-9);
+        assert!((start_codon.unwrap() - 1.0).abs() < 1e-9);
+
+        // A start codon running past the window keeps only its nucleotides in the window
+        let (_, start_codon) = calc_accessibility(&ensemble, &fold, 9);
+        assert!((start_codon.unwrap() - (0.8 + 0.5) / 2.0).abs() < 1e-9);
+
+        // One past the window, the start codon has no nucleotides left in it
+        let (_, start_codon) = calc_accessibility(&ensemble, &fold, 11);
+        assert_eq!(start_codon, None);
+        assert_eq!(ensemble.accessibility(0, 3), None);
+
+        // Without a binding site the rRNA has nothing to open
+        let fold = complex(mrna, vec![], vec![]);
+        let (sd, start_codon) = calc_accessibility(&ensemble, &fold, 8);
+        assert_eq!(sd, Some(1.0));
+        assert!((start_codon.unwrap() - (0.9 + 0.8 + 0.5) / 3.0).abs() < 1e-9);
+    }
 }
@@ -1,14 +1,18 @@
-
 use std::f64::consts::E;
 
 pub const BETA: f64 = 0.40002512;
 pub fn rt_eff() -> f64 {return 1.0/BETA}
-const LOGK: f64 = 7.279194329;
+pub const LOGK: f64 = 7.279194329;
 pub fn k() -> f64 {return E.powf(LOGK)}
 pub const RNAMODEL: &str = "rna2004";
 pub const AUTO_DANGLES: bool = true;
 pub const DEFAULT_DANGLES: &str = "all";
 pub const DEFAULT_TEMP: f32 = 37.0;
+pub const RT: f64 = 0.61632;  // kcal/mol at DEFAULT_TEMP, for Boltzmann weighting of structures
+pub const KELVIN: f64 = 273.15;
+pub const DEFAULT_MONOVALENT: f64 = 1.021;  // mol/L, the standard state of the Turner parameters
+pub const DEFAULT_MAGNESIUM: f64 = 0.0;  // mol/L
+pub const NO_LONELY_PAIRS: bool = true;
 pub const OPTIMAL_SPACING: usize = 5;
 pub const CUTOFF: usize = 35;
 
@@ -16,7 +20,49 @@ pub const CUTOFF: usize = 35;
 pub const DG_SPACING_PUSH: [f64; 4] = [17.20965071, 3.46341492, 1.790848365, 3.0];
 pub const DG_SPACING_PULL: [f64; 3] = [0.06422042, 0.275640836, 0.0];
 
-pub const STANDBY_SITE_LEN: i32 = 4;  // Number of nt before SD sequence that must be unpaired for ribosome binding
-pub const FOOTPRINT: i32 = 1000;
+pub const STANDBY_SITE_LEN: usize = 4;  // Number of nt before SD sequence that must be unpaired for ribosome binding
+pub const FOOTPRINT: usize = 1000;
 pub const ENERGY_CUTOFF: f32 = 3.0;
-pub const VERBOSE: bool = false;
\ No newline at end of file
+pub const HYBRIDIZATION_PENALTY: f64 = 2.481;  // Modifies the ViennaRNA hybridization penalty to match NUPACK
+pub const VERBOSE: bool = false;
+pub const MAX_KINETIC_SCORE: f64 = 0.8;  // Kinetic score above which an mRNA is flagged as slow folding
+pub const SHAPE_SLOPE: f64 = 1.8;  // kcal/mol, Deigan et al. (2009) pseudo-energies m ln(reactivity + 1) + b
+pub const SHAPE_INTERCEPT: f64 = -0.6;  // kcal/mol
+pub const THERMOMETER_FOLD_CHANGE: f64 = 4.0;  // Default expression change between temperatures that flags an RNA thermometer
+pub const EXPRESSION_INTERVAL: (f64, f64) = (0.025, 0.975);  // Quantiles of the expression uncertainty interval
+
+// Hybridization of the start codon to the initiator tRNA (CAT)
+pub const START_CODON_ENERGIES: [(&str, f64); 4] = [
+    ("ATG", -1.194),
+    ("GTG", -0.0748),
+    ("TTG", -0.0435),
+    ("CTG", -0.03406),
+];
+pub const DEFAULT_START_CODONS: [&str; 3] = ["ATG", "GTG", "TTG"];  // CTG is ignored by default
+pub const ECOLI_ASD: &str = "ACCTCCTTA";
+
+// anti-Shine-Dalgarno sequences (3' ends of the 16S rRNA) of common hosts, selectable by name
+pub const ASD_PRESETS: [(&str, &str); 6] = [
+    ("Escherichia coli", ECOLI_ASD),
+    ("Bacillus subtilis", "ACCTCCTTTCT"),
+    ("Pseudomonas putida", "ACCTCCTTA"),
+    ("Vibrio natriegens", "ACCTCCTTA"),
+    ("Synechocystis", "ACCTCCTTT"),
+    ("Corynebacterium glutamicum", "ACCTCCTTTCT"),
+];
+pub const ASD_FLANK: &str = "TGGATC";  // Conserved 16S rRNA nt 5' of the anti-Shine-Dalgarno sequence
+pub const ASD_MAX_MISMATCHES: usize = 2;  // Above this, an aSD does not look like a 16S 3' end
+pub const ASD_MAX_LENGTH: usize = 13;  // Longest aSD taken from the 3' end of an annotated 16S rRNA
+pub const SSU_MOTIF: &str = "GTAACAAGG";  // Conserved 16S rRNA motif shortly upstream of ASD_FLANK
+pub const SSU_MOTIF_DISTANCE: usize = 40;  // Most nt between SSU_MOTIF and ASD_FLANK
+pub const CIRCULAR_OVERHANG: usize = 200;  // Bases wrapped around the origin of circular sequences
+pub const DECIMAL_PLACES: u32 = 4;
+pub const LOCAL_WINDOW: usize = 240;  // Window of RNAplfold-style local folding, as commonly used for mRNA accessibility
+pub const LOCAL_SPAN: usize = 160;  // Maximum base pair span of local folding
+pub const ELONGATION_RATE: f64 = 50.0;  // nt/s transcribed by the bacterial RNA polymerase
+pub const LOADING_TIME: f64 = 1.0;  // s between transcription of the start codon and ribosome loading
+pub const COTRANSCRIPTIONAL_STEP: usize = 5;  // nt added to the transcript between co-transcriptional folds
+pub const REFOLDING_BARRIER: f32 = 3.0;  // kcal/mol that breaking earlier helices has to gain during transcription
+pub const TERMINATION_FOOTPRINT: (usize, usize) = (15, 10);  // nt a terminating ribosome covers upstream of the stop codon and downstream of it
+pub const COMPETITION_DISTANCE: usize = 10;  // Start codons closer than this share an RBS and compete for its ribosomes
+pub const SWITCH_MIN_RATIO: f64 = 10.0;  // ON/OFF ratio below which a toehold switch is reported as leaky
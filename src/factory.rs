@@ -0,0 +1,1634 @@
+use crate::calculations::*;
+use crate::constants::*;
+use crate::file_parser::fileparser::DNASequence;
+use crate::folding::FoldingBackend;
+use crate::hybridization::calc_dg_mrna_rrna;
+#[cfg(not(feature = "vienna"))]
+use crate::turner::TurnerBackend;
+use crate::types::{
+    asd_preset, ContextTable, FoldResult, ModelParameters, MrnaState, SaltConditions,
+};
+#[cfg(feature = "vienna")]
+use crate::vienna_wrapper::ViennaBackend;
+use polars::prelude::*;
+use rayon::prelude::*;
+use std::cmp::min;
+use std::error::Error;
+use std::sync::Arc;
+
+fn default_backend() -> Arc<dyn FoldingBackend> {
+    #[cfg(feature = "vienna")]
+    return Arc::new(ViennaBackend);
+    #[cfg(not(feature = "vienna"))]
+    return Arc::new(TurnerBackend);
+}
+
+/// Predicts translation initiation rates. Create one with [`Ostir::builder`].
+pub struct Ostir {
+    asd: String,
+    start_codons: Vec<String>,
+    parameters: ModelParameters,
+    circular: bool,
+    range: Option<(usize, usize)>,
+    backend: Arc<dyn FoldingBackend>,
+    accessibility: bool,
+    ensemble: bool,
+    uncertainty: bool,
+    kinetics: bool,
+    local_folding: bool,
+    cotranscriptional: bool,
+    competition: bool,
+    srna: Option<String>,
+    context: Option<ContextTable>,
+    pool: Option<rayon::ThreadPool>,
+}
+
+/// Builder for [`Ostir`]. Unset values fall back to the OSTIR defaults.
+pub struct OstirBuilder {
+    asd: String,
+    organism: Option<String>,
+    start_codons: Vec<String>,
+    parameters: ModelParameters,
+    threads: usize,
+    circular: bool,
+    range: Option<(usize, usize)>,
+    backend: Arc<dyn FoldingBackend>,
+    accessibility: bool,
+    ensemble: bool,
+    uncertainty: bool,
+    kinetics: bool,
+    local_folding: bool,
+    cotranscriptional: bool,
+    competition: bool,
+    srna: Option<String>,
+    context: Option<ContextTable>,
+}
+
+/// The OSTIR prediction for a single start codon
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+    pub name: String,
+    pub start_codon: String,
+    pub start_position: usize, // 1-indexed
+    pub expression: f64,
+    pub rbs_distance_bp: i64,
+    pub dg_total: f64,
+    pub dg_rrna_mrna: f64,
+    pub dg_mrna: f64,
+    /// dG_mRNA of the nascent transcript when the ribosome loads, in co-transcriptional mode
+    pub dg_mrna_cotranscriptional: Option<f64>,
+    pub dg_spacing: f64,
+    pub dg_standby: f64,
+    pub dg_start_codon: f64,
+    /// Energy of the nucleotides around the start codon, when a context table is given
+    pub dg_context: Option<f64>,
+    pub mrna_structure: String,
+    pub mrna_rrna_structure: String,
+    /// First and last nucleotide (1-indexed) of the mRNA folding window
+    pub folding_window: (usize, usize),
+    /// Mean unpaired probability of the rRNA binding site in the mRNA ensemble, when requested
+    pub sd_accessibility: Option<f64>,
+    /// Mean unpaired probability of the start codon in the mRNA ensemble, when requested
+    pub start_codon_accessibility: Option<f64>,
+    /// Ensemble free energy of the mRNA folding window, when ensemble energies are requested.
+    /// Measured reactivities add their pseudo-energies to it.
+    pub dg_mrna_ensemble: Option<f64>,
+    /// Boltzmann-weighted dG_rRNA:mRNA + dG_spacing over all binding sites in the subopt band.
+    /// Each site is scored with its subopt energy, in which the mRNA upstream of the start codon
+    /// folds with the rRNA bound, without refolding the mRNA around the ribosome footprint as
+    /// dG_rRNA:mRNA does. It is only comparable with the other ensemble energies.
+    pub dg_rrna_mrna_ensemble: Option<f64>,
+    /// dG_rRNA:mRNA_ensemble + dG_start_codon + dG_context - dG_mRNA_ensemble - dG_standby, with
+    /// the dG_standby of the best binding site
+    pub dg_total_ensemble: Option<f64>,
+    pub expression_ensemble: Option<f64>,
+    /// Expression interval over the suboptimal mRNA and mRNA:rRNA structures, when requested
+    pub expression_low: Option<f64>,
+    pub expression_median: Option<f64>,
+    pub expression_high: Option<f64>,
+    /// Interaction energy of the sRNA with the mRNA folding window, when an sRNA is given
+    pub dg_srna: Option<f64>,
+    /// Fractions of the rRNA binding site and of the start codon paired with the sRNA
+    pub sd_occlusion: Option<f64>,
+    pub start_codon_occlusion: Option<f64>,
+    /// dG_total and expression with the sRNA, which has to be displaced when it occludes either
+    pub dg_total_srna: Option<f64>,
+    pub expression_srna: Option<f64>,
+    /// Span of the longest-range helix of the mRNA structure over the folding window length, and
+    /// the minimum base pair probability it implies, when kinetics are requested
+    pub kinetic_score: Option<f64>,
+    pub min_bp_prob: Option<f64>,
+    /// Whether the kinetic score exceeds max_kinetic_score, making the prediction unreliable
+    pub slow_folding: Option<bool>,
+    /// Mean unpaired probabilities of the rRNA binding site and of the start codon from local
+    /// folding of the whole mRNA, and the energy to open the mRNA between them, when requested
+    pub sd_local_accessibility: Option<f64>,
+    pub start_codon_local_accessibility: Option<f64>,
+    pub dg_open_local: Option<f64>,
+    /// Fraction of the initiation at an RBS that starts at this codon, when start codons compete,
+    /// and the expression of the RBS shared out accordingly
+    pub initiation_fraction: Option<f64>,
+    pub expression_competition: Option<f64>,
+}
+
+// A sequence cleaned up for prediction. Circular sequences are extended by their overhang.
+struct PreparedSequence {
+    name: String,
+    mrna: String,
+    start_codons: Vec<(usize, String)>,
+    constraints: String, // Hard folding constraints over the whole mRNA, empty when unconstrained
+    reactivities: Vec<Option<f64>>,
+    unpaired: Vec<f64>, // Local unpaired probabilities of the mRNA, empty when not requested
+}
+
+/// Predictions of a sequence at each temperature (in C) of a sweep
+pub type TemperatureSweep = Vec<(f32, Vec<Prediction>)>;
+
+/// Lazily computes the predictions of a sequence one start codon at a time
+pub struct PredictionIter<'a> {
+    ostir: &'a Ostir,
+    sequence: PreparedSequence,
+    start_codons: std::vec::IntoIter<(usize, String)>,
+}
+
+impl Ostir {
+    pub fn builder() -> OstirBuilder {
+        OstirBuilder {
+            asd: ECOLI_ASD.to_string(),
+            organism: None,
+            start_codons: DEFAULT_START_CODONS.iter().map(|c| c.to_string()).collect(),
+            parameters: ModelParameters::default(),
+            threads: 1,
+            circular: false,
+            range: None,
+            backend: default_backend(),
+            accessibility: false,
+            ensemble: false,
+            uncertainty: false,
+            kinetics: false,
+            local_folding: false,
+            cotranscriptional: false,
+            competition: false,
+            srna: None,
+            context: None,
+        }
+    }
+
+    pub fn asd(&self) -> &str {
+        &self.asd
+    }
+
+    pub fn parameters(&self) -> &ModelParameters {
+        &self.parameters
+    }
+
+    /// Predicts the expression from every start codon in the sequence, ordered by position
+    pub fn predict(&self, sequence: &DNASequence) -> Result<Vec<Prediction>, Box<dyn Error>> {
+        self.predict_with(sequence, &self.asd, &self.parameters, &MrnaState::default())
+    }
+
+    /// Predicts the sequence once at each folding temperature (in C), for expression versus
+    /// temperature curves of every start codon
+    pub fn predict_temperature_sweep(
+        &self,
+        sequence: &DNASequence,
+        temperatures: &[f32],
+    ) -> Result<TemperatureSweep, Box<dyn Error>> {
+        temperatures
+            .iter()
+            .map(|&temperature| {
+                let params = ModelParameters {
+                    temperature,
+                    ..self.parameters.clone()
+                };
+                let state = MrnaState::default();
+                let predictions = self.predict_with(sequence, &self.asd, &params, &state)?;
+                Ok((temperature, predictions))
+            })
+            .collect()
+    }
+
+    /// Predicts with another aSD, other model parameters or in another state of the mRNA, for the
+    /// modes that compare predictions. The aSD must already be validated.
+    pub(crate) fn predict_with(
+        &self,
+        sequence: &DNASequence,
+        asd: &str,
+        params: &ModelParameters,
+        state: &MrnaState,
+    ) -> Result<Vec<Prediction>, Box<dyn Error>> {
+        let sequence = self.prepare(sequence, params)?;
+
+        let results: Vec<Result<Option<Prediction>, String>> = match &self.pool {
+            Some(pool) => pool.install(|| {
+                sequence
+                    .start_codons
+                    .par_iter()
+                    .map(|(start_pos, codon)| {
+                        self.predict_start_codon(asd, params, state, &sequence, *start_pos, codon)
+                            .map_err(|e| e.to_string())
+                    })
+                    .collect()
+            }),
+            None => sequence
+                .start_codons
+                .iter()
+                .map(|(start_pos, codon)| {
+                    self.predict_start_codon(asd, params, state, &sequence, *start_pos, codon)
+                        .map_err(|e| e.to_string())
+                })
+                .collect(),
+        };
+
+        let mut predictions = vec![];
+        for result in results {
+            if let Some(prediction) = result? {
+                predictions.push(prediction);
+            }
+        }
+        if self.competition {
+            share_initiation(&mut predictions, params);
+        }
+        Ok(predictions)
+    }
+
+    /// Streaming variant of [`Ostir::predict`] that yields predictions as they are computed
+    pub fn predict_iter(
+        &self,
+        sequence: &DNASequence,
+    ) -> Result<PredictionIter<'_>, Box<dyn Error>> {
+        let mut sequence = self.prepare(sequence, &self.parameters)?;
+        let start_codons = std::mem::take(&mut sequence.start_codons).into_iter();
+        Ok(PredictionIter {
+            ostir: self,
+            sequence,
+            start_codons,
+        })
+    }
+
+    /// Cleans up the sequence, wraps circular sequences and finds the start codons to evaluate.
+    /// With local folding, the whole mRNA is folded once here for all of its start codons.
+    fn prepare(
+        &self,
+        sequence: &DNASequence,
+        params: &ModelParameters,
+    ) -> Result<PreparedSequence, Box<dyn Error>> {
+        let name = if sequence.description.is_empty() {
+            "unnamed".to_string()
+        } else {
+            sequence.description.clone()
+        };
+
+        let mut mrna = sequence.record.replace(" ", "").to_uppercase();
+        if !mrna.chars().all(|c| "ACGTU".contains(c)) {
+            return Err(format!(
+                "Input sequence ({}) contains non-nucleotide characters",
+                name
+            )
+            .into());
+        }
+
+        let start_codons = self.find_start_codons(&mrna);
+
+        let mrna_len = mrna.len();
+        if sequence.reactivities.len() > mrna_len {
+            return Err(format!(
+                "The reactivity profile of {} is longer than its sequence ({} > {} nt)",
+                name,
+                sequence.reactivities.len(),
+                mrna_len
+            )
+            .into());
+        }
+        let mut reactivities = sequence.reactivities.clone();
+        if self.circular && !reactivities.is_empty() {
+            reactivities.resize(mrna_len, None);
+            let overhang = reactivities[..min(mrna_len, CIRCULAR_OVERHANG)].to_vec();
+            reactivities.extend(overhang);
+        }
+
+        if sequence.constraints.len() > mrna_len {
+            return Err(format!(
+                "The folding constraints of {} are longer than its sequence ({} > {} nt)",
+                name,
+                sequence.constraints.len(),
+                mrna_len
+            )
+            .into());
+        }
+        let mut constraints = sequence.constraints.clone();
+        if !constraints.is_empty() {
+            constraints.push_str(&".".repeat(mrna_len - constraints.len()));
+            if self.circular {
+                // Forced pairs are not repeated in the overhang
+                let overhang =
+                    constraints[..min(mrna_len, CIRCULAR_OVERHANG)].replace(['(', ')'], ".");
+                constraints.push_str(&overhang);
+            }
+        }
+
+        if self.circular {
+            let overhang = mrna[..min(mrna_len, CIRCULAR_OVERHANG)].to_string();
+            mrna.push_str(&overhang);
+        }
+
+        let unpaired = if self.local_folding {
+            self.backend.local_unpaired_probabilities(
+                &mrna,
+                params.local_window,
+                params.local_span,
+                params.temperature,
+                &params.salt,
+                &params.dangles,
+            )?
+        } else {
+            vec![]
+        };
+
+        Ok(PreparedSequence {
+            name,
+            mrna,
+            start_codons,
+            constraints,
+            reactivities,
+            unpaired,
+        })
+    }
+
+    /// Finds all start codons within the search range. Positions are 0-indexed.
+    fn find_start_codons(&self, mrna: &str) -> Vec<(usize, String)> {
+        if mrna.len() < 3 {
+            return vec![];
+        }
+        let (range_start, range_end) = self.range.unwrap_or((1, mrna.len()));
+        let end_0 = min(range_end.max(1) - 1, mrna.len() - 3);
+        let begin_0 = min(range_start.max(1) - 1, end_0);
+
+        (begin_0..=end_0)
+            .filter_map(|i| {
+                let codon = &mrna[i..i + 3];
+                let dna_codon = codon.replace("U", "T");
+                if self.start_codons.contains(&dna_codon) {
+                    Some((i, codon.to_string()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn predict_start_codon(
+        &self,
+        asd: &str,
+        params: &ModelParameters,
+        state: &MrnaState,
+        sequence: &PreparedSequence,
+        start_pos: usize,
+        codon: &str,
+    ) -> Result<Option<Prediction>, Box<dyn Error>> {
+        let backend = self.backend.as_ref();
+        let (name, mrna) = (sequence.name.as_str(), sequence.mrna.as_str());
+        let (constraints, reactivities) = (sequence.constraints.as_str(), &sequence.reactivities);
+
+        // Leaderless start codons can't be bound by the 16S rRNA
+        if start_pos == 0 {
+            return Ok(None);
+        }
+
+        // Set dangles based on length between 5' end of mRNA and start codon
+        let dangles = params.dangles_for(start_pos);
+
+        // Start codon energy
+        let dg_start_codon = params
+            .start_codon_energy(codon)
+            .ok_or(format!("No energy is defined for start codon {}", codon))?;
+
+        // Energy of mRNA folding
+        let trimmed_mrna = vec![cutoff_mrna(mrna, start_pos, params)];
+        let mrna_fold = calc_dg_mrna(
+            &trimmed_mrna,
+            start_pos,
+            &dangles,
+            constraints,
+            reactivities,
+            params,
+            state,
+            backend,
+        )?;
+        let dg_mrna = *mrna_fold.get_d_g() as f64;
+
+        // Energy of mRNA folding on the nascent transcript, when the ribosome loads
+        let dg_mrna_cotranscriptional = if self.cotranscriptional {
+            Some(calc_dg_mrna_cotranscriptional(
+                mrna,
+                start_pos,
+                &dangles,
+                constraints,
+                reactivities,
+                params,
+                state,
+                backend,
+            )?)
+        } else {
+            None
+        };
+
+        // Kinetic score of the mRNA structure, to flag mRNAs that may fold slowly
+        let (kinetic_score, min_bp_prob) = match &mrna_fold {
+            FoldResult::Mono(fold) if self.kinetics => {
+                let (kinetic_score, min_bp_prob) = calc_kinetic_score(fold)?;
+                (Some(kinetic_score), Some(min_bp_prob))
+            }
+            _ => (None, None),
+        };
+
+        // Constraints of the folding window, which the mRNA:rRNA complex shares
+        let window_begin = params.window_start(start_pos);
+        let window = window_begin..window_begin + trimmed_mrna[0].len();
+        let window_constraints = if constraints.is_empty() {
+            ""
+        } else {
+            &constraints[window.clone()]
+        };
+
+        // Energy of mRNA:rRNA hybridization & folding
+        let hybridization = match calc_dg_mrna_rrna(
+            mrna,
+            asd,
+            start_pos,
+            &dangles,
+            constraints,
+            reactivities,
+            params,
+            state,
+            backend,
+        )? {
+            Some(hybridization) => hybridization,
+            None => return Ok(None),
+        };
+        let dg_mrna_rrna = hybridization.fold.d_g as f64 - params.hybridization_penalty;
+        let dg_mrna_rrna_withspacing = dg_mrna_rrna + hybridization.dg_spacing;
+
+        // Standby site correction, where nucleotides unwound by a terminating ribosome can not fold
+        let mrna_constraints = state.unwound_constraints(window_constraints, window.clone());
+        let window_reactivities = reactivity_window(reactivities, window);
+        let dg_standby = calc_dg_standby_site(
+            &hybridization.fold,
+            &dangles,
+            &mrna_constraints,
+            &window_reactivities,
+            params,
+            state,
+            backend,
+        )?;
+
+        // Context of the start codon, which the other terms of the total energy share
+        let dg_context = self
+            .context
+            .as_ref()
+            .map(|context| context.energy(mrna, start_pos));
+        let dg_codon = dg_start_codon + dg_context.unwrap_or(0.0);
+
+        // Total energy is mRNA:rRNA + start - rRNA - mRNA - standby_site
+        let dg_total = dg_mrna_rrna_withspacing + dg_codon - dg_mrna - dg_standby;
+
+        // The mRNA ensemble is shared by the accessibility and the ensemble energies, and folded
+        // like dG_mRNA
+        let ensemble = if self.accessibility || self.ensemble {
+            Some(mrna_ensemble(
+                &trimmed_mrna,
+                &mrna_constraints,
+                &window_reactivities,
+                &dangles,
+                params,
+                state,
+                backend,
+            )?)
+        } else {
+            None
+        };
+
+        // Accessibility from the base pair probabilities of the same folding window
+        let (sd_accessibility, start_codon_accessibility) = match &ensemble {
+            Some(ensemble) if self.accessibility => {
+                let start_in_window = start_pos - window_begin + 1;
+                calc_accessibility(ensemble, &hybridization.fold, start_in_window)
+            }
+            _ => (None, None),
+        };
+
+        // Ensemble energies, which already include the spacing of every binding site
+        let (dg_mrna_ensemble, dg_rrna_mrna_ensemble, dg_total_ensemble) = match &ensemble {
+            Some(ensemble) if self.ensemble => {
+                let dg_mrna_ensemble = ensemble.d_g as f64;
+                let dg_rrna_mrna_ensemble =
+                    hybridization.dg_ensemble - params.hybridization_penalty;
+                let dg_total_ensemble =
+                    dg_rrna_mrna_ensemble + dg_codon - dg_mrna_ensemble - dg_standby;
+                (
+                    Some(dg_mrna_ensemble),
+                    Some(dg_rrna_mrna_ensemble),
+                    Some(dg_total_ensemble),
+                )
+            }
+            _ => (None, None, None),
+        };
+
+        // Uncertainty from the alternative structures of the mRNA, folded like dG_mRNA, and of
+        // the complex
+        let (expression_low, expression_median, expression_high) = if self.uncertainty {
+            let mut mrna_energies: Vec<f64> = subopt_mrna(
+                &trimmed_mrna,
+                &mrna_constraints,
+                &window_reactivities,
+                &dangles,
+                params,
+                state,
+                backend,
+            )
+            .iter()
+            .map(|fold| *fold.get_d_g() as f64)
+            .collect();
+            if mrna_energies.is_empty() {
+                mrna_energies.push(dg_mrna);
+            }
+            let dg_offset = dg_codon - params.hybridization_penalty - dg_standby;
+            let (low, median, high) = calc_expression_interval(
+                &mrna_energies,
+                &hybridization.site_energies,
+                dg_offset,
+                params,
+            );
+            (Some(low), Some(median), Some(high))
+        } else {
+            (None, None, None)
+        };
+
+        // Occlusion by a trans-acting sRNA that pairs with the same mRNA window
+        let (dg_srna, sd_occlusion, start_codon_occlusion, dg_total_srna) = match &self.srna {
+            Some(srna) => {
+                let start_in_window = start_pos - window_begin + 1;
+                let (dg_srna, sd, start_codon) = calc_dg_srna(
+                    srna,
+                    dg_mrna,
+                    &hybridization.fold,
+                    start_in_window,
+                    &dangles,
+                    &mrna_constraints,
+                    &window_reactivities,
+                    params,
+                    backend,
+                )?;
+                let occluded = sd > 0.0 || start_codon > 0.0;
+                let dg_total_srna = if occluded {
+                    dg_total - dg_srna
+                } else {
+                    dg_total
+                };
+                (
+                    Some(dg_srna),
+                    Some(sd),
+                    Some(start_codon),
+                    Some(dg_total_srna),
+                )
+            }
+            None => (None, None, None, None),
+        };
+
+        // Accessibility looked up from the local folding of the whole mRNA
+        let (sd_local_accessibility, start_codon_local_accessibility, dg_open_local) =
+            if sequence.unpaired.is_empty() {
+                (None, None, None)
+            } else {
+                let (sd, start_codon, dg_open) = calc_local_accessibility(
+                    &sequence.unpaired,
+                    &hybridization.fold,
+                    window_begin,
+                    start_pos,
+                    params,
+                );
+                (Some(sd), Some(start_codon), Some(dg_open))
+            };
+
+        Ok(Some(Prediction {
+            name: name.to_string(),
+            start_codon: codon.to_string(),
+            start_position: start_pos + 1,
+            expression: calc_expression_level(dg_total, params),
+            rbs_distance_bp: hybridization.aligned_spacing,
+            dg_total,
+            dg_rrna_mrna: dg_mrna_rrna,
+            dg_mrna,
+            dg_mrna_cotranscriptional,
+            dg_spacing: hybridization.dg_spacing,
+            dg_standby,
+            dg_start_codon,
+            dg_context,
+            mrna_structure: mrna_fold.get_dots().clone(),
+            mrna_rrna_structure: hybridization.fold.dots.clone(),
+            folding_window: (window_begin + 1, window_begin + trimmed_mrna[0].len()),
+            sd_accessibility,
+            start_codon_accessibility,
+            dg_mrna_ensemble,
+            dg_rrna_mrna_ensemble,
+            dg_total_ensemble,
+            expression_ensemble: dg_total_ensemble.map(|dg| calc_expression_level(dg, params)),
+            expression_low,
+            expression_median,
+            expression_high,
+            dg_srna,
+            sd_occlusion,
+            start_codon_occlusion,
+            dg_total_srna,
+            expression_srna: dg_total_srna.map(|dg| calc_expression_level(dg, params)),
+            kinetic_score,
+            min_bp_prob,
+            slow_folding: kinetic_score.map(|score| score > params.max_kinetic_score),
+            sd_local_accessibility,
+            start_codon_local_accessibility,
+            dg_open_local,
+            initiation_fraction: None,
+            expression_competition: None,
+        }))
+    }
+}
+
+// Shares initiation among start codons with a common RBS, chained within COMPETITION_DISTANCE nt.
+// Ribosomes bind at each start codon in proportion to its expression. They initiate there when
+// the initiator tRNA pairs with the codon, with probability 1 / (1 + exp(dG_start_codon / RT)),
+// and otherwise scan on to the next start codon downstream (leaky scanning). The RBS keeps the
+// expression of its strongest start codon.
+fn share_initiation(predictions: &mut [Prediction], params: &ModelParameters) {
+    let mut begin = 0;
+    while begin < predictions.len() {
+        let mut end = begin + 1;
+        while end < predictions.len()
+            && predictions[end].start_position - predictions[end - 1].start_position
+                < COMPETITION_DISTANCE
+        {
+            end += 1;
+        }
+        let group = &mut predictions[begin..end];
+
+        let total: f64 = group.iter().map(|p| p.expression).sum();
+        let strongest = group.iter().map(|p| p.expression).fold(0.0, f64::max);
+        let mut scanning = 0.0;
+        let mut initiated = vec![];
+        for prediction in group.iter() {
+            let binding = if total > 0.0 {
+                prediction.expression / total
+            } else {
+                0.0
+            };
+            let pairing = 1.0 / (1.0 + (prediction.dg_start_codon / params.rt()).exp());
+            scanning += binding;
+            initiated.push(scanning * pairing);
+            scanning *= 1.0 - pairing;
+        }
+        let initiated_total: f64 = initiated.iter().sum();
+        for (prediction, initiated) in group.iter_mut().zip(initiated) {
+            let fraction = if initiated_total > 0.0 {
+                initiated / initiated_total
+            } else {
+                0.0
+            };
+            prediction.initiation_fraction = Some(fraction);
+            prediction.expression_competition = Some(fraction * strongest);
+        }
+        begin = end;
+    }
+}
+
+impl OstirBuilder {
+    /// anti-Shine-Dalgarno sequence, the 3' end of the 16S rRNA
+    pub fn asd(mut self, asd: &str) -> Self {
+        self.asd = asd.to_string();
+        self
+    }
+
+    /// Host whose anti-Shine-Dalgarno sequence to use, from the presets in [`ASD_PRESETS`] (e.g.
+    /// "B. subtilis"). Takes precedence over [`OstirBuilder::asd`].
+    pub fn organism(mut self, organism: &str) -> Self {
+        self.organism = Some(organism.to_string());
+        self
+    }
+
+    pub fn start_codons(mut self, start_codons: &[&str]) -> Self {
+        self.start_codons = start_codons.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn parameters(mut self, parameters: ModelParameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Folding temperature in C. The expression model is rescaled from its 37 C calibration.
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.parameters.temperature = temperature;
+        self
+    }
+
+    /// Energies of the nucleotides around each start codon, added to dG_total as dG_context
+    pub fn context(mut self, context: ContextTable) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Ionic conditions of the folding model. Defaults to the 1 M NaCl of the Turner parameters.
+    pub fn salt(mut self, salt: SaltConditions) -> Self {
+        self.parameters.salt = salt;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn circular(mut self, circular: bool) -> Self {
+        self.circular = circular;
+        self
+    }
+
+    /// Folding engine used for all energy calculations. Defaults to ViennaRNA, or to the
+    /// pure-Rust Turner backend when OSTIR is built without the `vienna` feature.
+    pub fn backend<B: FoldingBackend + 'static>(mut self, backend: B) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    /// Also report how accessible the rRNA binding site and the start codon are in the Boltzmann
+    /// ensemble of the mRNA, rather than only in its MFE structure
+    pub fn accessibility(mut self, accessibility: bool) -> Self {
+        self.accessibility = accessibility;
+        self
+    }
+
+    /// Also report ensemble-averaged energies: dG_mRNA from the partition function of the mRNA and
+    /// dG_rRNA:mRNA from all 16S rRNA binding sites in the subopt band, instead of the best one.
+    /// The binding sites keep their subopt energies, so the ensemble columns form their own
+    /// dG_total and should not be mixed with the MFE ones.
+    pub fn ensemble(mut self, ensemble: bool) -> Self {
+        self.ensemble = ensemble;
+        self
+    }
+
+    /// Also report a low/median/high expression interval from the suboptimal structures of the
+    /// mRNA and of the mRNA:rRNA complex within the subopt energy band
+    pub fn uncertainty(mut self, uncertainty: bool) -> Self {
+        self.uncertainty = uncertainty;
+        self
+    }
+
+    /// Also report the kinetic score of the mRNA structure and flag start codons whose mRNA may
+    /// fold too slowly to reach it, as the original RBS Calculator did
+    pub fn kinetics(mut self, kinetics: bool) -> Self {
+        self.kinetics = kinetics;
+        self
+    }
+
+    /// Also fold every sequence as a whole in sliding windows (RNAplfold-style local folding) and
+    /// report the accessibility of each start codon from these unpaired probabilities. Long
+    /// records are folded once instead of once per start codon.
+    pub fn local_folding(mut self, local_folding: bool) -> Self {
+        self.local_folding = local_folding;
+        self
+    }
+
+    /// Share the initiation of start codons within a few nt of each other, which compete for the
+    /// ribosomes of the same RBS. Applies to [`Ostir::predict`] but not to streamed predictions.
+    pub fn competition(mut self, competition: bool) -> Self {
+        self.competition = competition;
+        self
+    }
+
+    /// Also report dG_mRNA of the nascent transcript when the ribosome loads, folding it as it
+    /// is transcribed at the elongation rate. It starts with the folding window but can be
+    /// transcribed past its end.
+    pub fn cotranscriptional(mut self, cotranscriptional: bool) -> Self {
+        self.cotranscriptional = cotranscriptional;
+        self
+    }
+
+    /// Transcription elongation rate in nt/s for co-transcriptional folding
+    pub fn elongation_rate(mut self, elongation_rate: f64) -> Self {
+        self.parameters.elongation_rate = elongation_rate;
+        self
+    }
+
+    /// Time in s from transcription of the start codon to ribosome loading
+    pub fn loading_time(mut self, loading_time: f64) -> Self {
+        self.parameters.loading_time = loading_time;
+        self
+    }
+
+    /// Window length and maximum base pair span of local folding
+    pub fn local_window(mut self, window: usize, span: usize) -> Self {
+        self.parameters.local_window = window;
+        self.parameters.local_span = span;
+        self
+    }
+
+    /// Number of nt folded upstream of the start codon and from the start codon on. Both default
+    /// to the symmetric 35 nt window of the calibration.
+    pub fn folding_window(mut self, upstream: usize, downstream: usize) -> Self {
+        self.parameters.upstream_cutoff = upstream;
+        self.parameters.downstream_cutoff = downstream;
+        self
+    }
+
+    /// Fold the whole transcript for every start codon instead of a window around it, so that
+    /// accessibility reflects structures formed anywhere in a long 5' UTR
+    pub fn full_transcript(mut self, full_transcript: bool) -> Self {
+        self.parameters.full_transcript = full_transcript;
+        self
+    }
+
+    /// Number of nt from the start codon on that the bound ribosome keeps unfolded. The default
+    /// of the calibration covers the whole downstream mRNA.
+    pub fn footprint(mut self, footprint: usize) -> Self {
+        self.parameters.footprint = footprint;
+        self
+    }
+
+    /// Kinetic score above which start codons are flagged as slow folding
+    pub fn max_kinetic_score(mut self, max_kinetic_score: f64) -> Self {
+        self.parameters.max_kinetic_score = max_kinetic_score;
+        self
+    }
+
+    /// Trans-acting sRNA co-folded with the mRNA window of every start codon. Its pairing with the
+    /// rRNA binding site or the start codon is reported and has to be undone to initiate.
+    pub fn srna(mut self, srna: &str) -> Self {
+        self.srna = Some(srna.to_string());
+        self
+    }
+
+    /// Only consider start codons between these 1-indexed positions (inclusive)
+    pub fn range(mut self, start: usize, end: usize) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    pub fn build(self) -> Result<Ostir, Box<dyn Error>> {
+        let asd = match &self.organism {
+            Some(organism) => asd_preset(organism).ok_or_else(|| {
+                let presets: Vec<&str> = ASD_PRESETS.iter().map(|(species, _)| *species).collect();
+                format!(
+                    "No anti-Shine-Dalgarno preset for {} ({})",
+                    organism,
+                    presets.join(", ")
+                )
+            })?,
+            None => &self.asd,
+        };
+        let asd = asd.to_uppercase().replace("U", "T");
+        if asd.is_empty() || !asd.chars().all(|c| "ACGT".contains(c)) {
+            return Err(format!(
+                "anti-Shine-Dalgarno sequence provided ({}) contains non-nucleotide characters",
+                self.asd
+            )
+            .into());
+        }
+
+        let srna = match &self.srna {
+            Some(provided) => {
+                let srna = provided.to_uppercase().replace("U", "T");
+                if srna.is_empty() || !srna.chars().all(|c| "ACGT".contains(c)) {
+                    return Err(format!(
+                        "sRNA sequence provided ({}) contains non-nucleotide characters",
+                        provided
+                    )
+                    .into());
+                }
+                Some(srna)
+            }
+            None => None,
+        };
+
+        let mut start_codons = vec![];
+        for codon in &self.start_codons {
+            let codon = codon.to_uppercase().replace("U", "T");
+            if self.parameters.start_codon_energy(&codon).is_none() {
+                return Err(format!("No energy is defined for start codon {}", codon).into());
+            }
+            start_codons.push(codon);
+        }
+
+        if let Some((start, end)) = self.range {
+            if end < start {
+                return Err(format!(
+                    "Start location ({}) is not less than end location ({})",
+                    start, end
+                )
+                .into());
+            }
+        }
+
+        let salt = self.parameters.salt;
+        SaltConditions::new(salt.monovalent, salt.magnesium)?;
+
+        if self.cotranscriptional
+            && !(self.parameters.elongation_rate > 0.0 && self.parameters.loading_time > 0.0)
+        {
+            return Err(
+                "Co-transcriptional folding needs a positive elongation rate and loading time"
+                    .into(),
+            );
+        }
+
+        // Every start codon would be leaderless without nucleotides upstream of it, and cut
+        // short without the three nucleotides from it on
+        if self.parameters.upstream_cutoff == 0 && !self.parameters.full_transcript {
+            return Err(
+                "The folding window needs at least one nt upstream of the start codon".into(),
+            );
+        }
+        if self.parameters.downstream_cutoff < 3 && !self.parameters.full_transcript {
+            return Err("The folding window needs the whole start codon downstream of it".into());
+        }
+
+        if self.local_folding && self.parameters.local_window == 0 {
+            return Err("Local folding needs a window of at least one nt".into());
+        }
+
+        if self.threads == 0 {
+            return Err("At least one thread is required".into());
+        }
+        let pool = if self.threads > 1 {
+            Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(self.threads)
+                    .build()?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Ostir {
+            asd,
+            start_codons,
+            parameters: self.parameters,
+            circular: self.circular,
+            range: self.range,
+            backend: self.backend,
+            accessibility: self.accessibility,
+            ensemble: self.ensemble,
+            uncertainty: self.uncertainty,
+            kinetics: self.kinetics,
+            local_folding: self.local_folding,
+            cotranscriptional: self.cotranscriptional,
+            competition: self.competition,
+            srna,
+            context: self.context,
+            pool,
+        })
+    }
+}
+
+impl Iterator for PredictionIter<'_> {
+    type Item = Result<Prediction, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let params = &self.ostir.parameters;
+        for (start_pos, codon) in self.start_codons.by_ref() {
+            match self.ostir.predict_start_codon(
+                &self.ostir.asd,
+                params,
+                &MrnaState::default(),
+                &self.sequence,
+                start_pos,
+                &codon,
+            ) {
+                Ok(Some(prediction)) => return Some(Ok(prediction)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// Rounds to `decimal_places` when given, for the tables of every prediction mode
+pub(crate) fn rounding(decimal_places: Option<u32>) -> impl Fn(f64) -> f64 + Copy {
+    move |x| match decimal_places {
+        Some(decimal_places) => {
+            let factor = 10_f64.powi(decimal_places as i32);
+            (x * factor).round() / factor
+        }
+        None => x,
+    }
+}
+
+/// Collects predictions into a table with the same columns as the OSTIR command line output.
+/// Energies and expression are rounded to `decimal_places` when given. The optional columns
+/// follow the fixed ones in the order of the `Prediction` fields, so the columns added by each
+/// option stay together: co-transcriptional folding, start codon context, accessibility,
+/// ensemble energies, uncertainty, sRNA, kinetics, local folding and start codon competition.
+pub fn predictions_to_dataframe(
+    predictions: &[Prediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let column = |f: fn(&Prediction) -> f64| -> Vec<f64> {
+        predictions.iter().map(|p| round(f(p))).collect()
+    };
+
+    let mut df = df!(
+        "name" => predictions.iter().map(|p| p.name.as_str()).collect::<Vec<&str>>(),
+        "start_codon" => predictions.iter().map(|p| p.start_codon.as_str()).collect::<Vec<&str>>(),
+        "start_position" => predictions.iter().map(|p| p.start_position as i64).collect::<Vec<i64>>(),
+        "expression" => column(|p| p.expression),
+        "RBS_distance_bp" => predictions.iter().map(|p| p.rbs_distance_bp).collect::<Vec<i64>>(),
+        "dG_total" => column(|p| p.dg_total),
+        "dG_rRNA:mRNA" => column(|p| p.dg_rrna_mrna),
+        "dG_mRNA" => column(|p| p.dg_mrna),
+        "dG_spacing" => column(|p| p.dg_spacing),
+        "dG_standby" => column(|p| p.dg_standby),
+        "dG_start_codon" => column(|p| p.dg_start_codon),
+    )?;
+
+    // Optional columns are only added when they were computed
+    let optional = |f: fn(&Prediction) -> Option<f64>| -> Option<Vec<Option<f64>>> {
+        predictions
+            .iter()
+            .any(|p| f(p).is_some())
+            .then(|| predictions.iter().map(|p| f(p).map(round)).collect())
+    };
+    if let Some(values) = optional(|p| p.dg_mrna_cotranscriptional) {
+        df.with_column(Series::new("dG_mRNA_cotranscriptional", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_context) {
+        df.with_column(Series::new("dG_context", values))?;
+    }
+    if let Some(values) = optional(|p| p.sd_accessibility) {
+        df.with_column(Series::new("SD_accessibility", values))?;
+    }
+    if let Some(values) = optional(|p| p.start_codon_accessibility) {
+        df.with_column(Series::new("start_codon_accessibility", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_mrna_ensemble) {
+        df.with_column(Series::new("dG_mRNA_ensemble", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_rrna_mrna_ensemble) {
+        df.with_column(Series::new("dG_rRNA:mRNA_ensemble", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_total_ensemble) {
+        df.with_column(Series::new("dG_total_ensemble", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_ensemble) {
+        df.with_column(Series::new("expression_ensemble", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_low) {
+        df.with_column(Series::new("expression_low", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_median) {
+        df.with_column(Series::new("expression_median", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_high) {
+        df.with_column(Series::new("expression_high", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_srna) {
+        df.with_column(Series::new("dG_mRNA:sRNA", values))?;
+    }
+    if let Some(values) = optional(|p| p.sd_occlusion) {
+        df.with_column(Series::new("SD_occlusion", values))?;
+    }
+    if let Some(values) = optional(|p| p.start_codon_occlusion) {
+        df.with_column(Series::new("start_codon_occlusion", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_total_srna) {
+        df.with_column(Series::new("dG_total_sRNA", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_srna) {
+        df.with_column(Series::new("expression_sRNA", values))?;
+    }
+    if let Some(values) = optional(|p| p.kinetic_score) {
+        df.with_column(Series::new("kinetic_score", values))?;
+    }
+    if let Some(values) = optional(|p| p.min_bp_prob) {
+        df.with_column(Series::new("min_bp_prob", values))?;
+    }
+    if predictions.iter().any(|p| p.slow_folding.is_some()) {
+        let flags: Vec<Option<bool>> = predictions.iter().map(|p| p.slow_folding).collect();
+        df.with_column(Series::new("slow_folding", flags))?;
+    }
+    if let Some(values) = optional(|p| p.sd_local_accessibility) {
+        df.with_column(Series::new("SD_local_accessibility", values))?;
+    }
+    if let Some(values) = optional(|p| p.start_codon_local_accessibility) {
+        df.with_column(Series::new("start_codon_local_accessibility", values))?;
+    }
+    if let Some(values) = optional(|p| p.dg_open_local) {
+        df.with_column(Series::new("dG_open_local", values))?;
+    }
+    if let Some(values) = optional(|p| p.initiation_fraction) {
+        df.with_column(Series::new("initiation_fraction", values))?;
+    }
+    if let Some(values) = optional(|p| p.expression_competition) {
+        df.with_column(Series::new("expression_competition", values))?;
+    }
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence, RBS_MRNA};
+    use crate::folding::MockBackend;
+    use crate::types::asd_mismatches;
+
+    #[test]
+    fn predict_with_mock_backend() {
+        // GGAGG binds the CCUCC of the aSD four nt upstream of the aligned start codon
+        let backend = rbs_backend();
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+
+        let predictions = ostir.predict(&sequence).unwrap();
+        assert_eq!(predictions.len(), 1);
+        let prediction = &predictions[0];
+        assert_eq!(prediction.start_codon, "ATG");
+        assert_eq!(prediction.start_position, 14);
+        assert_eq!(prediction.rbs_distance_bp, 4);
+        assert_eq!(prediction.dg_mrna, 0.0);
+        assert!((prediction.dg_rrna_mrna - (-5.0 - HYBRIDIZATION_PENALTY)).abs() < 1e-6);
+        assert!((prediction.dg_spacing - 0.0039).abs() < 1e-4);
+        assert_eq!(prediction.dg_standby, 0.0);
+        let dg_total = prediction.dg_rrna_mrna + prediction.dg_spacing + prediction.dg_start_codon;
+        assert!((prediction.dg_total - dg_total).abs() < 1e-9);
+
+        let streamed: Vec<Prediction> = ostir
+            .predict_iter(&sequence)
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        assert_eq!(streamed, predictions);
+    }
+
+    #[test]
+    fn reactivities_guide_the_mrna_structure() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], -5.5, "...(((.......)))...");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let mut sequence = rbs_sequence();
+        assert_eq!(ostir.predict(&sequence).unwrap()[0].dg_mrna, -6.0);
+
+        // Reactive ends rule out the outer helix
+        sequence.reactivities = vec![None; mrna.len()];
+        for i in [0, 1, 2, 16, 17, 18] {
+            sequence.reactivities[i] = Some(2.0);
+        }
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_mrna, -5.5);
+        assert_eq!(prediction.mrna_structure, "...(((.......)))...");
+
+        sequence.reactivities.push(None);
+        assert!(ostir.predict(&sequence).is_err());
+    }
+
+    #[test]
+    fn constraints_hold_in_the_mrna_rrna_complex() {
+        // Downstream of a 3 nt footprint, the mRNA of the complex folds on its own
+        let post = "GGGAAAACCC";
+        let backend = rbs_backend()
+            .with_structure(&[post], -3.0, "(((....)))")
+            .with_structure(&[post], 0.0, "..........");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .footprint(3)
+            .build()
+            .unwrap();
+        let mut sequence = DNASequence::new(format!("TAAGGAGGTAAAAATG{}", post), 1, false).unwrap();
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert!(prediction
+            .mrna_rrna_structure
+            .ends_with("(((....)))&.)))))..."));
+
+        sequence.constraints = format!("{}{}", ".".repeat(16), "x".repeat(post.len()));
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert!(prediction
+            .mrna_rrna_structure
+            .ends_with("..........&.)))))..."));
+    }
+
+    #[test]
+    fn reactivities_guide_the_mrna_rrna_complex() {
+        // Upstream of the aSD binding site, the mRNA of the complex folds on its own
+        let pre = "GGGCAAAAGCCCTAA";
+        let mrna = format!("{}GGAGGTAAAA", pre);
+        let backend = MockBackend::new()
+            .with_structure(
+                &[&mrna, ECOLI_ASD],
+                -8.0,
+                "...............(((((.....&.)))))...",
+            )
+            .with_structure(&[pre], -3.0, "(((......)))...")
+            .with_structure(&[pre], -2.5, "...(((...)))...");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let mut sequence = DNASequence::new(format!("{}ATGGCT", mrna), 1, false).unwrap();
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert!(prediction
+            .mrna_rrna_structure
+            .starts_with("(((......)))..."));
+
+        // Reactive 5' nucleotides rule out the outer helix
+        sequence.reactivities = vec![None; mrna.len() + 6];
+        for i in [0, 1, 2] {
+            sequence.reactivities[i] = Some(2.0);
+        }
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert!(prediction
+            .mrna_rrna_structure
+            .starts_with("...(((...)))..."));
+    }
+
+    #[test]
+    fn srna_occluding_the_sd_lowers_expression() {
+        let mrna = RBS_MRNA;
+        let backend =
+            rbs_backend().with_structure(&[mrna, "CCTCCT"], -9.0, "..((((((...........&))))))");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .srna("CCUCCU")
+            .build()
+            .unwrap();
+        let sequence = rbs_sequence();
+
+        // The sRNA pairs with all of the SD but none of the start codon
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_srna, Some(-9.0));
+        assert_eq!(prediction.sd_occlusion, Some(1.0));
+        assert_eq!(prediction.start_codon_occlusion, Some(0.0));
+        assert_eq!(prediction.dg_total_srna, Some(prediction.dg_total + 9.0));
+        assert!(prediction.expression_srna.unwrap() < prediction.expression);
+
+        let df = predictions_to_dataframe(&[prediction.clone()], None).unwrap();
+        assert!(df.column("dG_mRNA:sRNA").is_ok());
+        assert!(Ostir::builder().srna("CCNCC").build().is_err());
+    }
+
+    #[test]
+    fn srna_cofold_keeps_the_reactivities_and_constraints() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna, "CCTCCT"], -9.0, "..((((((...........&))))))")
+            .with_structure(&[mrna, "CCTCCT"], -3.0, "...............((((&))))..");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .srna("CCUCCU")
+            .build()
+            .unwrap();
+
+        // A reactive SD does not pair with the sRNA, which binds after the start codon instead
+        let mut sequence = rbs_sequence();
+        sequence.reactivities = vec![None; mrna.len()];
+        sequence.reactivities[2..8].fill(Some(2.0));
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_srna, Some(-3.0));
+        assert_eq!(prediction.sd_occlusion, Some(0.0));
+
+        // Unless the constraints keep that site unpaired
+        sequence.constraints = format!("{}xxxx", ".".repeat(15));
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_srna, Some(-9.0));
+        assert_eq!(prediction.sd_occlusion, Some(1.0));
+    }
+
+    #[test]
+    fn long_range_helices_flag_slow_folding() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend().with_structure(&[mrna], -6.0, "(((.............)))");
+        let sequence = rbs_sequence();
+        let ostir = Ostir::builder().backend(backend.clone()).build().unwrap();
+        assert_eq!(ostir.predict(&sequence).unwrap()[0].slow_folding, None);
+
+        // The outer pair spans 18 of the 19 nt of the folding window
+        let ostir = Ostir::builder()
+            .backend(backend.clone())
+            .kinetics(true)
+            .build()
+            .unwrap();
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert!((prediction.kinetic_score.unwrap() - 18.0 / 19.0).abs() < 1e-9);
+        assert!((prediction.min_bp_prob.unwrap() - 18_f64.powf(-1.44)).abs() < 1e-9);
+        assert_eq!(prediction.slow_folding, Some(true));
+        let df = predictions_to_dataframe(&[prediction.clone()], None).unwrap();
+        assert!(df.column("slow_folding").is_ok());
+
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .kinetics(true)
+            .max_kinetic_score(0.95)
+            .build()
+            .unwrap();
+        assert_eq!(
+            ostir.predict(&sequence).unwrap()[0].slow_folding,
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn nearby_start_codons_share_initiation() {
+        // The GTG at 41 and the AUG at 49 share an RBS, the AUG at 31 has its own
+        let mrna = "ACUUCUAAUUUAUUCUAUUUAUUCGCGGAUAUGCAUAGGAGUGCUUCGAUGUCAU";
+        let sequence = DNASequence::new(mrna.to_string(), 1, false).unwrap();
+        let predictions = Ostir::builder()
+            .build()
+            .unwrap()
+            .predict(&sequence)
+            .unwrap();
+        assert_eq!(predictions[0].initiation_fraction, None);
+
+        let ostir = Ostir::builder().competition(true).build().unwrap();
+        let shared = ostir.predict(&sequence).unwrap();
+        let positions: Vec<usize> = shared.iter().map(|p| p.start_position).collect();
+        assert_eq!(positions, vec![31, 41, 49]);
+        assert_eq!(shared[0].initiation_fraction, Some(1.0));
+        assert_eq!(
+            shared[0].expression_competition,
+            Some(predictions[0].expression)
+        );
+
+        let fraction = |i: usize| shared[i].initiation_fraction.unwrap();
+        assert!((fraction(1) + fraction(2) - 1.0).abs() < 1e-9);
+        // The stronger downstream AUG takes most ribosomes despite the upstream GTG
+        assert!(fraction(2) > 0.5);
+        let strongest = predictions[1].expression.max(predictions[2].expression);
+        let expression =
+            shared[1].expression_competition.unwrap() + shared[2].expression_competition.unwrap();
+        assert!((expression - strongest).abs() < 1e-6);
+    }
+
+    #[test]
+    fn helices_formed_during_transcription_persist() {
+        // The hairpin of the transcript up to the start codon competes with a more stable one
+        let mrna = RBS_MRNA;
+        let backend = |d_g: f32| {
+            rbs_backend()
+                .with_structure(&["TAAGGAGGTAAAAATG"], -2.0, "(((......)))....")
+                .with_structure(&[mrna], -2.0, "(((......))).......")
+                .with_structure(&[mrna], d_g, ".......(((.....))).")
+        };
+        let sequence = rbs_sequence();
+        let predict = |d_g: f32| {
+            let ostir = Ostir::builder()
+                .backend(backend(d_g))
+                .cotranscriptional(true)
+                .elongation_rate(3.0)
+                .build()
+                .unwrap();
+            ostir.predict(&sequence).unwrap().remove(0)
+        };
+
+        // Unfolding the early hairpin gains less than the refolding barrier
+        let prediction = predict(-4.0);
+        assert_eq!(prediction.dg_mrna, -4.0);
+        assert_eq!(prediction.dg_mrna_cotranscriptional, Some(-2.0));
+        let df = predictions_to_dataframe(&[prediction], None).unwrap();
+        assert_eq!(df.get_column_names()[11], "dG_mRNA_cotranscriptional");
+
+        assert_eq!(predict(-6.0).dg_mrna_cotranscriptional, Some(-6.0));
+    }
+
+    #[test]
+    fn transcription_continues_past_the_folding_window() {
+        // Only the whole transcript, one nt longer than the folding window, folds
+        let backend = rbs_backend().with_structure(&[RBS_MRNA], -5.0, ".......((((....))))");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .folding_window(35, 5)
+            .cotranscriptional(true)
+            .elongation_rate(3.0)
+            .build()
+            .unwrap();
+        let prediction = &ostir.predict(&rbs_sequence()).unwrap()[0];
+        assert_eq!(prediction.folding_window, (1, 18));
+        assert_eq!(prediction.dg_mrna, 0.0);
+        assert_eq!(prediction.dg_mrna_cotranscriptional, Some(-5.0));
+    }
+
+    #[test]
+    fn local_folding_opens_the_rbs() {
+        // The rRNA binding site folds back onto the start codon in a third of the ensemble
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], 0.0, "...................")
+            .with_structure(&[mrna], (RT * 2_f64.ln()) as f32, "...(((((...)))))...");
+        let sequence = rbs_sequence();
+        let ostir = Ostir::builder().backend(backend.clone()).build().unwrap();
+        assert_eq!(ostir.predict(&sequence).unwrap()[0].dg_open_local, None);
+
+        let ostir = Ostir::builder()
+            .backend(backend.clone())
+            .local_folding(true)
+            .build()
+            .unwrap();
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        let open: f64 = 2.0 / 3.0;
+        assert!((prediction.sd_local_accessibility.unwrap() - open).abs() < 1e-6);
+        assert!((prediction.start_codon_local_accessibility.unwrap() - open).abs() < 1e-6);
+        assert!((prediction.dg_open_local.unwrap() + RT * open.ln()).abs() < 1e-6);
+        let df = predictions_to_dataframe(&[prediction.clone()], None).unwrap();
+        assert!(df.column("dG_open_local").is_ok());
+
+        // The hairpin spans more than the maximum base pair span
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .local_folding(true)
+            .local_window(19, 4)
+            .build()
+            .unwrap();
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.sd_local_accessibility, Some(1.0));
+        assert_eq!(prediction.dg_open_local, Some(0.0));
+    }
+
+    #[test]
+    fn folding_windows_follow_the_cutoffs() {
+        let mrna = format!("{}TAAGGAGGTAAAAATG{}", "C".repeat(40), "GCT".repeat(20));
+        let sequence = DNASequence::new(mrna.clone(), 1, false).unwrap();
+        let window = |ostir: Ostir| ostir.predict(&sequence).unwrap()[0].folding_window;
+
+        let ostir = Ostir::builder().build().unwrap();
+        assert_eq!(window(ostir), (19, 88));
+
+        let ostir = Ostir::builder().folding_window(10, 5).build().unwrap();
+        assert_eq!(window(ostir), (44, 58));
+
+        let ostir = Ostir::builder().full_transcript(true).build().unwrap();
+        assert_eq!(window(ostir), (1, mrna.len()));
+
+        assert!(Ostir::builder().folding_window(0, 35).build().is_err());
+        assert!(Ostir::builder().folding_window(35, 2).build().is_err());
+        let ostir = Ostir::builder()
+            .folding_window(35, 2)
+            .full_transcript(true)
+            .build()
+            .unwrap();
+        assert_eq!(window(ostir), (1, mrna.len()));
+        let ostir = Ostir::builder()
+            .folding_window(0, 35)
+            .full_transcript(true)
+            .build()
+            .unwrap();
+        assert_eq!(window(ostir), (1, mrna.len()));
+    }
+
+    #[test]
+    fn ensemble_energies_weight_every_binding_site() {
+        // Two binding sites of the aSD, four and seven nt upstream of the start codon
+        let backend = rbs_backend().with_structure(
+            &["TAAGGAGGTAAAA", ECOLI_ASD],
+            -7.0,
+            "(((((........&.)))))...",
+        );
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .ensemble(true)
+            .build()
+            .unwrap();
+        let sequence = rbs_sequence();
+
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        let params = ostir.parameters();
+        let weight =
+            |d_g: f64, spacing: i64| (-(d_g + calc_spacing_penalty(spacing, params)) / RT).exp();
+        let z = weight(-8.0, 4) + weight(-7.0, 7);
+        let dg_rrna_mrna_ensemble = prediction.dg_rrna_mrna_ensemble.unwrap();
+        assert!((dg_rrna_mrna_ensemble + RT * z.ln() + params.hybridization_penalty).abs() < 1e-9);
+        assert!(dg_rrna_mrna_ensemble < prediction.dg_rrna_mrna + prediction.dg_spacing);
+        assert_eq!(prediction.dg_mrna_ensemble, Some(0.0));
+        assert_eq!(prediction.sd_accessibility, None);
+
+        let df = predictions_to_dataframe(std::slice::from_ref(prediction), None).unwrap();
+        assert!(df.column("dG_rRNA:mRNA_ensemble").is_ok());
+        assert!(df.column("SD_accessibility").is_err());
+    }
+
+    #[test]
+    fn uncertainty_interval_spans_binding_sites() {
+        let backend = rbs_backend().with_structure(
+            &["TAAGGAGGTAAAA", ECOLI_ASD],
+            -7.0,
+            "(((((........&.)))))...",
+        );
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .uncertainty(true)
+            .build()
+            .unwrap();
+        let sequence = rbs_sequence();
+
+        // The weaker site carries about 5% of the weight, so it only moves the low end
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        let params = ostir.parameters();
+        let expression = |d_g: f64, spacing: i64| {
+            let dg_site = d_g + calc_spacing_penalty(spacing, params);
+            let dg_total = dg_site - params.hybridization_penalty + prediction.dg_start_codon
+                - prediction.dg_standby;
+            calc_expression_level(dg_total, params)
+        };
+        assert!((prediction.expression_low.unwrap() - expression(-7.0, 7)).abs() < 1e-9);
+        assert!((prediction.expression_median.unwrap() - expression(-8.0, 4)).abs() < 1e-9);
+        assert_eq!(prediction.expression_high, prediction.expression_median);
+        assert_eq!(prediction.expression_ensemble, None);
+    }
+
+    #[test]
+    fn uncertainty_folds_the_mrna_like_dg_mrna() {
+        // The refolded complex scores like its binding site
+        let backend = rbs_backend()
+            .with_structure(
+                &[RBS_MRNA, ECOLI_ASD],
+                -8.0,
+                "...(((((...........&.)))))...",
+            )
+            .with_structure(&[RBS_MRNA], -6.0, "(((.............)))")
+            .with_structure(&[RBS_MRNA], 0.0, "...................");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .uncertainty(true)
+            .build()
+            .unwrap();
+
+        // Reactive ends keep the mRNA from closing the hairpin
+        let mut sequence = rbs_sequence();
+        sequence.reactivities = vec![Some(2.0); RBS_MRNA.len()];
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_mrna, 0.0);
+        assert!(prediction.expression_low.unwrap() <= prediction.expression);
+        assert!(prediction.expression <= prediction.expression_high.unwrap());
+    }
+
+    #[test]
+    fn temperature_sweep_rescales_expression() {
+        let backend = rbs_backend();
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+
+        let sweep = ostir
+            .predict_temperature_sweep(&sequence, &[20.0, 37.0])
+            .unwrap();
+        assert_eq!(sweep.len(), 2);
+        assert_eq!(sweep[1], (37.0, ostir.predict(&sequence).unwrap()));
+
+        // The mock energies do not change with temperature, so only BETA is rescaled
+        let (cold, warm) = (&sweep[0].1[0], &sweep[1].1[0]);
+        assert_eq!(cold.dg_total, warm.dg_total);
+        assert!(cold.expression > warm.expression);
+    }
+
+    #[test]
+    fn host_presets_select_the_asd() {
+        assert_eq!(asd_preset("B. subtilis"), Some("ACCTCCTTTCT"));
+        assert_eq!(asd_preset("bacillus subtilis"), Some("ACCTCCTTTCT"));
+        assert_eq!(asd_preset("Synechocystis"), Some("ACCTCCTTT"));
+        assert_eq!(asd_preset("B. anthracis"), None);
+
+        let ostir = Ostir::builder()
+            .asd("GGGGG")
+            .organism("C. glutamicum")
+            .build()
+            .unwrap();
+        assert_eq!(ostir.asd(), "ACCTCCTTTCT");
+        assert!(Ostir::builder().organism("Yeast").build().is_err());
+
+        // Shifted and truncated 16S 3' ends match; unrelated sequences do not
+        assert_eq!(asd_mismatches(ECOLI_ASD), 0);
+        assert_eq!(asd_mismatches("GATCACCTCCTT"), 0);
+        assert_eq!(asd_mismatches("acuuccuua"), 1);
+        assert!(asd_mismatches("GTGTGTGTG") > ASD_MAX_MISMATCHES);
+    }
+
+    #[test]
+    fn context_energies_add_to_dg_total() {
+        let backend = rbs_backend();
+        // An A before the start codon and a G opening the second codon; +30 is past the mRNA
+        let context = ContextTable::new(vec![
+            (-1, [-0.5, 0.0, 0.0, 0.0]),
+            (4, [0.0, 0.0, 1.0, 0.0]),
+            (30, [9.0, 9.0, 9.0, 9.0]),
+        ])
+        .unwrap();
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .context(context)
+            .build()
+            .unwrap();
+        let sequence = rbs_sequence();
+
+        let prediction = &ostir.predict(&sequence).unwrap()[0];
+        assert_eq!(prediction.dg_context, Some(0.5));
+        let dg_total = prediction.dg_rrna_mrna + prediction.dg_spacing + prediction.dg_start_codon;
+        assert!((prediction.dg_total - dg_total - 0.5).abs() < 1e-9);
+        let df = predictions_to_dataframe(&[prediction.clone()], None).unwrap();
+        assert_eq!(df.get_column_names()[11], "dG_context");
+
+        assert!(ContextTable::new(vec![(0, [0.0; 4])]).is_err());
+        assert!(ContextTable::new(vec![(1, [0.0; 4]), (1, [1.0; 4])]).is_err());
+    }
+}
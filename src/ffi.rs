@@ -0,0 +1,464 @@
+//! C ABI over the prediction engine. The matching header is `include/ostir.h`, which is
+//! generated from this module with `cbindgen --config cbindgen.toml --output include/ostir.h`.
+//!
+//! All functions returning `c_int` return `OSTIR_OK` (0) on success and a negative status
+//! otherwise; `ostir_last_error` then describes what went wrong.
+use crate::factory::{predictions_to_dataframe, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use crate::types::ModelParameters;
+use polars::prelude::*;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+pub const OSTIR_OK: c_int = 0;
+pub const OSTIR_ERROR_NULL_POINTER: c_int = -1;
+pub const OSTIR_ERROR_INVALID_ARGUMENT: c_int = -2;
+pub const OSTIR_ERROR_PREDICTION: c_int = -3;
+pub const OSTIR_ERROR_OUT_OF_RANGE: c_int = -4;
+
+/// Opaque prediction context holding the settings and the rows of the last prediction
+pub struct OstirContext {
+    asd: String,
+    start_codons: Vec<String>,
+    parameters: ModelParameters,
+    threads: usize,
+    circular: bool,
+    range: Option<(usize, usize)>,
+    ostir: Option<Ostir>, // Rebuilt when a setting changes
+    predictions: Vec<Prediction>,
+    row_strings: Vec<[CString; 4]>,
+    table: Option<DataFrame>,
+    last_error: CString,
+}
+
+/// One prediction. String pointers are owned by the context and stay valid until the next
+/// call to `ostir_predict` or `ostir_context_free`.
+#[repr(C)]
+pub struct OstirRow {
+    pub name: *const c_char,
+    pub start_codon: *const c_char,
+    pub start_position: usize,
+    pub expression: f64,
+    pub rbs_distance_bp: i64,
+    pub dg_total: f64,
+    pub dg_rrna_mrna: f64,
+    pub dg_mrna: f64,
+    pub dg_spacing: f64,
+    pub dg_standby: f64,
+    pub dg_start_codon: f64,
+    pub mrna_structure: *const c_char,
+    pub mrna_rrna_structure: *const c_char,
+}
+
+impl OstirContext {
+    fn fail(&mut self, status: c_int, message: &str) -> c_int {
+        self.last_error = CString::new(message.replace('\0', "")).unwrap_or_default();
+        status
+    }
+
+    fn ostir(&mut self) -> Result<&Ostir, String> {
+        if self.ostir.is_none() {
+            let mut builder = Ostir::builder()
+                .asd(&self.asd)
+                .parameters(self.parameters.clone())
+                .threads(self.threads)
+                .circular(self.circular);
+            let start_codons: Vec<&str> = self.start_codons.iter().map(|c| c.as_str()).collect();
+            builder = builder.start_codons(&start_codons);
+            if let Some((start, end)) = self.range {
+                builder = builder.range(start, end);
+            }
+            self.ostir = Some(builder.build().map_err(|e| e.to_string())?);
+        }
+        Ok(self.ostir.as_ref().unwrap())
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Creates a context with the default OSTIR settings. Free it with `ostir_context_free`.
+#[no_mangle]
+pub extern "C" fn ostir_context_new() -> *mut OstirContext {
+    Box::into_raw(Box::new(OstirContext {
+        asd: crate::constants::ECOLI_ASD.to_string(),
+        start_codons: crate::constants::DEFAULT_START_CODONS
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        parameters: ModelParameters::default(),
+        threads: 1,
+        circular: false,
+        range: None,
+        ostir: None,
+        predictions: vec![],
+        row_strings: vec![],
+        table: None,
+        last_error: CString::default(),
+    }))
+}
+
+/// # Safety
+/// `ctx` must come from `ostir_context_new` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_context_free(ctx: *mut OstirContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Message describing the last failed call, or an empty string
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_last_error(ctx: *const OstirContext) -> *const c_char {
+    match ctx.as_ref() {
+        Some(ctx) => ctx.last_error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Sets the anti-Shine-Dalgarno sequence
+///
+/// # Safety
+/// `ctx` must be a valid context and `asd` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_asd(ctx: *mut OstirContext, asd: *const c_char) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    match to_str(asd) {
+        Some(asd) => {
+            ctx.asd = asd.to_string();
+            ctx.ostir = None;
+            OSTIR_OK
+        }
+        None => ctx.fail(
+            OSTIR_ERROR_INVALID_ARGUMENT,
+            "The anti-Shine-Dalgarno sequence is not valid UTF-8",
+        ),
+    }
+}
+
+/// Sets the start codons as a comma separated list, e.g. "ATG,GTG,TTG"
+///
+/// # Safety
+/// `ctx` must be a valid context and `start_codons` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_start_codons(
+    ctx: *mut OstirContext,
+    start_codons: *const c_char,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    match to_str(start_codons) {
+        Some(start_codons) => {
+            ctx.start_codons = start_codons
+                .split(',')
+                .map(|c| c.trim().to_string())
+                .collect();
+            ctx.ostir = None;
+            OSTIR_OK
+        }
+        None => ctx.fail(
+            OSTIR_ERROR_INVALID_ARGUMENT,
+            "The start codons are not valid UTF-8",
+        ),
+    }
+}
+
+/// Sets a numeric model parameter by name: beta, log_k, optimal_spacing, cutoff (both
+/// upstream_cutoff and downstream_cutoff), upstream_cutoff, downstream_cutoff, full_transcript
+/// (nonzero to fold whole transcripts), standby_site_length, footprint, energy_cutoff,
+/// hybridization_penalty, temperature, monovalent or magnesium (salt concentrations in mol/L).
+/// Lengths and cutoffs must be non-negative integers.
+///
+/// # Safety
+/// `ctx` must be a valid context and `name` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_parameter(
+    ctx: *mut OstirContext,
+    name: *const c_char,
+    value: f64,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    let parameters = &mut ctx.parameters;
+    let count = value as usize;
+    let is_count = value.is_finite() && value >= 0.0 && value.fract() == 0.0;
+    match to_str(name) {
+        Some(
+            "optimal_spacing"
+            | "cutoff"
+            | "upstream_cutoff"
+            | "downstream_cutoff"
+            | "standby_site_length"
+            | "footprint",
+        ) if !is_count => {
+            return ctx.fail(
+                OSTIR_ERROR_INVALID_ARGUMENT,
+                "The model parameter must be a non-negative integer",
+            )
+        }
+        Some("beta") => parameters.beta = value,
+        Some("log_k") => parameters.log_k = value,
+        Some("optimal_spacing") => parameters.optimal_spacing = count,
+        Some("cutoff") => {
+            parameters.upstream_cutoff = count;
+            parameters.downstream_cutoff = count;
+        }
+        Some("upstream_cutoff") => parameters.upstream_cutoff = count,
+        Some("downstream_cutoff") => parameters.downstream_cutoff = count,
+        Some("full_transcript") => parameters.full_transcript = value != 0.0,
+        Some("standby_site_length") => parameters.standby_site_length = count,
+        Some("footprint") => parameters.footprint = count,
+        Some("energy_cutoff") => parameters.energy_cutoff = value as f32,
+        Some("hybridization_penalty") => parameters.hybridization_penalty = value,
+        Some("temperature") => parameters.temperature = value as f32,
+        Some("monovalent") => parameters.salt.monovalent = value,
+        Some("magnesium") => parameters.salt.magnesium = value,
+        _ => return ctx.fail(OSTIR_ERROR_INVALID_ARGUMENT, "Unknown model parameter"),
+    }
+    ctx.ostir = None;
+    OSTIR_OK
+}
+
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_threads(ctx: *mut OstirContext, threads: usize) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    ctx.threads = threads;
+    ctx.ostir = None;
+    OSTIR_OK
+}
+
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_circular(ctx: *mut OstirContext, circular: bool) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    ctx.circular = circular;
+    ctx.ostir = None;
+    OSTIR_OK
+}
+
+/// Only considers start codons between these 1-indexed positions (inclusive). Passing 0 for
+/// both resets the range to the whole sequence.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_set_range(
+    ctx: *mut OstirContext,
+    start: usize,
+    end: usize,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    ctx.range = if start == 0 && end == 0 {
+        None
+    } else {
+        Some((start, end))
+    };
+    ctx.ostir = None;
+    OSTIR_OK
+}
+
+/// Predicts every start codon of `sequence`, replacing the rows of the previous prediction
+///
+/// # Safety
+/// `ctx` must be a valid context. `name` may be null; otherwise it and `sequence` must be
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_predict(
+    ctx: *mut OstirContext,
+    name: *const c_char,
+    sequence: *const c_char,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    ctx.predictions.clear();
+    ctx.row_strings.clear();
+    ctx.table = None;
+
+    let Some(sequence) = to_str(sequence) else {
+        return ctx.fail(
+            OSTIR_ERROR_INVALID_ARGUMENT,
+            "The sequence is null or not valid UTF-8",
+        );
+    };
+    let name = to_str(name).unwrap_or("");
+
+    let result = catch_unwind(AssertUnwindSafe(|| -> Result<Vec<Prediction>, String> {
+        let mut sequence =
+            DNASequence::new(sequence.to_string(), 1, false).map_err(|e| e.to_string())?;
+        sequence.description = name.to_string();
+        ctx.ostir()?.predict(&sequence).map_err(|e| e.to_string())
+    }));
+    let predictions = match result {
+        Ok(Ok(predictions)) => predictions,
+        Ok(Err(e)) => return ctx.fail(OSTIR_ERROR_PREDICTION, &e),
+        Err(_) => return ctx.fail(OSTIR_ERROR_PREDICTION, "The prediction panicked"),
+    };
+
+    let c_string = |s: &str| CString::new(s.replace('\0', "")).unwrap_or_default();
+    ctx.row_strings = predictions
+        .iter()
+        .map(|p| {
+            [
+                c_string(&p.name),
+                c_string(&p.start_codon),
+                c_string(&p.mrna_structure),
+                c_string(&p.mrna_rrna_structure),
+            ]
+        })
+        .collect();
+    ctx.table = predictions_to_dataframe(&predictions, None).ok();
+    ctx.predictions = predictions;
+    OSTIR_OK
+}
+
+/// Number of rows produced by the last `ostir_predict`
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_num_rows(ctx: *const OstirContext) -> usize {
+    match ctx.as_ref() {
+        Some(ctx) => ctx.predictions.len(),
+        None => 0,
+    }
+}
+
+/// Copies row `row` of the last prediction into `out`
+///
+/// # Safety
+/// `ctx` must be a valid context and `out` must point to writable memory for an `OstirRow`.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_get_row(
+    ctx: *mut OstirContext,
+    row: usize,
+    out: *mut OstirRow,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    if out.is_null() {
+        return ctx.fail(OSTIR_ERROR_NULL_POINTER, "The output row is null");
+    }
+    let (Some(p), Some(strings)) = (ctx.predictions.get(row), ctx.row_strings.get(row)) else {
+        return ctx.fail(OSTIR_ERROR_OUT_OF_RANGE, "Row index out of range");
+    };
+    *out = OstirRow {
+        name: strings[0].as_ptr(),
+        start_codon: strings[1].as_ptr(),
+        start_position: p.start_position,
+        expression: p.expression,
+        rbs_distance_bp: p.rbs_distance_bp,
+        dg_total: p.dg_total,
+        dg_rrna_mrna: p.dg_rrna_mrna,
+        dg_mrna: p.dg_mrna,
+        dg_spacing: p.dg_spacing,
+        dg_standby: p.dg_standby,
+        dg_start_codon: p.dg_start_codon,
+        mrna_structure: strings[2].as_ptr(),
+        mrna_rrna_structure: strings[3].as_ptr(),
+    };
+    OSTIR_OK
+}
+
+/// Reads any numeric output column by its CSV header name (e.g. "dG_rRNA:mRNA") for row `row`
+///
+/// # Safety
+/// `ctx` must be a valid context, `column` a NUL-terminated string and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ostir_get_value(
+    ctx: *mut OstirContext,
+    row: usize,
+    column: *const c_char,
+    out: *mut f64,
+) -> c_int {
+    let Some(ctx) = ctx.as_mut() else {
+        return OSTIR_ERROR_NULL_POINTER;
+    };
+    if out.is_null() {
+        return ctx.fail(OSTIR_ERROR_NULL_POINTER, "The output value is null");
+    }
+    let Some(column) = to_str(column) else {
+        return ctx.fail(
+            OSTIR_ERROR_INVALID_ARGUMENT,
+            "The column name is null or not valid UTF-8",
+        );
+    };
+    let value = ctx.table.as_ref().and_then(|table| {
+        let series = table.column(column).ok()?.cast(&DataType::Float64).ok()?;
+        series.f64().ok()?.get(row)
+    });
+    match value {
+        Some(value) => {
+            *out = value;
+            OSTIR_OK
+        }
+        None => ctx.fail(
+            OSTIR_ERROR_OUT_OF_RANGE,
+            "Unknown column, non-numeric column or row out of range",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicts_through_the_c_abi() {
+        let name = CString::new("test").unwrap();
+        let sequence =
+            CString::new("ACTTCTAATTTATTCTATTTATTCGGAGGTTTATCATGCGTAAAGGCGAAGAGCTG").unwrap();
+        let parameter = |name: &str| CString::new(name).unwrap();
+        unsafe {
+            let ctx = ostir_context_new();
+            for bad in [-1.0, 2.5, f64::NAN, f64::INFINITY] {
+                let status = ostir_set_parameter(ctx, parameter("footprint").as_ptr(), bad);
+                assert_eq!(status, OSTIR_ERROR_INVALID_ARGUMENT);
+            }
+            let status = ostir_set_parameter(ctx, parameter("cutoff").as_ptr(), 30.0);
+            assert_eq!(status, OSTIR_OK);
+            assert_eq!((*ctx).parameters.upstream_cutoff, 30);
+
+            assert_eq!(
+                ostir_predict(ctx, name.as_ptr(), sequence.as_ptr()),
+                OSTIR_OK
+            );
+            assert!(ostir_num_rows(ctx) > 0);
+            let mut row = std::mem::MaybeUninit::<OstirRow>::uninit();
+            assert_eq!(ostir_get_row(ctx, 0, row.as_mut_ptr()), OSTIR_OK);
+            let row = row.assume_init();
+            assert_eq!(CStr::from_ptr(row.name).to_str(), Ok("test"));
+            assert!(row.expression > 0.0);
+            let mut expression = 0.0;
+            let column = parameter("expression");
+            assert_eq!(
+                ostir_get_value(ctx, 0, column.as_ptr(), &mut expression),
+                OSTIR_OK
+            );
+            assert_eq!(expression, row.expression);
+            ostir_context_free(ctx);
+        }
+    }
+}
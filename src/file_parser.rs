@@ -1,4 +1,5 @@
 pub mod fileparser {
+    use crate::types::ContextTable;
     use std::char;
     use std::io::Error;
     use std::path::Path;
@@ -12,11 +13,17 @@ pub mod fileparser {
         pub max_iter_size: usize,
         pub buffered_sequence: String,
         pub sequence_length: usize,
+        /// Per-nucleotide SHAPE reactivities (`None` where unmeasured); empty when no probing
+        /// data is available
+        pub reactivities: Vec<Option<f64>>,
+        /// Hard folding constraints in ViennaRNA dot-bracket notation (`x` keeps a nucleotide
+        /// unpaired); empty when the sequence folds freely
+        pub constraints: String,
         buffer_is_full: bool,
     }
 
     impl DNASequence {
-        fn new(seq: String, max_iter_size: usize, iscircular: bool) -> Result<DNASequence, Error> {
+        pub fn new(seq: String, max_iter_size: usize, iscircular: bool) -> Result<DNASequence, Error> {
             // Perform sanity checking
 
             if max_iter_size <= 0 {
@@ -65,6 +72,8 @@ pub mod fileparser {
             max_iter_size: max_iter_size,
             buffered_sequence: String::new(),
             sequence_length: seq_length.clone(),
+            reactivities: Vec::new(),
+            constraints: String::new(),
             buffer_is_full: false,
         }
      }
@@ -120,10 +129,40 @@ pub mod fileparser {
         }
     }
 
+    /// Annotated feature of a GenBank record. Coordinates are 1-indexed and inclusive, and span
+    /// every part of joined locations.
     pub struct Feature {
-        description: String,
-        start: usize,
-        end: usize,
+        pub kind: String,        // Feature key, e.g. rRNA or CDS
+        pub description: String, // Qualifiers, one per line
+        pub start: usize,
+        pub end: usize,
+        pub complement: bool,
+    }
+
+    impl Feature {
+        /// Sequence of the feature in the 5' to 3' direction of its strand
+        pub fn sequence(&self, record: &str) -> String {
+            let forward = &record[self.start - 1..self.end];
+            match self.complement {
+                true => reverse_complement(forward),
+                false => forward.to_string(),
+            }
+        }
+    }
+
+    /// Reverse complement of a DNA or RNA sequence as uppercase DNA. Ambiguous bases become N.
+    pub fn reverse_complement(sequence: &str) -> String {
+        sequence
+            .chars()
+            .rev()
+            .map(|c| match c.to_ascii_uppercase() {
+                'A' => 'T',
+                'C' => 'G',
+                'G' => 'C',
+                'T' | 'U' => 'A',
+                _ => 'N',
+            })
+            .collect()
     }
 
     fn parse_fasta(
@@ -154,7 +193,10 @@ pub mod fileparser {
                         in_seq = false;
                         let new_seq = DNASequence::new(sequence, max_iter_size, iscircular);
                         match new_seq {
-                            Ok(seq) => sequences.push(seq),
+                            Ok(mut seq) => {
+                                seq.description = description.trim().to_string();
+                                sequences.push(seq)
+                            }
                             Err(e) => return Err(e),
                         };
                         description = String::new();
@@ -185,7 +227,10 @@ pub mod fileparser {
         if in_seq {
             let new_seq = DNASequence::new(sequence, max_iter_size, iscircular);
             match new_seq {
-                Ok(seq) => sequences.push(seq),
+                Ok(mut seq) => {
+                    seq.description = description.trim().to_string();
+                    sequences.push(seq)
+                }
                 Err(e) => return Err(e),
             };
         }
@@ -193,23 +238,105 @@ pub mod fileparser {
         Ok(sequences)
     }
 
-    struct GenbankParser {
-        name: String,
-        file: String,
-        iscircular: bool,
-        bufferedseq: String,
-    }
-    impl GenbankParser {
-        fn new(_file: String, _iscircular: bool) {
-            todo!("Implement GenbankParser")
+    /// Creates a feature without qualifiers from its key and a GenBank location such as
+    /// `complement(<1..200)` or `join(1..50,60..200)`. Locations that refer to other records are
+    /// not supported.
+    fn parse_feature(kind: &str, location: &str) -> Result<Feature, Error> {
+        let positions: Vec<usize> = location
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|position| position.parse().ok())
+            .collect();
+        match (positions.iter().min(), positions.iter().max()) {
+            (Some(&start), Some(&end)) if start > 0 && !location.contains(':') => Ok(Feature {
+                kind: kind.to_string(),
+                description: String::new(),
+                start,
+                end,
+                complement: location.contains("complement("),
+            }),
+            _ => Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid location of a {} feature: {}", kind, location),
+            )),
         }
+    }
 
-        fn get(self, _start: i32, _end: i32) -> SeqSegment {
-            todo!("Implement get")
-        }
-        fn next() -> SeqSegment {
-            todo!("Implement next")
+    /// Reads the records of a GenBank flat file with their features. Records are named after
+    /// their LOCUS and are circular when their LOCUS line says so.
+    fn parse_genbank(file: &Path, max_iter_size: usize) -> Result<Vec<DNASequence>, Error> {
+        let contents = std::fs::read_to_string(file)?;
+
+        let mut sequences = Vec::new();
+        for entry in contents.split("\n//").filter(|entry| entry.contains("LOCUS")) {
+            let mut name = String::new();
+            let mut iscircular = false;
+            let mut features: Vec<Feature> = Vec::new();
+            let mut record = String::new();
+            // Feature key and location until its first qualifier, which may span several lines
+            let mut location: Option<(String, String)> = None;
+            let mut section = "";
+
+            for line in entry.lines() {
+                if !line.starts_with(' ') && !line.trim().is_empty() {
+                    section = line.split_whitespace().next().unwrap_or("");
+                    if section == "LOCUS" {
+                        name = line.split_whitespace().nth(1).unwrap_or("").to_string();
+                        iscircular = line.contains(" circular");
+                    }
+                    continue;
+                }
+                match section {
+                    "FEATURES" => {
+                        let key = line.get(5..21).unwrap_or("").trim();
+                        let value = line.get(21..).unwrap_or("").trim();
+                        if !key.is_empty() {
+                            if let Some((key, text)) = location.take() {
+                                features.push(parse_feature(&key, &text)?);
+                            }
+                            location = Some((key.to_string(), value.to_string()));
+                            continue;
+                        }
+                        if let Some((key, text)) = &mut location {
+                            if !value.starts_with('/') {
+                                text.push_str(value);
+                                continue;
+                            }
+                            features.push(parse_feature(key, text)?);
+                            location = None;
+                        }
+                        if let Some(feature) = features.last_mut() {
+                            if !feature.description.is_empty() {
+                                let separator = if value.starts_with('/') { '\n' } else { ' ' };
+                                feature.description.push(separator);
+                            }
+                            feature.description.push_str(value);
+                        }
+                    }
+                    "ORIGIN" => record.extend(line.chars().filter(|c| c.is_alphabetic())),
+                    _ => {}
+                }
+            }
+            // The last feature may have no qualifiers
+            if let Some((key, text)) = location {
+                features.push(parse_feature(&key, &text)?);
+            }
+
+            if let Some(feature) = features.iter().find(|feature| feature.end > record.len()) {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "The {} feature at {}..{} runs past the end of {}",
+                        feature.kind, feature.start, feature.end, name
+                    ),
+                ));
+            }
+            let mut sequence = DNASequence::new(record, max_iter_size, iscircular)?;
+            sequence.description = name;
+            sequence.features = features;
+            sequences.push(sequence);
         }
+
+        Ok(sequences)
     }
 
     pub struct SeqSegment {
@@ -218,9 +345,113 @@ pub mod fileparser {
         pub end: usize,
     }
 
+    /// Reads a `.shape` reactivity profile: one `position reactivity` pair per line, with
+    /// 1-indexed positions separated from the value by whitespace or a comma. Positions that
+    /// are missing or marked -999 are returned as `None`.
+    pub fn parse_shape_file(filename: &str) -> Result<Vec<Option<f64>>, std::io::Error> {
+        let contents = std::fs::read_to_string(filename)?;
+        let invalid = |line: &str| {
+            Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid reactivity line: {}", line),
+            )
+        };
+
+        let mut reactivities = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty());
+            let (position, value) = match (fields.next(), fields.next()) {
+                (Some(position), Some(value)) => (position, value),
+                _ => return Err(invalid(line)),
+            };
+            let position: usize = match position.parse() {
+                Ok(position) if position > 0 => position,
+                // Skip a header row such as "position,reactivity"
+                Err(_) if reactivities.is_empty() => continue,
+                _ => return Err(invalid(line)),
+            };
+            let value: f64 = value.parse().map_err(|_| invalid(line))?;
+
+            if reactivities.len() < position {
+                reactivities.resize(position, None);
+            }
+            reactivities[position - 1] = if value <= -999.0 { None } else { Some(value) };
+        }
+
+        Ok(reactivities)
+    }
+
+    /// Reads a start codon context table: one `position A C G T` row of energies (kcal/mol) per
+    /// line, separated by whitespace or commas. Positions are numbered from +1 at the first
+    /// nucleotide of the start codon and -1 before it. A header row and `#` comments are skipped.
+    pub fn parse_context_file(filename: &str) -> Result<ContextTable, std::io::Error> {
+        let contents = std::fs::read_to_string(filename)?;
+        let invalid = |message: String| Error::new(std::io::ErrorKind::InvalidData, message);
+
+        let mut scores = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            let [position, a, c, g, t] = fields[..] else {
+                return Err(invalid(format!("Invalid context line: {}", line)));
+            };
+            let position: i64 = match position.trim_start_matches('+').parse() {
+                Ok(position) => position,
+                // Skip a header row such as "position,A,C,G,T"
+                Err(_) if scores.is_empty() => continue,
+                Err(_) => return Err(invalid(format!("Invalid context line: {}", line))),
+            };
+            let mut energies = [0.0; 4];
+            for (energy, field) in energies.iter_mut().zip([a, c, g, t]) {
+                *energy = field
+                    .parse()
+                    .map_err(|_| invalid(format!("Invalid context line: {}", line)))?;
+            }
+            scores.push((position, energies));
+        }
+
+        ContextTable::new(scores).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Input type of a file from its extension, "fasta" or "genbank"
+    pub fn file_type(filename: &str) -> Option<&'static str> {
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        if ["fasta", "fa", "fna"].contains(&extension) {
+            Some("fasta")
+        } else if ["gb", "gbk", "gbff", "genbank"].contains(&extension) {
+            Some("genbank")
+        } else {
+            None
+        }
+    }
+
     pub fn parse_file(
         filename: &str,
         max_iter_size: usize,
+    ) -> Result<Vec<DNASequence>, std::io::Error> {
+        parse_file_as(filename, file_type(filename).unwrap_or(""), max_iter_size)
+    }
+
+    /// Parses a file as the given input type, "fasta" or "genbank", whatever its extension
+    pub fn parse_file_as(
+        filename: &str,
+        file_type: &str,
+        max_iter_size: usize,
     ) -> Result<Vec<DNASequence>, std::io::Error> {
         // Check the file type
         // Create the appropriate SeqParser
@@ -239,12 +470,14 @@ pub mod fileparser {
 
         println!("Parsing file");
 
-        if file.extension().unwrap() == "fasta" {
+        if file_type == "fasta" {
             let result = parse_fasta(file, max_iter_size, iscircular);
             match result {
                 Ok(seq) => return Ok(seq),
                 Err(e) => return Err(e),
             }
+        } else if file_type == "genbank" {
+            parse_genbank(file, max_iter_size)
         } else {
             // Raise an error
             Err(std::io::Error::new(
@@ -0,0 +1,320 @@
+use crate::constants::{SHAPE_INTERCEPT, SHAPE_SLOPE};
+use crate::types::{DanglesSetting, Ensemble, FoldResult, SaltConditions};
+use std::error::Error;
+
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockBackend;
+
+/// The RNA folding operations OSTIR needs. Sequences with more than one strand are co-folded.
+/// Energies are in kcal/mol and base pair coordinates are 1-indexed over the concatenated strands.
+pub trait FoldingBackend: Send + Sync {
+    /// Minimum free energy structure
+    fn mfe<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>>;
+
+    /// All structures within `energy_gap` of the MFE, sorted by energy. Co-folds in which the
+    /// strands do not bind each other are left out.
+    fn subopt<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        energy_gap: f32,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>>;
+
+    /// Free energy of a given structure in dot-bracket notation
+    fn eval_structure(
+        &self,
+        sequences: &[&str],
+        dots: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> f32;
+
+    /// Ensemble free energy and base pair probabilities from the partition function
+    fn partition_function(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>>;
+
+    /// Minimum free energy structure guided by SHAPE reactivities (one per nucleotide, `None`
+    /// where unmeasured), applied as Deigan pseudo-energies on every nucleotide of a stacked pair.
+    /// The energy reported is that of the structure without the pseudo-energies.
+    fn mfe_with_reactivities<'a>(
+        &self,
+        _sequences: &'a [&'a str],
+        _constraints: &str,
+        _reactivities: &[Option<f64>],
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        Err("This folding backend does not support SHAPE reactivities".into())
+    }
+
+    /// Structures within `energy_gap` of the MFE guided by SHAPE reactivities, as scored with the
+    /// pseudo-energies. Their energies are reported without them, and the structures sorted by
+    /// these. Backends without SHAPE support report no structures.
+    #[allow(clippy::too_many_arguments)]
+    fn subopt_with_reactivities<'a>(
+        &self,
+        _sequences: &'a [&'a str],
+        _constraints: &str,
+        _reactivities: &[Option<f64>],
+        _energy_gap: f32,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        vec![]
+    }
+
+    /// Ensemble guided by SHAPE reactivities, as in `mfe_with_reactivities`. Its free energy
+    /// includes the pseudo-energies.
+    fn partition_function_with_reactivities(
+        &self,
+        _sequences: &[&str],
+        _constraints: &str,
+        _reactivities: &[Option<f64>],
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        Err("This folding backend does not support SHAPE reactivities".into())
+    }
+
+    /// Probability that each nucleotide of a long sequence is unpaired, averaged over every
+    /// window of `window` nt that contains it, with base pairs spanning at most `span` nt (the
+    /// RNAplfold model). Sequences shorter than the window are folded as a whole.
+    fn local_unpaired_probabilities(
+        &self,
+        _sequence: &str,
+        _window: usize,
+        _span: usize,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        Err("This folding backend does not support local folding".into())
+    }
+}
+
+/// Deigan et al. (2009) stacking pseudo-energy of every nucleotide in kcal/mol. Unmeasured and
+/// negative reactivities add nothing.
+pub fn shape_pseudo_energies(reactivities: &[Option<f64>]) -> Vec<f64> {
+    reactivities
+        .iter()
+        .map(|reactivity| match reactivity {
+            Some(r) if *r >= 0.0 => SHAPE_SLOPE * (r + 1.0).ln() + SHAPE_INTERCEPT,
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// Fixture shared by the prediction tests: an mRNA with a GGAGG Shine-Dalgarno upstream of its
+/// AUG and a backend in which the E. coli aSD pairs with it
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::MockBackend;
+    use crate::constants::ECOLI_ASD;
+    use crate::file_parser::fileparser::DNASequence;
+
+    pub(crate) const RBS_MRNA: &str = "TAAGGAGGTAAAAATGGCT";
+
+    /// Backend with only the aSD binding site registered; tests add the structures they need
+    pub(crate) fn rbs_backend() -> MockBackend {
+        MockBackend::new().with_structure(
+            &["TAAGGAGGTAAAA", ECOLI_ASD],
+            -8.0,
+            "...(((((.....&.)))))...",
+        )
+    }
+
+    pub(crate) fn rbs_sequence() -> DNASequence {
+        DNASequence::new(RBS_MRNA.to_string(), 1, false).unwrap()
+    }
+}
+
+// Utilities ----------------
+
+pub(crate) fn round_energy(energy: f32, decimals: i32) -> f32 {
+    let factor = 10_f32.powi(decimals);
+    (energy * factor).round() / factor
+}
+
+pub fn coordinates_to_dots(strands: &[&str], bp_x: &[usize], bp_y: &[usize]) -> String {
+    let bp_x: Vec<usize> = bp_x.iter().map(|&pos| pos - 1).collect(); // Shift so that 1st position is 0
+    let bp_y: Vec<usize> = bp_y.iter().map(|&pos| pos - 1).collect(); // Shift so that 1st position is 0
+
+    let mut bracket_notation = Vec::new();
+    let mut counter = 0;
+
+    for (strand_number, &seq) in strands.iter().enumerate() {
+        let seq_len = seq.len();
+        if strand_number > 0 {
+            bracket_notation.push('&');
+        }
+        for pos in counter..(seq_len + counter) {
+            if bp_x.contains(&pos) {
+                bracket_notation.push('(');
+            } else if bp_y.contains(&pos) {
+                bracket_notation.push(')');
+            } else {
+                bracket_notation.push('.');
+            }
+        }
+        counter += seq_len;
+    }
+
+    bracket_notation.iter().collect::<String>()
+}
+
+/// 1-indexed base pairs of a structure in dot-bracket notation. Strand separators (`&`) do not
+/// count as positions.
+pub fn dots_to_coordinates(dots_string: &str) -> Result<(Vec<usize>, Vec<usize>), Box<dyn Error>> {
+    let mut bp_x: Vec<usize> = vec![];
+    let mut bp_y: Vec<usize> = vec![];
+
+    let mut last_nt_x_list: Vec<usize> = Vec::new();
+    let mut num_strands = 0;
+
+    for (pos, letter) in dots_string.chars().enumerate() {
+        match letter {
+            '.' => {}
+            '(' => {
+                bp_x.push(pos - num_strands);
+                bp_y.push(0); // Placeholder value to be replaced later
+                last_nt_x_list.push(pos - num_strands);
+            }
+            ')' => {
+                let Some(nt_x) = last_nt_x_list.pop() else {
+                    return Err(format!("Unbalanced brackets in {}", dots_string).into());
+                };
+                let nt_x_pos = bp_x.iter().position(|&x| x == nt_x).unwrap();
+                bp_y[nt_x_pos] = pos - num_strands;
+            }
+            '&' => {
+                num_strands += 1;
+            }
+            _ => {
+                return Err(format!("Invalid character {} in bracket notation", letter).into());
+            }
+        }
+    }
+
+    if !last_nt_x_list.is_empty() {
+        return Err(format!("Unbalanced brackets in {}", dots_string).into());
+    }
+
+    // Shift so that 1st position is 1
+    bp_x.iter_mut().for_each(|x| *x += 1);
+    bp_y.iter_mut().for_each(|y| *y += 1);
+    Ok((bp_x, bp_y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_backend_is_deterministic() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let backend = MockBackend::new()
+            .with_structure(&["GGGAAACCC"], -1.5, "(((...)))")
+            .with_structure(&["GGGAAACCC"], -0.5, ".((...)).");
+
+        let sequences = vec!["GGGAAACCC"];
+        let mfe = backend.mfe(&sequences, "", 37.0, &salt, &dangles).unwrap();
+        assert_eq!(*mfe.get_d_g(), -1.5);
+        assert_eq!(mfe.get_bp_x(), &vec![1, 2, 3]);
+        assert_eq!(mfe.get_bp_y(), &vec![9, 8, 7]);
+
+        assert_eq!(
+            backend
+                .subopt(&sequences, "", 0.5, 37.0, &salt, &dangles)
+                .len(),
+            1
+        );
+        assert_eq!(
+            backend
+                .subopt(&sequences, "", 1.0, 37.0, &salt, &dangles)
+                .len(),
+            2
+        );
+        assert_eq!(
+            backend.eval_structure(&sequences, ".((...)).", 37.0, &salt, &dangles),
+            -0.5
+        );
+        assert_eq!(
+            backend.eval_structure(&sequences, "((.....))", 37.0, &salt, &dangles),
+            -2.0
+        );
+        let ensemble = backend
+            .partition_function(&sequences, "", 37.0, &salt, &dangles)
+            .unwrap();
+        assert!(ensemble.d_g < -1.5);
+        assert!((ensemble.pair_probability(2, 8) - 1.0).abs() < 1e-9);
+        assert!(ensemble.pair_probability(1, 9) > 0.5 && ensemble.pair_probability(1, 9) < 1.0);
+        let unpaired = ensemble.unpaired_probabilities();
+        assert!((unpaired[0] + ensemble.pair_probability(1, 9) - 1.0).abs() < 1e-9);
+        assert_eq!(unpaired[4], 1.0);
+
+        let unknown = vec!["ACGU"];
+        assert_eq!(
+            backend
+                .mfe(&unknown, "", 37.0, &salt, &dangles)
+                .unwrap()
+                .get_dots(),
+            "...."
+        );
+    }
+
+    #[test]
+    fn mock_backend_drops_unbound_cofolds() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let backend = MockBackend::new()
+            .with_structure(&["GGAGG", "CCUCC"], -6.0, "(((((&)))))")
+            .with_structure(&["GGAGG", "CCUCC"], -1.0, ".....&.....");
+
+        let sequences = vec!["GGAGG", "CCUCC"];
+        let folds = backend.subopt(&sequences, "", 10.0, 37.0, &salt, &dangles);
+        assert_eq!(folds.len(), 1);
+        assert_eq!(folds[0].get_bp_y(), &vec![10, 9, 8, 7, 6]);
+    }
+
+    #[test]
+    fn bracket_notation_round_trips() {
+        // A single base pair is 1-indexed like any other structure
+        let (bp_x, bp_y) = dots_to_coordinates("(...)").unwrap();
+        assert_eq!((bp_x.clone(), bp_y.clone()), (vec![1], vec![5]));
+        assert_eq!(coordinates_to_dots(&vec!["GAAAC"], &bp_x, &bp_y), "(...)");
+
+        let (bp_x, bp_y) = dots_to_coordinates("((..&.))").unwrap();
+        assert_eq!((bp_x.clone(), bp_y.clone()), (vec![1, 2], vec![7, 6]));
+        assert_eq!(
+            coordinates_to_dots(&vec!["GGAA", "ACC"], &bp_x, &bp_y),
+            "((..&.))"
+        );
+
+        assert!(dots_to_coordinates("((...)").is_err());
+        assert!(dots_to_coordinates("(...))").is_err());
+        assert!(dots_to_coordinates("(.x.)").is_err());
+    }
+}
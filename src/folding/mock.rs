@@ -0,0 +1,287 @@
+//! Deterministic folding backend for the tests of OSTIR and of crates built on it
+use super::{dots_to_coordinates, shape_pseudo_energies, FoldingBackend};
+use crate::constants::{DEFAULT_TEMP, KELVIN, RT};
+use crate::types::{DanglesSetting, Ensemble, FoldResult, SaltConditions};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// Deterministic in-memory backend for tests. Structures are registered per sequence; unknown
+/// sequences stay unfolded and unregistered structures are scored at -1 kcal/mol per base pair.
+/// Hard constraints select among the registered structures.
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend {
+    structures: HashMap<String, Vec<(f32, String)>>,
+    energies: HashMap<(String, String), f32>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a structure of the sequences. The lowest energy one is returned as the MFE.
+    pub fn with_structure(mut self, sequences: &[&str], d_g: f32, dots: &str) -> Self {
+        let folds = self.structures.entry(Self::key(sequences)).or_default();
+        folds.push((d_g, dots.to_string()));
+        folds.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then_with(|| a.1.cmp(&b.1)));
+        self.energies
+            .insert((Self::key(sequences), dots.replace('&', "")), d_g);
+        self
+    }
+
+    fn key(sequences: &[&str]) -> String {
+        sequences.join("&").to_uppercase().replace('T', "U")
+    }
+
+    fn folds(&self, sequences: &[&str]) -> &[(f32, String)] {
+        self.structures
+            .get(&Self::key(sequences))
+            .map(|folds| folds.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Registered structures that keep the forced pairs and unpaired nucleotides of the
+    /// constraints. Fails when there are registered structures but none of them is allowed.
+    fn allowed_folds(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+    ) -> Result<Vec<&(f32, String)>, Box<dyn Error>> {
+        let folds = self.folds(sequences);
+        let allowed: Vec<&(f32, String)> = folds
+            .iter()
+            .filter(|(_, dots)| {
+                let dots = dots.chars().filter(|&c| c != '&');
+                let symbols = constraints.chars().filter(|&c| c != '&');
+                dots.zip(symbols).all(|(dot, symbol)| match symbol {
+                    'x' => dot == '.',
+                    '(' | ')' => dot == symbol,
+                    '|' => dot != '.',
+                    _ => true,
+                })
+            })
+            .collect();
+        if allowed.is_empty() && !folds.is_empty() {
+            return Err("No structure satisfies the constraints".into());
+        }
+        Ok(allowed)
+    }
+
+    /// Energy of a registered structure with the pseudo-energies of its stacks
+    fn shape_score(pseudo_energies: &[f64], (d_g, dots): &(f32, String)) -> f64 {
+        let pseudo_energy = |i: usize| pseudo_energies.get(i - 1).copied().unwrap_or(0.0);
+        // Malformed structures fail where they are reported
+        let (bp_x, bp_y) = dots_to_coordinates(dots).unwrap_or_default();
+        let pairs: HashSet<(usize, usize)> = bp_x.into_iter().zip(bp_y).collect();
+        let stacks: f64 = pairs
+            .iter()
+            .filter(|&&(i, j)| pairs.contains(&(i + 1, j - 1)))
+            .map(|&(i, j)| {
+                [i, j, i + 1, j - 1]
+                    .into_iter()
+                    .map(pseudo_energy)
+                    .sum::<f64>()
+            })
+            .sum();
+        *d_g as f64 + stacks
+    }
+
+    /// Boltzmann sum of `score` over the registered structures allowed by the constraints, whose
+    /// energies do not change with temp
+    fn ensemble(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        temp: f32,
+        score: impl Fn(&(f32, String)) -> f64,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        let rt = RT * (temp as f64 + KELVIN) / (DEFAULT_TEMP as f64 + KELVIN);
+        let length = sequences.iter().map(|s| s.len()).sum();
+        let folds = self.allowed_folds(sequences, constraints)?;
+        if folds.is_empty() {
+            return Ok(Ensemble::new(0.0, length, []));
+        }
+        let weights: Vec<f64> = folds
+            .iter()
+            .map(|fold| (-score(fold) / rt).exp())
+            .collect();
+        let z: f64 = weights.iter().sum();
+
+        let mut pairs = HashMap::new();
+        for ((_, dots), weight) in folds.iter().zip(&weights) {
+            let (bp_x, bp_y) = dots_to_coordinates(dots)?;
+            for (&i, &j) in bp_x.iter().zip(bp_y.iter()) {
+                *pairs.entry((i, j)).or_insert(0.0) += weight / z;
+            }
+        }
+        let pairs = pairs
+            .into_iter()
+            .map(|((i, j), probability)| (i, j, probability));
+        Ok(Ensemble::new((-rt * z.ln()) as f32, length, pairs))
+    }
+
+    /// Registered structures allowed by the constraints within `energy_gap` of the lowest score,
+    /// reported with their energies and sorted by them
+    fn subopt_folds<'a>(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        energy_gap: f32,
+        score: impl Fn(&(f32, String)) -> f64,
+    ) -> Vec<FoldResult<'a>> {
+        let folds = self.allowed_folds(sequences, constraints).unwrap_or_default();
+        let Some(lowest) = folds.iter().map(|fold| score(fold)).min_by(f64::total_cmp) else {
+            return vec![];
+        };
+        let mut folds: Vec<&(f32, String)> = folds
+            .into_iter()
+            .filter(|fold| score(fold) <= lowest + energy_gap as f64)
+            .collect();
+        folds.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        folds
+            .into_iter()
+            .filter_map(|(d_g, dots)| {
+                let (bp_x, bp_y) = dots_to_coordinates(dots).ok()?;
+                Some(FoldResult::create(None, *d_g, dots.clone(), bp_x, bp_y))
+            })
+            .filter(|fold| {
+                sequences.len() < 2 || fold.get_bp_y().iter().any(|&y| y > sequences[0].len())
+            })
+            .collect()
+    }
+}
+
+impl FoldingBackend for MockBackend {
+    fn mfe<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        let (d_g, dots) = match self.allowed_folds(sequences, constraints)?.first() {
+            Some((d_g, dots)) => (*d_g, dots.clone()),
+            None => (0.0, ".".repeat(sequences.iter().map(|s| s.len()).sum())),
+        };
+        let (bp_x, bp_y) = dots_to_coordinates(&dots)?;
+        Ok(FoldResult::create(Some(sequences), d_g, dots, bp_x, bp_y))
+    }
+
+    fn subopt<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        energy_gap: f32,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        self.subopt_folds(sequences, constraints, energy_gap, |(d_g, _)| *d_g as f64)
+    }
+
+    fn eval_structure(
+        &self,
+        sequences: &[&str],
+        dots: &str,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> f32 {
+        let dots = dots.replace('&', "");
+        match self.energies.get(&(Self::key(sequences), dots.clone())) {
+            Some(d_g) => *d_g,
+            None => -(dots.matches('(').count() as f32),
+        }
+    }
+
+    fn partition_function(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        self.ensemble(sequences, constraints, temp, |(d_g, _)| *d_g as f64)
+    }
+
+    fn mfe_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        // Registered structures are ranked with the pseudo-energies of their stacks
+        let pseudo_energies = shape_pseudo_energies(reactivities);
+        let score = |fold: &(f32, String)| Self::shape_score(&pseudo_energies, fold);
+        let ranked = self
+            .allowed_folds(sequences, constraints)?
+            .into_iter()
+            .min_by(|a, b| score(a).total_cmp(&score(b)));
+        let (d_g, dots) = match ranked {
+            Some((d_g, dots)) => (*d_g, dots.clone()),
+            None => (0.0, ".".repeat(sequences.iter().map(|s| s.len()).sum())),
+        };
+        let (bp_x, bp_y) = dots_to_coordinates(&dots)?;
+        Ok(FoldResult::create(Some(sequences), d_g, dots, bp_x, bp_y))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn subopt_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        energy_gap: f32,
+        _temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        let pseudo_energies = shape_pseudo_energies(reactivities);
+        self.subopt_folds(sequences, constraints, energy_gap, |fold| {
+            Self::shape_score(&pseudo_energies, fold)
+        })
+    }
+
+    fn partition_function_with_reactivities(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        temp: f32,
+        _salt: &SaltConditions,
+        _dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        let pseudo_energies = shape_pseudo_energies(reactivities);
+        self.ensemble(sequences, constraints, temp, |fold| {
+            Self::shape_score(&pseudo_energies, fold)
+        })
+    }
+
+    fn local_unpaired_probabilities(
+        &self,
+        sequence: &str,
+        _window: usize,
+        span: usize,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        // The registered structures of the whole sequence, without the pairs wider than the span
+        let ensemble = self.partition_function(&[sequence], "", temp, salt, dangles)?;
+        let unpaired = (1..=ensemble.len())
+            .map(|i| {
+                let paired: f64 = (1..=ensemble.len())
+                    .filter(|&j| i.abs_diff(j) < span)
+                    .map(|j| ensemble.pair_probability(i, j))
+                    .sum();
+                (1.0 - paired).clamp(0.0, 1.0)
+            })
+            .collect();
+        Ok(unpaired)
+    }
+}
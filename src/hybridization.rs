@@ -1,201 +1,246 @@
-fn calc_dg_mrna_rRNA(mrna_in: &str, rrna: &str, start_pos: usize, dangles: &DanglesSetting, constraints: &str) -> Result<bool, &str>{
-    ///Calculates the dG_mRNA_rRNA from the mRNA and rRNA sequence.
-    ///Considers all feasible 16S rRNA binding sites and includes the effects of non-optimal spacing."""
-
+use crate::calculations::{
+    calc_spacing_penalty, find_binding_position, fold_mrna, reactivity_window,
+};
+use crate::constants::*;
+use crate::folding::{coordinates_to_dots, FoldingBackend};
+use crate::types::{CoFoldResult, DanglesSetting, ModelParameters, MrnaState};
+use std::cmp::min;
+use std::error::Error;
+use std::ops::Range;
+
+/// The 16S rRNA binding site chosen for a start codon, with the complex refolded around it
+pub(crate) struct Hybridization<'a> {
+    pub fold: CoFoldResult<'a>,
+    pub dg_spacing: f64,
+    pub aligned_spacing: i64,
+    /// Boltzmann-weighted dG of all binding sites in the subopt band, each with its own dG_spacing.
+    /// Sites are scored with their subopt energies, before the refolding of the chosen site.
+    pub dg_ensemble: f64,
+    /// dG + dG_spacing of every binding site upstream of the start codon in the subopt band
+    pub site_energies: Vec<f64>,
+}
+
+///Calculates the dG_mRNA_rRNA from the mRNA and rRNA sequence.
+///Considers all feasible 16S rRNA binding sites and includes the effects of non-optimal spacing.
+///Reactivities of the whole mRNA, when measured, guide the folds around the binding site, where
+///nucleotides unwound by a terminating ribosome can not fold. They can still bind the rRNA.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_dg_mrna_rrna<'a>(
+    mrna_in: &'a str,
+    rrna: &'a str,
+    start_pos: usize,
+    dangles: &DanglesSetting,
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    params: &ModelParameters,
+    state: &MrnaState,
+    backend: &dyn FoldingBackend,
+) -> Result<Option<Hybridization<'a>>, Box<dyn Error>> {
     // Collect all constants
-
-    let begin = max(0, start_pos as isize - CUTOFF as isize) as usize;
-    let mrna_len = min(mRNA_in.len(), start_pos + CUTOFF);
-    let start_pos_in_subsequence = min(start_pos, CUTOFF);
+    let window = params.folding_window(start_pos, mrna_in.len());
+    let begin = window.start;
+    let mrna_len = window.end;
+    let start_pos_in_subsequence = start_pos - begin;
     let startpos_to_end_len = mrna_len - start_pos_in_subsequence - begin;
 
     // 1. identify a list of rRNA-binding sites. Binding sites are hybridizations between the mRNA and rRNA and can include mismatches, bulges, etc. Intra-molecular folding is also allowed within the mRNA. The subopt program is used to generate a list of optimal & suboptimal binding sites.
     // Constraints: the entire rRNA-binding site must be upstream of the start codon
 
-    let mRNA = &mRNA_in[begin..start_pos];
     if begin == start_pos {
-        return Err("Warning: There is a leaderless start codon, which is being ignored.");
+        return Err("Warning: There is a leaderless start codon, which is being ignored.".into());
     }
-    //include viennaRNA folding constraints due to binding of global regulator
-
+    let mrna = &mrna_in[begin..start_pos];
 
-    let constraints = if let Some(cons) = constraints {
-        if cons.len() >= mRNA.len() {
-            Some(cons[begin..start_pos].to_string()) // Slicing and converting to String
+    //include viennaRNA folding constraints due to binding of global regulator
+    //Constraints are empty or cover the whole mRNA, padded with dots
+    let segment_constraints = |segment: Range<usize>| {
+        if constraints.is_empty() {
+            ""
         } else {
-            let mut extended_constraints = cons.to_string();
-            extended_constraints.push_str(&".".repeat(mRNA.len() - cons.len())); // Fill the rest with dots
-            Some(extended_constraints)
+            &constraints[segment]
         }
-    } else {
-        None
     };
-    
-    let (subopt_energy, subopt_basepairing_x, subopt_basepairing_y) = subopt(
-        &[mRNA, rRNA],
-        constraints.as_deref(),
-        ENERGY_CUTOFF,
+
+    let strands = vec![mrna, rrna];
+    let subopt_folds = backend.subopt(
+        &strands,
+        segment_constraints(begin..start_pos),
+        params.energy_cutoff,
+        params.temperature,
+        &params.salt,
         dangles,
-        DEFAULT_TEMP,
     );
 
-    if subopt_basepairing_x.is_empty() {return Ok((None, None, None));}
-
+    if subopt_folds.is_empty() {
+        return Ok(None);
+    }
 
     // 2. Calculate dG_spacing for each 16S rRNA binding site
 
     // Calculate the aligned spacing for each binding site in the list
-    let mut aligned_spacing = Vec::new();
-    let loop_len = subopt_basepairing_x.len();
-    for i in 0..loop_len {
-        aligned_spacing.push(calc_aligned_spacing(
-            rRNA, 
-            mRNA, 
-            start_pos_in_subsequence, 
-            subopt_basepairing_x[i], 
-            subopt_basepairing_y[i]
-        ));
-    }
-    
-    let mut dG_spacing_list = Vec::new();
-    let mut dG_mRNA_rRNA = Vec::new();
-    let mut dG_mRNA_rRNA_withspacing = Vec::new();
-    
+    let aligned_spacing: Vec<Option<i64>> = subopt_folds
+        .iter()
+        .map(|fold| {
+            find_binding_position(
+                start_pos_in_subsequence,
+                mrna.len(),
+                rrna.len(),
+                fold.get_bp_x(),
+                fold.get_bp_y(),
+            )
+        })
+        .collect();
 
     // Calculate dG_spacing using aligned spacing value. Add it to dG_mRNA_rRNA.
-    for i in 0..loop_len {
-        dG_mRNA_rRNA.push(subopt_energy[i]);
-        let val = calc_dG_spacing(aligned_spacing[i]);
-        dG_spacing_list.push(val);
-        dG_mRNA_rRNA_withspacing.push(val + subopt_energy[i]);
+    let dg_spacing_list: Vec<f64> = aligned_spacing
+        .iter()
+        .map(|spacing| match spacing {
+            Some(spacing) => calc_spacing_penalty(*spacing, params),
+            None => f64::INFINITY,
+        })
+        .collect();
+
+    // 3. Find 16S rRNA binding site that minimizes dG_spacing+dG_mRNA_rRNA.
+    let mut index = 0;
+    let mut min_withspacing = f64::INFINITY;
+    for (i, fold) in subopt_folds.iter().enumerate() {
+        let withspacing = *fold.get_d_g() as f64 + dg_spacing_list[i];
+        if withspacing < min_withspacing {
+            min_withspacing = withspacing;
+            index = i;
+        }
     }
-    
-    // Find 16S rRNA binding site that minimizes dG_spacing+dG_mRNA_rRNA.
-    let (_, index) = find_min(&dG_mRNA_rRNA_withspacing);
-    let dG_spacing_final = dG_spacing_list[index];
-    let spacing_value = aligned_spacing[index];
-    
+    let dg_spacing_final = dg_spacing_list[index];
+
+    // Ensemble over all binding sites upstream of the start codon
+    let site_energies: Vec<f64> = subopt_folds
+        .iter()
+        .zip(dg_spacing_list.iter())
+        .filter(|(_, dg_spacing)| dg_spacing.is_finite())
+        .map(|(fold, dg_spacing)| *fold.get_d_g() as f64 + dg_spacing)
+        .collect();
+    let rt = params.rt();
+    let z: f64 = site_energies.iter().map(|d_g| (-d_g / rt).exp()).sum();
+    let dg_ensemble = -rt * z.ln();
+    let spacing_value = match aligned_spacing[index] {
+        Some(spacing) => spacing,
+        None => return Ok(None), // None of the binding sites are upstream of the start codon
+    };
+
     // Check: Is the dG spacing large compared to the energy gap? If so, this means the list of suboptimal 16S rRNA binding sites generated by subopt is too short.
-    if dG_spacing_final > ENERGY_CUTOFF {
-        if ostir_constants.verbose {
-            println!("Warning: The spacing penalty is greater than the energy gap. dG (spacing) = {}", dG_spacing_final);
-        }
+    if dg_spacing_final > params.energy_cutoff as f64 && VERBOSE {
+        println!(
+            "Warning: The spacing penalty is greater than the energy gap. dG (spacing) = {}",
+            dg_spacing_final
+        );
     }
-    // 4. Identify the 5' and 3' ends of the identified 16S rRNA binding site. Create a base pair list.
 
+    // 4. Identify the 5' and 3' ends of the identified 16S rRNA binding site. Create a base pair list.
+    let bp_x = subopt_folds[index].get_bp_x();
+    let bp_y = subopt_folds[index].get_bp_y();
 
+    let mut most_5p_mrna = usize::MAX;
 
-    let mut most_5p_mRNA = ostir_constants.infinity;
-    let mut most_3p_mRNA = -ostir_constants.infinity;
-    
     // Generate a list of rRNA-mRNA base paired nucleotides
     let mut bp_x_target = Vec::new();
     let mut bp_y_target = Vec::new();
-    
-    let bp_x = &subopt_basepairing_x[index];
-    let bp_y = &subopt_basepairing_y[index];
-    let loop_len = bp_x.len();
-    
-    for i in 0..loop_len {
-        let nt_x = bp_x[i];
-        let nt_y = bp_y[i];
-        if nt_y > mRNA.len() { // nt is rRNA
-            if let Some(index_match) = bp_y.iter().position(|&x| x == nt_y) {
-                most_5p_mRNA = most_5p_mRNA.min(bp_x[index_match]);
-                most_3p_mRNA = most_3p_mRNA.max(bp_x[index_match]);
-                bp_x_target.push(nt_x);
-                bp_y_target.push(nt_y);
-            }
+    for (&nt_x, &nt_y) in bp_x.iter().zip(bp_y.iter()) {
+        if nt_y > mrna.len() {
+            // nt is rRNA
+            most_5p_mrna = most_5p_mrna.min(nt_x);
+            bp_x_target.push(nt_x);
+            bp_y_target.push(nt_y);
         }
     }
-    
-    //The rRNA-binding site is between the nucleotides at positions most_5p_mRNA and most_3p_mRNA
-    //Now, fold the pre-sequence, rRNA-binding-sequence and post-sequence separately. 
-    //Take their base pairings and combine them together. Calculate the total energy. 
+
+    //The rRNA-binding site is between the nucleotides at positions most_5p_mrna and most_3p_mrna
+    //Now, fold the pre-sequence, rRNA-binding-sequence and post-sequence separately.
+    //Take their base pairings and combine them together. Calculate the total energy.
     //For secondary structures, this splitting operation is allowed.
-    //We postulate that not all of the post-sequence can form secondary structures. 
-    //Once the 30S complex binds to the mRNA, it prevents the formation of secondary 
-    //structures that are mutually exclusive with ribosome binding. We define self.footprint 
-    //to be the length of the 30S complex footprint. Here, we assume that the entire mRNA 
+    //We postulate that not all of the post-sequence can form secondary structures.
+    //Once the 30S complex binds to the mRNA, it prevents the formation of secondary
+    //structures that are mutually exclusive with ribosome binding. We define the footprint
+    //to be the length of the 30S complex footprint. Here, we assume that the entire mRNA
     //sequence downstream of the 16S rRNA binding site can not form secondary structures.
-    
-
-
-    let begin = begin as usize;
-    let most_5p_mRNA = most_5p_mRNA as usize;
-    let most_3p_mRNA = most_3p_mRNA as usize;
-
-    let mRNA_pre = &mrna_in[begin..begin + most_5p_mRNA - 1];
-    let post_window_end = mrna_len + 1;
-    let post_window_begin = std::cmp::min(start_pos + ostir_constants.footprint, post_window_end); // Footprint
-    let post_window_end = mrna_len + 1;
-    let mRNA_post = &mRNA_in[post_window_begin..post_window_end];
-
-    let mut total_bp_x = Vec::new();
-    let mut total_bp_y = Vec::new();
-
-    let pre_constraints;
-    let post_constraints;
-    if let Some(constraints) = constraints {
-        pre_constraints = Some(&constraints[begin..begin + most_5p_mRNA - 1]);
-        post_constraints = Some(&constraints[post_window_begin..post_window_end]);
-    } else {
-        pre_constraints = None;
-        post_constraints = None;
-    }
+
+    let pre_window_end = begin + most_5p_mrna - 1;
+    let mrna_pre = &mrna_in[begin..pre_window_end];
+    let post_window_end = mrna_len;
+    let post_window_begin = min(start_pos + params.footprint, post_window_end); // Footprint
+    let mrna_post = &mrna_in[post_window_begin..post_window_end];
+
+    let mut total_bp_x: Vec<usize> = Vec::new();
+    let mut total_bp_y: Vec<usize> = Vec::new();
 
     // Calculate pre-sequence folding
-    if mRNA_pre.len() > 0 {
-        let (mfe_basepairing_x, mfe_basepairing_y, _) = mfe(
-            &[mRNA_pre],
-            pre_constraints,
-            DEFAULT_TEMP,
+    if !mrna_pre.is_empty() {
+        let pre_strands = vec![mrna_pre];
+        let pre_window = begin..pre_window_end;
+        let pre_constraints = segment_constraints(pre_window.clone());
+        let pre_constraints = state.unwound_constraints(pre_constraints, pre_window);
+        let pre_reactivities = reactivity_window(reactivities, begin..pre_window_end);
+        let pre_fold = fold_mrna(
+            &pre_strands,
+            &pre_constraints,
+            &pre_reactivities,
             dangles,
-        );
-        let bp_x_pre = mfe_basepairing_x;
-        let bp_y_pre = mfe_basepairing_y;
-    
+            params,
+            state,
+            backend,
+        )?;
+
         // Add pre-sequence base pairings to total base pairings
-        total_bp_x.extend(bp_x_pre.iter().copied());
-        total_bp_y.extend(bp_y_pre.iter().copied());
-    } else {
-        let bp_x_pre = Vec::new();
-        let bp_y_pre = Vec::new();
+        total_bp_x.extend(pre_fold.get_bp_x());
+        total_bp_y.extend(pre_fold.get_bp_y());
     }
-    
+
     // Add rRNA-binding site base pairings to total base pairings
-    let rRNA_offset = std::cmp::min(startpos_to_end_len, cutoff);
+    let rrna_offset = startpos_to_end_len;
     total_bp_x.extend(bp_x_target.iter().copied());
-    for nt_y in &bp_y_target {
-        total_bp_y.push(*nt_y + rRNA_offset);
-    }
-    
+    total_bp_y.extend(bp_y_target.iter().map(|nt_y| nt_y + rrna_offset));
+
     // Calculate post-sequence folding
-    if mRNA_post.len() > 0 {
-        let (mfe_basepairing_x, mfe_basepairing_y, _) = mfe(
-            &[mRNA_post],
-            post_constraints,
-            DEFAULT_TEMP,
+    if !mrna_post.is_empty() {
+        let post_strands = vec![mrna_post];
+        let post_window = post_window_begin..post_window_end;
+        let post_constraints = segment_constraints(post_window.clone());
+        let post_constraints = state.unwound_constraints(post_constraints, post_window.clone());
+        let post_reactivities = reactivity_window(reactivities, post_window);
+        let post_fold = fold_mrna(
+            &post_strands,
+            &post_constraints,
+            &post_reactivities,
             dangles,
-        );
-        let bp_x_post = mfe_basepairing_x;
-        let bp_y_post = mfe_basepairing_y;
-    
-        let mut offset = post_window_begin - begin;
-        for (&nt_x, &nt_y) in bp_x_post.iter().zip(bp_y_post.iter()) {
+            params,
+            state,
+            backend,
+        )?;
+
+        let offset = post_window_begin - begin;
+        for (&nt_x, &nt_y) in post_fold.get_bp_x().iter().zip(post_fold.get_bp_y().iter()) {
             total_bp_x.push(nt_x + offset);
             total_bp_y.push(nt_y + offset);
         }
-    } else {
-        let bp_x_post = Vec::new();
-        let bp_y_post = Vec::new();
     }
 
-    let mRNA = &mRNA_in[begin..mRNA_len];
-    
-    // @TODO: Change to dots
-    let total_energy = eval_structure(&[mRNA, rRNA], &total_bp_x, &total_bp_y, DEFAULT_TEMP, dangles);
-    
-    let total_energy_withspacing = total_energy + dG_spacing_final;
-    
-}
\ No newline at end of file
+    let mrna = &mrna_in[begin..mrna_len];
+    let strands = vec![mrna, rrna];
+    let dots = coordinates_to_dots(&strands, &total_bp_x, &total_bp_y);
+    let total_energy =
+        backend.eval_structure(&strands, &dots, params.temperature, &params.salt, dangles)
+            + state.ligand_energy(mrna, &dots) as f32;
+
+    Ok(Some(Hybridization {
+        fold: CoFoldResult {
+            seqs: (mrna, rrna),
+            d_g: total_energy,
+            dots,
+            bp_x: total_bp_x,
+            bp_y: total_bp_y,
+        },
+        dg_spacing: dg_spacing_final,
+        aligned_spacing: spacing_value,
+        dg_ensemble,
+        site_energies,
+    }))
+}
@@ -0,0 +1,58 @@
+//! OSTIR (Open Source Translation Initiation Rates) predicts the rates at which ribosomes
+//! bind to and initiate translation from the start codons of bacterial mRNAs.
+//!
+//! ```no_run
+//! use ostir::{DNASequence, Ostir};
+//!
+//! let ostir = Ostir::builder().asd("ACCTCCTTA").threads(4).build().unwrap();
+//! let sequence = DNASequence::new("ACUUCUAAUUUAUUCUAUUUAUUCGCGGAUAUGCAUAGGAGUGCUUCGAUGUCAU".to_string(), 1, false).unwrap();
+//! for prediction in ostir.predict(&sequence).unwrap() {
+//!     println!("{} {} {}", prediction.start_codon, prediction.start_position, prediction.expression);
+//! }
+//! ```
+mod calculations;
+pub mod constants;
+mod factory;
+pub mod ffi;
+pub mod file_parser;
+pub mod folding;
+mod hybridization;
+mod operon;
+mod ribosomes;
+mod riboswitch;
+mod rrna;
+mod thermometer;
+mod toehold;
+#[cfg(feature = "turner")]
+mod turner;
+pub mod types;
+#[cfg(feature = "vienna")]
+mod vienna_wrapper;
+#[cfg(feature = "vienna")]
+extern crate openmp_sys;
+
+#[cfg(not(any(feature = "vienna", feature = "turner")))]
+compile_error!("OSTIR needs a folding backend: enable the `vienna` or `turner` feature");
+
+pub use factory::{
+    predictions_to_dataframe, Ostir, OstirBuilder, Prediction, PredictionIter, TemperatureSweep,
+};
+pub use file_parser::fileparser;
+pub use file_parser::fileparser::DNASequence;
+pub use folding::FoldingBackend;
+#[cfg(any(test, feature = "mock"))]
+pub use folding::MockBackend;
+pub use operon::{operons_to_dataframe, CoupledPrediction};
+pub use ribosomes::{cross_talk_to_dataframe, pools_to_dataframe, PoolPrediction};
+pub use riboswitch::{riboswitches_to_dataframe, RiboswitchPrediction};
+pub use rrna::asds_from_16s;
+pub use thermometer::{thermometers_to_dataframe, ThermometerPrediction};
+pub use toehold::{switches_to_dataframe, SwitchPrediction};
+#[cfg(feature = "turner")]
+pub use turner::TurnerBackend;
+pub use types::{
+    asd_mismatches, asd_preset, Aptamer, ContextTable, DanglesSetting, Ensemble, ModelParameters,
+    SaltConditions,
+};
+#[cfg(feature = "vienna")]
+pub use vienna_wrapper::{FoldCompound, ViennaBackend};
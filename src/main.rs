@@ -1,117 +1,720 @@
-mod calculations;
-mod constants;
-mod file_parser;
-mod types;
-pub use file_parser::fileparser;
-pub use file_parser::fileparser::DNASequence;
 use indicatif::ProgressBar;
-mod vienna_wrapper;
+use ostir::constants::{
+    ASD_MAX_MISMATCHES, CUTOFF, DECIMAL_PLACES, ECOLI_ASD, THERMOMETER_FOLD_CHANGE,
+};
+use ostir::{
+    asd_mismatches, asds_from_16s, cross_talk_to_dataframe, fileparser, operons_to_dataframe,
+    pools_to_dataframe, predictions_to_dataframe, riboswitches_to_dataframe, switches_to_dataframe,
+    thermometers_to_dataframe, Aptamer, CoupledPrediction, DNASequence, Ostir, PoolPrediction,
+    Prediction, RiboswitchPrediction, SaltConditions, SwitchPrediction, ThermometerPrediction,
+};
 use polars::prelude::*;
-use vienna_wrapper::{mfe, subopt};
-extern crate openmp_sys;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+const USAGE: &str = "Usage: ostir -i <sequence or file> [options]
+
+Options:
+  -i, --input <INPUT>                 Input sequence or FASTA file
+  -o, --output <OUTPUT>               Output CSV file (printed to the console if omitted)
+  -s, --start <START>                 Most 5' nucleotide position to consider a start codon beginning (1-indexed)
+  -e, --end <END>                     Most 3' nucleotide position to consider a start codon beginning (1-indexed)
+  -a, --anti-Shine-Dalgarno <ASD>     anti-Shine-Dalgarno sequence: the 9 bp at the 3' end of the 16S rRNA
+      --organism <NAME>               Use the anti-Shine-Dalgarno sequence of a preset host, e.g. B. subtilis
+      --asd-from-16S                  Use the 3' end of the 16S rRNA annotated in or found in the input as the aSD
+  -t, --type <TYPE>                   Input type: string, fasta or genbank (detected if omitted)
+  -c, --circular                      Treat the input sequences as circular
+      --upstream <NT>                 Nucleotides folded upstream of the start codon [default: 35]
+      --downstream <NT>               Nucleotides folded from the start codon on [default: 35]
+      --full-transcript               Fold the whole transcript instead of a window around each start codon
+      --footprint <NT>                Nucleotides from the start codon on kept unfolded by the ribosome [default: 1000]
+  -T, --temperature <TEMPERATURE>     Folding temperature in C [default: 37]
+      --temperature-sweep <FROM:TO:STEP>
+                                      Predict at every temperature from FROM to TO (in C)
+      --monovalent <CONCENTRATION>    Monovalent salt (Na+, K+) in mol/L [default: 1.021]
+      --magnesium <CONCENTRATION>     Mg2+ in mol/L [default: 0]
+      --thermometer <LOW:HIGH>        Flag RNA thermometers by comparing two temperatures (in C)
+      --min-fold-change <FOLD>        Expression change that flags a thermometer [default: 4]
+      --aptamer <SEQ:STRUCTURE:DG>    Compare expression without and with the ligand of a riboswitch aptamer
+      --trigger <SEQUENCE>            Evaluate the inputs as toehold switches, OFF and ON with this trigger RNA
+      --cds <START..END,...>          Upstream CDS boundaries (1-indexed, through the stop codon) for translational coupling
+      --asd-pool <ASD:ABUNDANCE,...>  Predict with a pool of ribosomes, reporting each aSD and the abundance-weighted expression
+      --cross-talk <ASD,...>          Predict with each aSD as a cross-talk matrix for choosing orthogonal RBS/rRNA pairs
+      --srna <SEQUENCE>               Co-fold a trans-acting sRNA and report its occlusion of the SD and start codon
+      --shape <FILE>                  SHAPE reactivities (.shape) guiding dG_mRNA and dG_standby of a single input
+      --context <FILE>                Start codon context energies (position A C G T per line) added to dG_total
+  -p, --print-sequence                Include the input mRNA sequence in the output
+  -q, --print-anti-Shine-Dalgarno     Include the anti-Shine-Dalgarno sequence in the output
+      --accessibility                 Report the ensemble accessibility of the SD and start codon
+      --ensemble                      Also report ensemble-averaged dG_mRNA and dG_rRNA:mRNA
+      --uncertainty                   Report a low/median/high expression interval from subopt structures
+      --competition                   Share initiation among start codons within 10 nt of each other
+      --cotranscriptional <RATE>      Also report dG_mRNA when the ribosome loads on a transcript made at RATE nt/s
+      --loading-time <SECONDS>        Time from transcription of the start codon to ribosome loading [default: 1]
+      --local-folding                 Fold whole records locally (RNAplfold-style) and report the accessibility of each start codon
+      --local-window <W:L>            Window length and maximum base pair span of local folding [default: 240:160]
+      --kinetics                      Report the kinetic score and flag mRNAs that may fold slowly
+      --max-kinetic-score <SCORE>     Kinetic score above which a prediction is flagged [default: 0.8]
+      --exclude-slow-folding          Leave out predictions flagged as slow folding (implies --kinetics)
+  -j, --threads <THREADS>             Number of threads for multiprocessing
+  -v, --verbosity <VERBOSITY>         Show a progress bar when greater than 0
+  -h, --help                          Print this message";
+
+struct Arguments {
+    input: String,
+    output: Option<String>,
+    start: Option<usize>,
+    end: Option<usize>,
+    asd: String,
+    organism: Option<String>,
+    asd_from_16s: bool,
+    input_type: Option<String>,
+    circular: bool,
+    upstream: Option<usize>,
+    downstream: Option<usize>,
+    full_transcript: bool,
+    footprint: Option<usize>,
+    temperature: Option<f32>,
+    temperature_sweep: Option<Vec<f32>>,
+    monovalent: Option<f64>,
+    magnesium: Option<f64>,
+    thermometer: Option<(f32, f32)>,
+    min_fold_change: f64,
+    aptamer: Option<Aptamer>,
+    trigger: Option<String>,
+    cds: Option<Vec<(usize, usize)>>,
+    asd_pool: Option<Vec<(String, f64)>>,
+    cross_talk: Option<Vec<String>>,
+    srna: Option<String>,
+    shape: Option<String>,
+    context: Option<String>,
+    print_sequence: bool,
+    print_asd: bool,
+    accessibility: bool,
+    ensemble: bool,
+    uncertainty: bool,
+    competition: bool,
+    cotranscriptional: Option<f64>,
+    loading_time: Option<f64>,
+    local_folding: bool,
+    local_window: Option<(usize, usize)>,
+    kinetics: bool,
+    max_kinetic_score: Option<f64>,
+    exclude_slow_folding: bool,
+    threads: usize,
+    verbosity: i32,
+}
 
 fn main() {
-    println!("Hello, world!");
-    let sequences = fileparser::parse_file("test.fasta", 20).unwrap();
+    let arguments = match parse_arguments(std::env::args().skip(1).collect()) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    for sequence in sequences {
-        for _next in sequence {
-            //println!("{}", next.sequence);
+    if let Err(e) = run(arguments) {
+        eprintln!("ERROR: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_arguments(args: Vec<String>) -> Result<Arguments, Box<dyn Error>> {
+    let mut arguments = Arguments {
+        input: String::new(),
+        output: None,
+        start: None,
+        end: None,
+        asd: ECOLI_ASD.to_string(),
+        organism: None,
+        asd_from_16s: false,
+        input_type: None,
+        circular: false,
+        upstream: None,
+        downstream: None,
+        full_transcript: false,
+        footprint: None,
+        temperature: None,
+        temperature_sweep: None,
+        monovalent: None,
+        magnesium: None,
+        thermometer: None,
+        min_fold_change: THERMOMETER_FOLD_CHANGE,
+        aptamer: None,
+        trigger: None,
+        cds: None,
+        asd_pool: None,
+        cross_talk: None,
+        srna: None,
+        shape: None,
+        context: None,
+        print_sequence: false,
+        print_asd: false,
+        accessibility: false,
+        ensemble: false,
+        uncertainty: false,
+        competition: false,
+        cotranscriptional: None,
+        loading_time: None,
+        local_folding: false,
+        local_window: None,
+        kinetics: false,
+        max_kinetic_score: None,
+        exclude_slow_folding: false,
+        threads: 1,
+        verbosity: 0,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", flag));
+        match flag.as_str() {
+            "-i" | "--input" => arguments.input = value()?,
+            "-o" | "--output" => arguments.output = Some(value()?),
+            "-s" | "--start" => arguments.start = Some(value()?.parse()?),
+            "-e" | "--end" => arguments.end = Some(value()?.parse()?),
+            "-a" | "--anti-Shine-Dalgarno" => arguments.asd = value()?,
+            "--organism" => arguments.organism = Some(value()?),
+            "--asd-from-16S" => arguments.asd_from_16s = true,
+            "-t" | "--type" => arguments.input_type = Some(value()?),
+            "-j" | "--threads" => arguments.threads = value()?.parse()?,
+            "-v" | "--verbosity" => arguments.verbosity = value()?.parse()?,
+            "-T" | "--temperature" => arguments.temperature = Some(value()?.parse()?),
+            "--temperature-sweep" => {
+                arguments.temperature_sweep = Some(parse_temperature_sweep(&value()?)?)
+            }
+            "--monovalent" => arguments.monovalent = Some(value()?.parse()?),
+            "--magnesium" => arguments.magnesium = Some(value()?.parse()?),
+            "--thermometer" => arguments.thermometer = Some(parse_thermometer(&value()?)?),
+            "--min-fold-change" => arguments.min_fold_change = value()?.parse()?,
+            "--aptamer" => arguments.aptamer = Some(parse_aptamer(&value()?)?),
+            "--trigger" => arguments.trigger = Some(value()?),
+            "--cds" => arguments.cds = Some(parse_cds(&value()?)?),
+            "--asd-pool" => arguments.asd_pool = Some(parse_asd_pool(&value()?)?),
+            "--cross-talk" => {
+                arguments.cross_talk = Some(
+                    value()?
+                        .split(',')
+                        .map(|asd| asd.trim().to_string())
+                        .collect(),
+                )
+            }
+            "--srna" => arguments.srna = Some(value()?),
+            "--shape" => arguments.shape = Some(value()?),
+            "--context" => arguments.context = Some(value()?),
+            "-c" | "--circular" => arguments.circular = true,
+            "--upstream" => arguments.upstream = Some(value()?.parse()?),
+            "--downstream" => arguments.downstream = Some(value()?.parse()?),
+            "--full-transcript" => arguments.full_transcript = true,
+            "--footprint" => arguments.footprint = Some(value()?.parse()?),
+            "-p" | "--print-sequence" => arguments.print_sequence = true,
+            "-q" | "--print-anti-Shine-Dalgarno" => arguments.print_asd = true,
+            "--accessibility" => arguments.accessibility = true,
+            "--ensemble" => arguments.ensemble = true,
+            "--uncertainty" => arguments.uncertainty = true,
+            "--competition" => arguments.competition = true,
+            "--cotranscriptional" => arguments.cotranscriptional = Some(value()?.parse()?),
+            "--loading-time" => arguments.loading_time = Some(value()?.parse()?),
+            "--local-folding" => arguments.local_folding = true,
+            "--local-window" => arguments.local_window = Some(parse_local_window(&value()?)?),
+            "--kinetics" => arguments.kinetics = true,
+            "--max-kinetic-score" => arguments.max_kinetic_score = Some(value()?.parse()?),
+            "--exclude-slow-folding" => arguments.exclude_slow_folding = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unrecognized argument: {}", flag).into()),
         }
     }
 
-    let test_seq1 = "TTATGGCGAGCTCTGAAGACGTTATCAAAGAGTTCATGCGTTTCAAAGTTCGTATGGAAGGT";
-    let _test_seq2 = "TTATGGCGAGCTCTGAAGACGTTATCAAAGAGTTCAT";
-    let _test_seq_vec1 = vec![test_seq1];
-    let _test_seq_vec2 = vec![test_seq1, _test_seq2];
-    test_mfe(&_test_seq_vec1);
-    test_subopt(&_test_seq_vec2);
+    if arguments.input.is_empty() {
+        return Err("An input (-i) is required".into());
+    }
+    Ok(arguments)
+}
+
+/// Temperatures from FROM to TO (inclusive) in steps of STEP
+fn parse_temperature_sweep(sweep: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+    let bounds: Vec<f32> = sweep
+        .split(':')
+        .map(|bound| bound.trim().parse())
+        .collect::<Result<_, _>>()?;
+    let [from, to, step] = bounds[..] else {
+        return Err("The temperature sweep must be given as FROM:TO:STEP".into());
+    };
+    if step <= 0.0 || to < from {
+        return Err("The temperature sweep needs FROM <= TO and a positive STEP".into());
+    }
+    let steps = ((to - from) / step + 1e-4).floor() as usize;
+    Ok((0..=steps).map(|i| from + i as f32 * step).collect())
 }
 
-fn ostir(
-    sequence: DNASequence,
-    start: i64,
-    end: i64,
-    name: &str,
-    asd: &str,
-    circular: bool,
-    threads: i32,
-    bidirectional: bool,
-    verbosity: i32,
-) {
-    // Get start codon positions
-    let mut start_codon_positions: Vec<(usize, &str)> = vec![];
-    let start_codons: Vec<String> = vec![
-        "ATG".to_string(),
-        "GTG".to_string(),
-        "TTG".to_string(),
-        "CTG".to_string(),
-        "AUG".to_string(),
-        "GUG".to_string(),
-        "UUG".to_string(),
-        "CUG".to_string(),
+/// The LOW and HIGH temperatures of a thermometer screen
+fn parse_thermometer(temperatures: &str) -> Result<(f32, f32), Box<dyn Error>> {
+    match temperatures.split_once(':') {
+        Some((low, high)) => Ok((low.trim().parse()?, high.trim().parse()?)),
+        None => Err("The thermometer temperatures must be given as LOW:HIGH")?,
+    }
+}
+
+/// Window length and maximum base pair span of local folding, given as W:L
+fn parse_local_window(window: &str) -> Result<(usize, usize), Box<dyn Error>> {
+    match window.split_once(':') {
+        Some((window, span)) => Ok((window.trim().parse()?, span.trim().parse()?)),
+        None => Err("The local folding window must be given as W:L")?,
+    }
+}
+
+/// Coding sequences given as START..END pairs separated by commas
+fn parse_cds(cds: &str) -> Result<Vec<(usize, usize)>, Box<dyn Error>> {
+    cds.split(',')
+        .map(|boundaries| match boundaries.split_once("..") {
+            Some((start, end)) => Ok((start.trim().parse()?, end.trim().parse()?)),
+            None => Err("Coding sequences must be given as START..END")?,
+        })
+        .collect()
+}
+
+/// Ribosome pool given as ASD:ABUNDANCE pairs separated by commas
+fn parse_asd_pool(pool: &str) -> Result<Vec<(String, f64)>, Box<dyn Error>> {
+    pool.split(',')
+        .map(|rrna| match rrna.split_once(':') {
+            Some((asd, abundance)) => Ok((asd.trim().to_string(), abundance.trim().parse()?)),
+            None => Err("The ribosome pool must be given as ASD:ABUNDANCE pairs")?,
+        })
+        .collect()
+}
+
+/// Aptamer motif given as SEQUENCE:STRUCTURE:BINDING_ENERGY
+fn parse_aptamer(aptamer: &str) -> Result<Aptamer, Box<dyn Error>> {
+    let fields: Vec<&str> = aptamer.split(':').map(|field| field.trim()).collect();
+    let [sequence, structure, binding_energy] = fields[..] else {
+        return Err("The aptamer must be given as SEQUENCE:STRUCTURE:BINDING_ENERGY".into());
+    };
+    Ok(Aptamer::new(sequence, structure, binding_energy.parse()?)?)
+}
+
+/// aSD shared by most 16S rRNA genes of the input, with a warning when rrn operons disagree
+fn asd_from_16s(sequences: &[DNASequence]) -> Option<String> {
+    let mut counts: Vec<(String, usize)> = vec![];
+    for asd in sequences.iter().flat_map(asds_from_16s) {
+        match counts.iter_mut().find(|(other, _)| *other == asd) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((asd, 1)),
+        }
+    }
+    // Stable, so ties go to the first 16S rRNA
+    counts.sort_by_key(|count| std::cmp::Reverse(count.1));
+
+    match &counts[..] {
+        [] => {
+            eprintln!("WARNING: no 16S rRNA found in the input; keeping the given aSD");
+            None
+        }
+        [(asd, _)] => Some(asd.clone()),
+        [(asd, _), ..] => {
+            let found: Vec<String> = counts
+                .iter()
+                .map(|(asd, count)| format!("{} ({}x)", asd, count))
+                .collect();
+            eprintln!(
+                "WARNING: the 16S rRNA genes end in different anti-Shine-Dalgarno sequences: {}; \
+                 using {}",
+                found.join(", "),
+                asd
+            );
+            Some(asd.clone())
+        }
+    }
+}
+
+fn run(arguments: Arguments) -> Result<(), Box<dyn Error>> {
+    let input_type = match &arguments.input_type {
+        Some(input_type) => input_type.to_lowercase(),
+        None if Path::new(&arguments.input).is_file() => fileparser::file_type(&arguments.input)
+            .unwrap_or("fasta")
+            .to_string(),
+        None => "string".to_string(),
+    };
+
+    let mut sequences = match input_type.as_str() {
+        "fasta" | "genbank" => fileparser::parse_file_as(&arguments.input, &input_type, 1)?,
+        "string" => vec![DNASequence::new(
+            arguments.input.replace(" ", ""),
+            1,
+            arguments.circular,
+        )?],
+        _ => return Err(format!("Unknown input type: {}", input_type).into()),
+    };
+
+    // A reactivity profile describes one molecule
+    if let Some(shape) = &arguments.shape {
+        let [sequence] = &mut sequences[..] else {
+            return Err("SHAPE reactivities (--shape) need a single input sequence".into());
+        };
+        sequence.reactivities = fileparser::parse_shape_file(shape)?;
+    }
+
+    let derived_asd = if arguments.asd_from_16s {
+        asd_from_16s(&sequences)
+    } else {
+        None
+    };
+
+    // Without an end, a start position only considers the start codon at that position
+    let mut builder = Ostir::builder()
+        .asd(&arguments.asd)
+        .threads(arguments.threads)
+        .circular(arguments.circular)
+        .accessibility(arguments.accessibility)
+        .ensemble(arguments.ensemble)
+        .uncertainty(arguments.uncertainty)
+        .local_folding(arguments.local_folding || arguments.local_window.is_some())
+        .cotranscriptional(arguments.cotranscriptional.is_some())
+        .competition(arguments.competition)
+        .kinetics(arguments.kinetics || arguments.exclude_slow_folding);
+    if arguments.start.is_some() || arguments.end.is_some() {
+        let start = arguments.start.unwrap_or(1);
+        builder = builder.range(start, arguments.end.unwrap_or(start));
+    }
+    if arguments.upstream.is_some() || arguments.downstream.is_some() {
+        builder = builder.folding_window(
+            arguments.upstream.unwrap_or(CUTOFF),
+            arguments.downstream.unwrap_or(CUTOFF),
+        );
+    }
+    if let Some(elongation_rate) = arguments.cotranscriptional {
+        builder = builder.elongation_rate(elongation_rate);
+    }
+    if let Some(loading_time) = arguments.loading_time {
+        builder = builder.loading_time(loading_time);
+    }
+    if let Some((window, span)) = arguments.local_window {
+        builder = builder.local_window(window, span);
+    }
+    if let Some(footprint) = arguments.footprint {
+        builder = builder.footprint(footprint);
+    }
+    builder = builder.full_transcript(arguments.full_transcript);
+    if let Some(max_kinetic_score) = arguments.max_kinetic_score {
+        builder = builder.max_kinetic_score(max_kinetic_score);
+    }
+    match derived_asd {
+        Some(asd) => builder = builder.asd(&asd),
+        None => {
+            if let Some(organism) = &arguments.organism {
+                builder = builder.organism(organism);
+            }
+        }
+    }
+    if let Some(srna) = &arguments.srna {
+        builder = builder.srna(srna);
+    }
+    if let Some(context) = &arguments.context {
+        builder = builder.context(fileparser::parse_context_file(context)?);
+    }
+    if let Some(temperature) = arguments.temperature {
+        builder = builder.temperature(temperature);
+    }
+    if arguments.monovalent.is_some() || arguments.magnesium.is_some() {
+        let standard = SaltConditions::default();
+        builder = builder.salt(SaltConditions::new(
+            arguments.monovalent.unwrap_or(standard.monovalent),
+            arguments.magnesium.unwrap_or(standard.magnesium),
+        )?);
+    }
+    let ostir = builder.build()?;
+    let mismatches = asd_mismatches(ostir.asd());
+    if mismatches > ASD_MAX_MISMATCHES {
+        eprintln!(
+            "WARNING: the anti-Shine-Dalgarno sequence {} differs from the 16S rRNA 3' end of \
+             every preset host in at least {} nt",
+            ostir.asd(),
+            mismatches
+        );
+    }
+
+    let bar = if arguments.verbosity > 0 {
+        ProgressBar::new(sequences.len() as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+
+    let modes = [
+        arguments.thermometer.is_some(),
+        arguments.aptamer.is_some(),
+        arguments.trigger.is_some(),
+        arguments.cds.is_some(),
+        arguments.asd_pool.is_some(),
+        arguments.cross_talk.is_some(),
     ];
-    let tgt_sequence = &sequence.record;
-    for start_codon in start_codons {
-        let mut result: Vec<_> = tgt_sequence.match_indices(&start_codon).collect();
-        start_codon_positions.append(&mut result);
-    }
-
-    // Create Dataframe
-    let (_position, _codon): (Vec<usize>, Vec<&str>) =
-        start_codon_positions.iter().cloned().unzip();
-    let _df: DataFrame = df!(
-        "start_base" => Vec::<i64>::new(),
-        "start_codon" => Vec::<&str>::new(),
-    )
-    .unwrap();
-
-    // Set up progress bar
-    let _bar = ProgressBar::new(start_codon_positions.len().try_into().unwrap());
-
-    // Run calculations
-
-    // Return results
-}
-
-fn test_mfe(test_seq_vec: &Vec<&str>) {
-    // test mfe
-    println!("MFE");
-
-    let mfe = mfe(
-        test_seq_vec,
-        "placeholder",
-        37.0,
-        &types::DanglesSetting::new("all").unwrap(),
-    )
-    .unwrap();
-    // print mfe result as f32 and as string
-    println!("{}", mfe.get_d_g());
-    println!("{}", mfe.get_dots());
-    println!("{:?}", mfe.get_bp_x());
-    println!("{:?}", mfe.get_bp_y());
-}
-
-fn test_subopt(test_seq_vec: &Vec<&str>) {
-    // test subopt
-    println!("Subopt");
-
-    let subopt = subopt(
-        test_seq_vec,
-        "placeholder",
-        50.0,
-        37.0,
-        &types::DanglesSetting::new("all").unwrap(),
-    );
-
-    // print subopt result
-    for result in subopt {
-        let dg = result.get_d_g();
-        let bp_x = result.get_bp_x();
-        let bp_y = result.get_bp_y();
-        println!("{}", dg);
-        println!("{}", result.get_dots());
-        println!("{:?}", bp_x);
-        println!("{:?}", bp_y);
+    if modes.iter().filter(|&&mode| mode).count() > 1 {
+        return Err(
+            "Only one of --thermometer, --aptamer, --trigger, --cds, --asd-pool and \
+                    --cross-talk can be used"
+                .into(),
+        );
+    }
+    if arguments.temperature_sweep.is_some() && modes.contains(&true) {
+        return Err(
+            "--temperature-sweep cannot be combined with --thermometer, --aptamer, --trigger, \
+                    --cds, --asd-pool or --cross-talk"
+                .into(),
+        );
+    }
+    let (mut df, mrnas) = if let Some(temperatures) = arguments.thermometer {
+        screen_thermometers(&arguments, temperatures, &ostir, &sequences, &bar)?
+    } else if let Some(aptamer) = &arguments.aptamer {
+        predict_riboswitches(aptamer, &ostir, &sequences, &bar)?
+    } else if let Some(trigger) = &arguments.trigger {
+        predict_switches(trigger, &ostir, &sequences, &bar)?
+    } else if let Some(cds) = &arguments.cds {
+        predict_operons(cds, &ostir, &sequences, &bar)?
+    } else if let Some(pool) = &arguments.asd_pool {
+        predict_pools(pool, false, &ostir, &sequences, &bar)?
+    } else if let Some(asds) = &arguments.cross_talk {
+        let pool: Vec<(String, f64)> = asds.iter().map(|asd| (asd.clone(), 1.0)).collect();
+        predict_pools(&pool, true, &ostir, &sequences, &bar)?
+    } else {
+        predict_sequences(&arguments, &ostir, &sequences, &bar)?
+    };
+    bar.finish_and_clear();
+
+    if df.height() == 0 {
+        println!("No binding sites were identified.");
+        return Ok(());
+    }
+
+    // Non-standard ionic conditions are recorded with the predictions
+    let salt = ostir.parameters().salt;
+    if !salt.is_standard() {
+        df.with_column(Series::new(
+            "monovalent_M",
+            vec![salt.monovalent; df.height()],
+        ))?;
+        df.with_column(Series::new(
+            "magnesium_M",
+            vec![salt.magnesium; df.height()],
+        ))?;
+    }
+    if arguments.print_sequence {
+        df.insert_column(1, Series::new("sequence", mrnas))?;
+    }
+    if arguments.print_asd {
+        let asd = vec![ostir.asd().to_string(); df.height()];
+        df.insert_column(1, Series::new("anti-Shine-Dalgarno", asd))?;
+    }
+
+    match &arguments.output {
+        Some(output) => {
+            let mut file = File::create(output)?;
+            CsvWriter::new(&mut file).finish(&mut df)?;
+        }
+        None => CsvWriter::new(std::io::stdout()).finish(&mut df)?,
+    }
+    Ok(())
+}
+
+/// Predicts every sequence, at every temperature of the sweep when one is given
+fn predict_sequences(
+    arguments: &Arguments,
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut predictions: Vec<Prediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    let mut temperatures: Vec<f32> = vec![];
+    let mut slow_folding = 0;
+    for sequence in sequences {
+        // A sweep lists each start codon at every temperature
+        let mut sequence_predictions: Vec<(f32, Prediction)> = match &arguments.temperature_sweep {
+            Some(sweep) => ostir
+                .predict_temperature_sweep(sequence, sweep)?
+                .into_iter()
+                .flat_map(|(temperature, p)| p.into_iter().map(move |p| (temperature, p)))
+                .collect(),
+            None => {
+                let temperature = ostir.parameters().temperature;
+                ostir
+                    .predict(sequence)?
+                    .into_iter()
+                    .map(|p| (temperature, p))
+                    .collect()
+            }
+        };
+        sequence_predictions.sort_by_key(|(_, p)| p.start_position);
+        slow_folding += sequence_predictions
+            .iter()
+            .filter(|(_, p)| p.slow_folding == Some(true))
+            .count();
+        if arguments.exclude_slow_folding {
+            sequence_predictions.retain(|(_, p)| p.slow_folding != Some(true));
+        }
+        for (temperature, prediction) in sequence_predictions {
+            mrnas.push(sequence.record.clone());
+            temperatures.push(temperature);
+            predictions.push(prediction);
+        }
+        bar.inc(1);
+    }
+
+    if arguments.kinetics || arguments.exclude_slow_folding {
+        eprintln!(
+            "{} start codon(s) may fold slowly (kinetic score above {}){}",
+            slow_folding,
+            ostir.parameters().max_kinetic_score,
+            if arguments.exclude_slow_folding {
+                " and were excluded"
+            } else {
+                ""
+            }
+        );
+    }
+
+    let mut df = predictions_to_dataframe(&predictions, Some(DECIMAL_PLACES))?;
+    if arguments.temperature_sweep.is_some() {
+        df.insert_column(3, Series::new("temperature", temperatures))?;
+    }
+    // Custom folding windows are recorded with the predictions
+    if arguments.upstream.is_some() || arguments.downstream.is_some() || arguments.full_transcript {
+        let window = |f: fn(&Prediction) -> usize| -> Vec<i64> {
+            predictions.iter().map(|p| f(p) as i64).collect()
+        };
+        df.with_column(Series::new("window_start", window(|p| p.folding_window.0)))?;
+        df.with_column(Series::new("window_end", window(|p| p.folding_window.1)))?;
+    }
+    Ok((df, mrnas))
+}
+
+/// Compares every sequence at the two temperatures of a thermometer screen
+fn screen_thermometers(
+    arguments: &Arguments,
+    (low, high): (f32, f32),
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut thermometers: Vec<ThermometerPrediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    for sequence in sequences {
+        let sequence_thermometers =
+            ostir.predict_thermometers(sequence, low, high, arguments.min_fold_change)?;
+        mrnas.extend(
+            sequence_thermometers
+                .iter()
+                .map(|_| sequence.record.clone()),
+        );
+        thermometers.extend(sequence_thermometers);
+        bar.inc(1);
+    }
+    Ok((
+        thermometers_to_dataframe(&thermometers, Some(DECIMAL_PLACES))?,
+        mrnas,
+    ))
+}
+
+/// Compares every sequence without and with the ligand bound to the aptamer
+fn predict_riboswitches(
+    aptamer: &Aptamer,
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut riboswitches: Vec<RiboswitchPrediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    for sequence in sequences {
+        let sequence_riboswitches = ostir.predict_riboswitch(sequence, aptamer)?;
+        mrnas.extend(
+            sequence_riboswitches
+                .iter()
+                .map(|_| sequence.record.clone()),
+        );
+        riboswitches.extend(sequence_riboswitches);
+        bar.inc(1);
+    }
+    Ok((
+        riboswitches_to_dataframe(&riboswitches, Some(DECIMAL_PLACES))?,
+        mrnas,
+    ))
+}
+
+/// Predicts every sequence as an operon, with start codons coupled to the upstream CDS
+fn predict_operons(
+    cds: &[(usize, usize)],
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut predictions: Vec<CoupledPrediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    for sequence in sequences {
+        let sequence_predictions = ostir.predict_operon(sequence, cds)?;
+        mrnas.extend(sequence_predictions.iter().map(|_| sequence.record.clone()));
+        predictions.extend(sequence_predictions);
+        bar.inc(1);
+    }
+    Ok((
+        operons_to_dataframe(&predictions, Some(DECIMAL_PLACES))?,
+        mrnas,
+    ))
+}
+
+/// Predicts every sequence with each aSD of a ribosome pool, as a table of the pool or as a
+/// cross-talk matrix
+fn predict_pools(
+    pool: &[(String, f64)],
+    cross_talk: bool,
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut predictions: Vec<PoolPrediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    for sequence in sequences {
+        let sequence_predictions = ostir.predict_pool(sequence, pool)?;
+        mrnas.extend(sequence_predictions.iter().map(|_| sequence.record.clone()));
+        predictions.extend(sequence_predictions);
+        bar.inc(1);
+    }
+    let df = match cross_talk {
+        true => cross_talk_to_dataframe(&predictions, Some(DECIMAL_PLACES))?,
+        false => pools_to_dataframe(&predictions, Some(DECIMAL_PLACES))?,
+    };
+    Ok((df, mrnas))
+}
+
+/// Evaluates every sequence as a toehold switch, warning about leaky start codons
+fn predict_switches(
+    trigger: &str,
+    ostir: &Ostir,
+    sequences: &[DNASequence],
+    bar: &ProgressBar,
+) -> Result<(DataFrame, Vec<String>), Box<dyn Error>> {
+    let mut switches: Vec<SwitchPrediction> = vec![];
+    let mut mrnas: Vec<String> = vec![];
+    for sequence in sequences {
+        let sequence_switches = ostir.predict_switch(sequence, trigger)?;
+        for switch in &sequence_switches {
+            if !switch.leakage.is_empty() {
+                eprintln!(
+                    "WARNING: {} {} at {} may leak: {}",
+                    switch.off.name,
+                    switch.off.start_codon,
+                    switch.off.start_position,
+                    switch.leakage.join("; ")
+                );
+            }
+        }
+        mrnas.extend(sequence_switches.iter().map(|_| sequence.record.clone()));
+        switches.extend(sequence_switches);
+        bar.inc(1);
     }
+    Ok((
+        switches_to_dataframe(&switches, Some(DECIMAL_PLACES))?,
+        mrnas,
+    ))
 }
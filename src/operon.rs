@@ -0,0 +1,230 @@
+use crate::constants::TERMINATION_FOOTPRINT;
+use crate::factory::{rounding, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use crate::types::MrnaState;
+use polars::prelude::*;
+use std::error::Error;
+
+/// A start codon predicted on its own and with the ribosome that terminates translation of the
+/// upstream CDS whose stop codon is closest
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoupledPrediction {
+    pub independent: Prediction,
+    pub coupled: Prediction,
+    pub upstream_cds: Option<(usize, usize)>, // 1-indexed first and last nt, None when uncoupled
+}
+
+impl Ostir {
+    /// Predicts every start codon of a polycistronic mRNA with translational coupling. Each CDS
+    /// is given by its first nt and the last nt of its stop codon (1-indexed). The ribosome that
+    /// terminates at a stop codon unwinds the mRNA within its footprint, so start codons whose
+    /// folding window overlaps that footprint are also predicted with it unfolded, in the free
+    /// mRNA as well as around the bound 16S rRNA and at the standby site. It stays free to bind
+    /// the 16S rRNA. Start codons without an upstream CDS are predicted the same in both states.
+    pub fn predict_operon(
+        &self,
+        sequence: &DNASequence,
+        cds: &[(usize, usize)],
+    ) -> Result<Vec<CoupledPrediction>, Box<dyn Error>> {
+        let length = sequence.record.replace(" ", "").len();
+        for &(start, end) in cds {
+            if start == 0 || end < start + 2 || end > length {
+                return Err(format!(
+                    "The CDS {}..{} does not fit in {} ({} nt)",
+                    start, end, sequence.description, length
+                ))?;
+            }
+        }
+
+        let independent = self.predict(sequence)?;
+        // Nucleotides covered by the ribosome terminating at the stop codon that ends the CDS
+        let footprint = |&(_, end): &(usize, usize)| {
+            let first = (end - 2).saturating_sub(TERMINATION_FOOTPRINT.0).max(1);
+            (first, (end + TERMINATION_FOOTPRINT.1).min(length))
+        };
+
+        // The nearest stop codon whose footprint reaches into the folding window
+        let coupling: Vec<Option<usize>> = independent
+            .iter()
+            .map(|prediction| {
+                let (window_start, window_end) = prediction.folding_window;
+                (0..cds.len())
+                    .filter(|&i| cds[i].0 < prediction.start_position)
+                    .filter(|&i| {
+                        let (first, last) = footprint(&cds[i]);
+                        first <= window_end && last >= window_start
+                    })
+                    .min_by_key(|&i| (cds[i].1 - 2).abs_diff(prediction.start_position))
+            })
+            .collect();
+
+        let mut coupled: Vec<Option<Vec<Prediction>>> = vec![None; cds.len()];
+        for &i in coupling.iter().flatten() {
+            if coupled[i].is_none() {
+                let (first, last) = footprint(&cds[i]);
+                let state = MrnaState {
+                    unwound: Some(first - 1..last),
+                    ..MrnaState::default()
+                };
+                coupled[i] =
+                    Some(self.predict_with(sequence, self.asd(), self.parameters(), &state)?);
+            }
+        }
+
+        let mut predictions = vec![];
+        for (independent, i) in independent.into_iter().zip(coupling) {
+            let upstream = i.and_then(|i| {
+                let predictions = coupled[i].as_ref()?;
+                let prediction = predictions
+                    .iter()
+                    .find(|p| p.start_position == independent.start_position)?;
+                Some((cds[i], prediction.clone()))
+            });
+            predictions.push(match upstream {
+                Some((upstream_cds, coupled)) => CoupledPrediction {
+                    independent,
+                    coupled,
+                    upstream_cds: Some(upstream_cds),
+                },
+                None => CoupledPrediction {
+                    coupled: independent.clone(),
+                    independent,
+                    upstream_cds: None,
+                },
+            });
+        }
+        Ok(predictions)
+    }
+}
+
+/// Collects coupled predictions into a table with the expression, total and mRNA energies of
+/// both states and the coupling CDS.
+pub fn operons_to_dataframe(
+    predictions: &[CoupledPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let strings =
+        |f: fn(&CoupledPrediction) -> &str| -> Vec<&str> { predictions.iter().map(f).collect() };
+    let numbers = |f: fn(&CoupledPrediction) -> f64| -> Vec<f64> {
+        predictions.iter().map(|p| round(f(p))).collect()
+    };
+    let upstream_cds: Vec<Option<String>> = predictions
+        .iter()
+        .map(|p| {
+            p.upstream_cds
+                .map(|(start, end)| format!("{}..{}", start, end))
+        })
+        .collect();
+
+    DataFrame::new(vec![
+        Series::new("name", strings(|p| &p.independent.name)),
+        Series::new("start_codon", strings(|p| &p.independent.start_codon)),
+        Series::new(
+            "start_position",
+            predictions
+                .iter()
+                .map(|p| p.independent.start_position as i64)
+                .collect::<Vec<i64>>(),
+        ),
+        Series::new("expression", numbers(|p| p.independent.expression)),
+        Series::new("expression_coupled", numbers(|p| p.coupled.expression)),
+        Series::new("upstream_CDS", upstream_cds),
+        Series::new("dG_total", numbers(|p| p.independent.dg_total)),
+        Series::new("dG_total_coupled", numbers(|p| p.coupled.dg_total)),
+        Series::new("dG_mRNA", numbers(|p| p.independent.dg_mrna)),
+        Series::new("dG_mRNA_coupled", numbers(|p| p.coupled.dg_mrna)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ECOLI_ASD;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence, RBS_MRNA};
+    use crate::folding::MockBackend;
+
+    #[test]
+    fn terminating_ribosomes_unwind_the_rbs() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], 0.0, "...................");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+
+        // The stop codon of the upstream CDS ends at nt 9, so its ribosome covers the whole mRNA
+        let predictions = ostir.predict_operon(&sequence, &[(1, 9)]).unwrap();
+        assert_eq!(predictions.len(), 1);
+        let prediction = &predictions[0];
+        assert_eq!(prediction.upstream_cds, Some((1, 9)));
+        assert_eq!(prediction.independent.dg_mrna, -6.0);
+        assert_eq!(prediction.coupled.dg_mrna, 0.0);
+        assert_eq!(
+            prediction.coupled.dg_rrna_mrna,
+            prediction.independent.dg_rrna_mrna
+        );
+        assert!((prediction.independent.dg_total - prediction.coupled.dg_total - 6.0).abs() < 1e-6);
+        let df = operons_to_dataframe(&predictions, None).unwrap();
+        assert!(df.column("expression_coupled").is_ok());
+
+        // Downstream CDSs do not couple
+        let predictions = ostir.predict_operon(&sequence, &[(15, 17)]).unwrap();
+        assert_eq!(predictions[0].upstream_cds, None);
+        assert_eq!(predictions[0].coupled, predictions[0].independent);
+
+        assert!(ostir.predict_operon(&sequence, &[(5, 30)]).is_err());
+    }
+
+    #[test]
+    fn terminating_ribosomes_unwind_the_mrna_ensemble() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], 0.0, "...................");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .ensemble(true)
+            .uncertainty(true)
+            .build()
+            .unwrap();
+        let sequence = rbs_sequence();
+
+        // Only the unfolded mRNA is left in the ensemble and among the alternative structures
+        let prediction = &ostir.predict_operon(&sequence, &[(1, 9)]).unwrap()[0];
+        let (independent, coupled) = (&prediction.independent, &prediction.coupled);
+        assert!(independent.dg_mrna_ensemble.unwrap() < -6.0);
+        assert_eq!(coupled.dg_mrna_ensemble, Some(0.0));
+        assert!(coupled.expression_ensemble > independent.expression_ensemble);
+        assert!(coupled.expression_median > independent.expression_median);
+        assert!(coupled.expression_low > independent.expression_high);
+    }
+
+    #[test]
+    fn terminating_ribosomes_unwind_the_mrna_rrna_complex() {
+        // Upstream of the aSD binding site, the mRNA of the complex folds on its own
+        let pre = "GGGCAAAAGCCCTAA";
+        let mrna = format!("{}GGAGGTAAAA", pre);
+        let backend = MockBackend::new()
+            .with_structure(
+                &[&mrna, ECOLI_ASD],
+                -8.0,
+                "...............(((((.....&.)))))...",
+            )
+            .with_structure(&[pre], -3.0, "(((......)))...")
+            .with_structure(&[pre], 0.0, "...............");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = DNASequence::new(format!("{}ATGGCT", mrna), 1, false).unwrap();
+
+        // The ribosome terminating at nt 9 covers the 5' helix, but not the aSD binding site
+        let prediction = &ostir.predict_operon(&sequence, &[(1, 9)]).unwrap()[0];
+        assert!(prediction
+            .independent
+            .mrna_rrna_structure
+            .starts_with("(((......)))..."));
+        assert!(prediction
+            .coupled
+            .mrna_rrna_structure
+            .starts_with("...............((((("));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::factory::{rounding, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use crate::types::MrnaState;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// A start codon predicted with every rRNA of a heterogeneous pool of ribosomes
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolPrediction {
+    pub name: String,
+    pub start_codon: String,
+    pub start_position: usize,
+    pub predictions: Vec<(String, Option<Prediction>)>, // Per aSD, None when it cannot bind
+    pub expression: f64, // Mean expression over the pool, weighted by aSD abundance
+}
+
+impl PoolPrediction {
+    /// Expression with one aSD of the pool, zero when it does not bind the start codon
+    pub fn expression_with(&self, asd: &str) -> Option<f64> {
+        let (_, prediction) = self.predictions.iter().find(|(other, _)| other == asd)?;
+        Some(prediction.as_ref().map_or(0.0, |p| p.expression))
+    }
+}
+
+impl Ostir {
+    /// Predicts every start codon with each aSD of a pool of ribosomes, given with its relative
+    /// abundance (e.g. native and orthogonal ribosomes). Initiation scales with the concentration
+    /// of each kind of ribosome, so the expression of the pool is the abundance-weighted mean of
+    /// the expression with each aSD. A start codon that an aSD does not bind gets none from it.
+    pub fn predict_pool(
+        &self,
+        sequence: &DNASequence,
+        pool: &[(String, f64)],
+    ) -> Result<Vec<PoolPrediction>, Box<dyn Error>> {
+        let mut asds: Vec<String> = vec![];
+        for (asd, abundance) in pool {
+            let asd = asd.to_uppercase().replace('U', "T");
+            if asd.is_empty() || !asd.chars().all(|c| "ACGT".contains(c)) {
+                return Err(format!(
+                    "Invalid anti-Shine-Dalgarno sequence in the pool: {}",
+                    asd
+                ))?;
+            }
+            if asds.contains(&asd) {
+                return Err(format!(
+                    "The anti-Shine-Dalgarno {} is in the pool twice",
+                    asd
+                ))?;
+            }
+            if !abundance.is_finite() || *abundance < 0.0 {
+                return Err(format!("Invalid abundance of {}: {}", asd, abundance))?;
+            }
+            asds.push(asd);
+        }
+        let total: f64 = pool.iter().map(|(_, abundance)| abundance).sum();
+        if total <= 0.0 {
+            return Err("The pool needs an aSD with a positive abundance")?;
+        }
+
+        // Predictions of each aSD by start position
+        let mut by_position: BTreeMap<usize, Vec<Option<Prediction>>> = BTreeMap::new();
+        for (i, asd) in asds.iter().enumerate() {
+            let state = MrnaState::default();
+            for prediction in self.predict_with(sequence, asd, self.parameters(), &state)? {
+                let predictions = by_position
+                    .entry(prediction.start_position)
+                    .or_insert_with(|| vec![None; asds.len()]);
+                predictions[i] = Some(prediction);
+            }
+        }
+
+        let pools = by_position
+            .into_iter()
+            .map(|(start_position, predictions)| {
+                let first = predictions.iter().flatten().next().unwrap();
+                let (name, start_codon) = (first.name.clone(), first.start_codon.clone());
+                let expression = predictions
+                    .iter()
+                    .zip(pool)
+                    .map(|(p, (_, abundance))| abundance * p.as_ref().map_or(0.0, |p| p.expression))
+                    .sum::<f64>()
+                    / total;
+                PoolPrediction {
+                    name,
+                    start_codon,
+                    start_position,
+                    predictions: asds.iter().cloned().zip(predictions).collect(),
+                    expression,
+                }
+            })
+            .collect();
+        Ok(pools)
+    }
+}
+
+/// Start codon columns shared by the pool and cross-talk tables
+fn start_codon_columns(predictions: &[PoolPrediction]) -> Vec<Series> {
+    vec![
+        Series::new(
+            "name",
+            predictions
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<&str>>(),
+        ),
+        Series::new(
+            "start_codon",
+            predictions
+                .iter()
+                .map(|p| p.start_codon.as_str())
+                .collect::<Vec<&str>>(),
+        ),
+        Series::new(
+            "start_position",
+            predictions
+                .iter()
+                .map(|p| p.start_position as i64)
+                .collect::<Vec<i64>>(),
+        ),
+    ]
+}
+
+/// Collects pool predictions into a table with the weighted expression followed by the
+/// expression and total energy with each aSD, which are missing where it does not bind.
+pub fn pools_to_dataframe(
+    predictions: &[PoolPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let mut columns = start_codon_columns(predictions);
+    columns.push(Series::new(
+        "expression",
+        predictions
+            .iter()
+            .map(|p| round(p.expression))
+            .collect::<Vec<f64>>(),
+    ));
+
+    let asds = predictions.first().map_or(vec![], |p| {
+        p.predictions
+            .iter()
+            .map(|(asd, _)| asd.clone())
+            .collect::<Vec<String>>()
+    });
+    for (i, asd) in asds.iter().enumerate() {
+        let value = |f: fn(&Prediction) -> f64| -> Vec<Option<f64>> {
+            predictions
+                .iter()
+                .map(|p| p.predictions[i].1.as_ref().map(|p| round(f(p))))
+                .collect()
+        };
+        columns.push(Series::new(
+            &format!("expression_{}", asd),
+            value(|p| p.expression),
+        ));
+        columns.push(Series::new(
+            &format!("dG_total_{}", asd),
+            value(|p| p.dg_total),
+        ));
+    }
+    DataFrame::new(columns)
+}
+
+/// Collects pool predictions into a cross-talk matrix: the expression of each start codon
+/// (rows) with each aSD (columns, zero where it does not bind), the aSD that expresses it most
+/// and its specificity, the fold change over the next best aSD. Specificity is missing with a
+/// single aSD and where no other aSD binds, which would make it infinite.
+pub fn cross_talk_to_dataframe(
+    predictions: &[PoolPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let mut columns = start_codon_columns(predictions);
+
+    let asds = predictions.first().map_or(vec![], |p| {
+        p.predictions
+            .iter()
+            .map(|(asd, _)| asd.clone())
+            .collect::<Vec<String>>()
+    });
+    for asd in &asds {
+        let expression: Vec<f64> = predictions
+            .iter()
+            .map(|p| round(p.expression_with(asd).unwrap()))
+            .collect();
+        columns.push(Series::new(asd, expression));
+    }
+
+    let mut best_asds: Vec<&str> = vec![];
+    let mut specificities: Vec<Option<f64>> = vec![];
+    for prediction in predictions {
+        let mut expression: Vec<(f64, &str)> = asds
+            .iter()
+            .map(|asd| (prediction.expression_with(asd).unwrap(), asd.as_str()))
+            .collect();
+        expression.sort_by(|a, b| b.0.total_cmp(&a.0));
+        best_asds.push(expression[0].1);
+        specificities.push(
+            expression
+                .get(1)
+                .filter(|(second, _)| *second > 0.0)
+                .map(|(second, _)| round(expression[0].0 / second)),
+        );
+    }
+    columns.push(Series::new("best_aSD", best_asds));
+    columns.push(Series::new("specificity", specificities));
+    DataFrame::new(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ECOLI_ASD;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence};
+
+    #[test]
+    fn orthogonal_ribosomes_add_expression() {
+        // The orthogonal aSD (CCACC) pairs with the GGUGG site instead of the GGAGG site
+        let orthogonal = "ACCACCTTA";
+        let backend = rbs_backend()
+            .with_structure(
+                &["TAAGGAGGTAAAA", orthogonal],
+                -2.0,
+                "...((........&......)).",
+            )
+            .with_structure(&["TAAGGAGGTAAAA"], 0.0, ".............");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+
+        let native = ostir.predict(&sequence).unwrap();
+        let pool = [(ECOLI_ASD.to_string(), 3.0), (orthogonal.to_string(), 1.0)];
+        let predictions = ostir.predict_pool(&sequence, &pool).unwrap();
+        assert_eq!(predictions.len(), 1);
+        let prediction = &predictions[0];
+        assert_eq!(prediction.predictions[0].1.as_ref(), Some(&native[0]));
+        let native_expression = prediction.expression_with(ECOLI_ASD).unwrap();
+        let orthogonal_expression = prediction.expression_with(orthogonal).unwrap();
+        assert!(orthogonal_expression < native_expression);
+        let weighted = (3.0 * native_expression + orthogonal_expression) / 4.0;
+        assert!((prediction.expression - weighted).abs() < 1e-9);
+
+        let df = pools_to_dataframe(&predictions, None).unwrap();
+        assert!(df.column(&format!("expression_{}", orthogonal)).is_ok());
+        let df = cross_talk_to_dataframe(&predictions, None).unwrap();
+        assert_eq!(
+            df.column("best_aSD").unwrap().str().unwrap().get(0),
+            Some(ECOLI_ASD)
+        );
+
+        let twice = [(ECOLI_ASD.to_string(), 1.0), (ECOLI_ASD.to_string(), 1.0)];
+        assert!(ostir.predict_pool(&sequence, &twice).is_err());
+        assert!(ostir
+            .predict_pool(&sequence, &[(ECOLI_ASD.to_string(), 0.0)])
+            .is_err());
+    }
+    #[test]
+    fn specificity_needs_a_second_binding_asd() {
+        // No structure of the mRNA with the second aSD is registered, so it does not bind
+        let ostir = Ostir::builder().backend(rbs_backend()).build().unwrap();
+        let pool = [(ECOLI_ASD.to_string(), 1.0), ("GGGGGGGGG".to_string(), 1.0)];
+        let predictions = ostir.predict_pool(&rbs_sequence(), &pool).unwrap();
+        assert_eq!(predictions[0].expression_with("GGGGGGGGG"), Some(0.0));
+
+        let df = cross_talk_to_dataframe(&predictions, None).unwrap();
+        assert_eq!(
+            df.column("specificity").unwrap().f64().unwrap().get(0),
+            None
+        );
+    }
+}
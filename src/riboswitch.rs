@@ -0,0 +1,147 @@
+use crate::factory::{rounding, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use crate::types::{Aptamer, MrnaState};
+use polars::prelude::*;
+use std::error::Error;
+
+/// A start codon predicted without and with the ligand bound to the aptamer
+#[derive(Clone, Debug, PartialEq)]
+pub struct RiboswitchPrediction {
+    pub unbound: Prediction,
+    pub bound: Prediction,
+    pub on_off_ratio: f64, // Expression with the ligand over expression without it
+}
+
+impl Ostir {
+    /// Predicts every start codon in the ligand-free and the ligand-bound state of a riboswitch.
+    /// With the ligand, the mRNA can fold the aptamer motif at any of its sites for an extra
+    /// `binding_energy`, in the free mRNA as well as around the bound 16S rRNA. Ensemble and
+    /// uncertainty outputs ignore the ligand.
+    pub fn predict_riboswitch(
+        &self,
+        sequence: &DNASequence,
+        aptamer: &Aptamer,
+    ) -> Result<Vec<RiboswitchPrediction>, Box<dyn Error>> {
+        let unbound = self.predict(sequence)?;
+        let bound = MrnaState {
+            ligand: Some(aptamer.clone()),
+            ..MrnaState::default()
+        };
+        let bound = self.predict_with(sequence, self.asd(), self.parameters(), &bound)?;
+
+        let mut riboswitches = vec![];
+        for unbound in unbound {
+            let Some(bound) = bound
+                .iter()
+                .find(|p| p.start_position == unbound.start_position)
+            else {
+                continue;
+            };
+            riboswitches.push(RiboswitchPrediction {
+                on_off_ratio: bound.expression / unbound.expression,
+                unbound,
+                bound: bound.clone(),
+            });
+        }
+        Ok(riboswitches)
+    }
+}
+
+/// Collects riboswitch predictions into a table with the expression, mRNA energy and structures
+/// of both states.
+pub fn riboswitches_to_dataframe(
+    riboswitches: &[RiboswitchPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let strings = |f: fn(&RiboswitchPrediction) -> &str| -> Vec<&str> {
+        riboswitches.iter().map(f).collect()
+    };
+    let numbers = |f: fn(&RiboswitchPrediction) -> f64| -> Vec<f64> {
+        riboswitches.iter().map(|r| round(f(r))).collect()
+    };
+
+    DataFrame::new(vec![
+        Series::new("name", strings(|r| &r.unbound.name)),
+        Series::new("start_codon", strings(|r| &r.unbound.start_codon)),
+        Series::new(
+            "start_position",
+            riboswitches
+                .iter()
+                .map(|r| r.unbound.start_position as i64)
+                .collect::<Vec<i64>>(),
+        ),
+        Series::new("expression_unbound", numbers(|r| r.unbound.expression)),
+        Series::new("expression_bound", numbers(|r| r.bound.expression)),
+        Series::new("ON/OFF", numbers(|r| r.on_off_ratio)),
+        Series::new("dG_total_unbound", numbers(|r| r.unbound.dg_total)),
+        Series::new("dG_total_bound", numbers(|r| r.bound.dg_total)),
+        Series::new("dG_mRNA_unbound", numbers(|r| r.unbound.dg_mrna)),
+        Series::new("dG_mRNA_bound", numbers(|r| r.bound.dg_mrna)),
+        Series::new(
+            "mRNA_structure_unbound",
+            strings(|r| &r.unbound.mrna_structure),
+        ),
+        Series::new("mRNA_structure_bound", strings(|r| &r.bound.mrna_structure)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence, RBS_MRNA};
+
+    #[test]
+    fn ligand_stabilizes_the_aptamer() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], -4.0, "...(((.........))).");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+        let aptamer = Aptamer::new("GGAGGUAAAAAUGGC", "(((.........)))", -5.0).unwrap();
+        assert_eq!(aptamer.sites(mrna), vec![3]);
+
+        // The ligand turns the aptamer hairpin into the MFE, which sequesters the SD
+        let riboswitches = ostir.predict_riboswitch(&sequence, &aptamer).unwrap();
+        assert_eq!(riboswitches.len(), 1);
+        let riboswitch = &riboswitches[0];
+        assert_eq!(riboswitch.unbound.dg_mrna, -6.0);
+        assert_eq!(riboswitch.bound.dg_mrna, -9.0);
+        assert_eq!(riboswitch.bound.mrna_structure, "...(((.........))).");
+        assert!((riboswitch.bound.dg_total - riboswitch.unbound.dg_total - 3.0).abs() < 1e-6);
+        assert!(riboswitch.on_off_ratio < 1.0);
+        assert_eq!(ostir.predict(&sequence).unwrap()[0], riboswitch.unbound);
+
+        let df = riboswitches_to_dataframe(&riboswitches, None).unwrap();
+        assert!(df.column("ON/OFF").is_ok());
+        assert!(df.column("mRNA_structure_bound").is_ok());
+
+        assert!(Aptamer::new("GGAAACC", "((...))", -1.0).is_ok());
+        assert!(Aptamer::new("GGAAACC", "((...)", -1.0).is_err());
+        assert!(Aptamer::new("GGAAACC", ")(...()", -1.0).is_err());
+    }
+
+    #[test]
+    fn ligand_stabilizes_the_aptamer_in_the_ensemble() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], -4.0, "...(((.........))).");
+        let ostir = Ostir::builder()
+            .backend(backend)
+            .ensemble(true)
+            .uncertainty(true)
+            .build()
+            .unwrap();
+        let aptamer = Aptamer::new("GGAGGUAAAAAUGGC", "(((.........)))", -5.0).unwrap();
+
+        // The bound aptamer dominates the ensemble and the alternative structures
+        let riboswitch = &ostir.predict_riboswitch(&rbs_sequence(), &aptamer).unwrap()[0];
+        let (unbound, bound) = (&riboswitch.unbound, &riboswitch.bound);
+        let dg_bound = bound.dg_mrna_ensemble.unwrap();
+        assert!(dg_bound < -9.0 && dg_bound > -9.01);
+        assert!(unbound.dg_mrna_ensemble.unwrap() > -6.1);
+        assert!(bound.expression_median < unbound.expression_median);
+    }
+}
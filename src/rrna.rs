@@ -0,0 +1,151 @@
+use crate::constants::{ASD_FLANK, ASD_MAX_LENGTH, ECOLI_ASD, SSU_MOTIF, SSU_MOTIF_DISTANCE};
+use crate::file_parser::fileparser::{reverse_complement, DNASequence, Feature};
+
+/// anti-Shine-Dalgarno sequences at the 3' ends of the 16S rRNA genes of a record, in the order
+/// of their 3' ends. Annotated 16S rRNA features are used when the record has any. Otherwise
+/// both strands are searched for the conserved 3' end of the 16S rRNA (SSU_MOTIF followed by
+/// ASD_FLANK), and the aSD is taken as long as that of E. coli.
+pub fn asds_from_16s(sequence: &DNASequence) -> Vec<String> {
+    let record = sequence.record.to_uppercase().replace('U', "T");
+    let annotated: Vec<&Feature> = sequence
+        .features
+        .iter()
+        .filter(|feature| {
+            let description = feature.description.to_lowercase();
+            feature.kind == "rRNA"
+                && (description.contains("16s") || description.contains("small subunit"))
+        })
+        .collect();
+
+    let mut asds: Vec<(usize, String)> = if annotated.is_empty() {
+        search_16s(&record)
+    } else {
+        annotated
+            .into_iter()
+            .filter_map(|feature| {
+                let asd = annotated_asd(&record, feature)?;
+                Some((
+                    if feature.complement {
+                        feature.start
+                    } else {
+                        feature.end
+                    },
+                    asd,
+                ))
+            })
+            .collect()
+    };
+    asds.sort();
+    asds.into_iter().map(|(_, asd)| asd).collect()
+}
+
+/// aSD of an annotated 16S rRNA: the nucleotides after the last ASD_FLANK near its 3' end up to
+/// the annotated end. Annotations that stop short of a full aSD are extended along the genome.
+fn annotated_asd(record: &str, feature: &Feature) -> Option<String> {
+    // The rRNA and up to ASD_MAX_LENGTH nt downstream of it
+    let rrna = match feature.complement {
+        true => {
+            let start = feature.start.saturating_sub(ASD_MAX_LENGTH).max(1);
+            reverse_complement(&record[start - 1..feature.end])
+        }
+        false => {
+            record[feature.start - 1..(feature.end + ASD_MAX_LENGTH).min(record.len())].to_string()
+        }
+    };
+    let length = feature.end - feature.start + 1;
+
+    let search_from = length.saturating_sub(SSU_MOTIF_DISTANCE + ASD_FLANK.len());
+    let tail = search_from + rrna.get(search_from..length)?.rfind(ASD_FLANK)? + ASD_FLANK.len();
+    let asd_length = (length - tail).clamp(ECOLI_ASD.len(), ASD_MAX_LENGTH);
+    Some(rrna[tail..(tail + asd_length).min(rrna.len())].to_string())
+}
+
+/// Unannotated 16S rRNA 3' ends on both strands, with the position of the end of their aSD
+fn search_16s(record: &str) -> Vec<(usize, String)> {
+    let mut asds = vec![];
+    for (strand, complement) in [
+        (record.to_string(), false),
+        (reverse_complement(record), true),
+    ] {
+        for (motif, _) in strand.match_indices(SSU_MOTIF) {
+            let from = motif + SSU_MOTIF.len();
+            let to = (from + SSU_MOTIF_DISTANCE + ASD_FLANK.len()).min(strand.len());
+            let Some(flank) = strand[from..to].find(ASD_FLANK) else {
+                continue;
+            };
+            let tail = from + flank + ASD_FLANK.len();
+            let Some(asd) = strand.get(tail..tail + ECOLI_ASD.len()) else {
+                continue;
+            };
+            let end = tail + ECOLI_ASD.len();
+            asds.push((
+                if complement {
+                    strand.len() - end + 1
+                } else {
+                    end
+                },
+                asd.to_string(),
+            ));
+        }
+    }
+    asds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_parser::fileparser;
+
+    // 3' end of the E. coli 16S rRNA followed by a few nt of the spacer
+    const RRNA: &str = "GTCGTAACAAGGTAACCGTAGGGGAACCTGCGGTTGGATCACCTCCTTAGCGTT";
+
+    #[test]
+    fn asd_is_read_from_the_16s_rrna() {
+        // The 16S rRNA lies on the reverse strand at 16..64, annotated 3 nt short of its 3' end
+        let genome = format!("TTTTTTTTTT{}TTTTTTTTTT", reverse_complement(RRNA));
+        let origin: Vec<String> = genome
+            .as_bytes()
+            .chunks(60)
+            .enumerate()
+            .map(|(i, line)| format!("{:>9} {}", i * 60 + 1, std::str::from_utf8(line).unwrap()))
+            .collect();
+        let genbank = [
+            "LOCUS       TEST                      74 bp    DNA     circular BCT 01-JAN-2000",
+            "FEATURES             Location/Qualifiers",
+            "     source          1..74",
+            "     rRNA            complement(19..",
+            "                     64)",
+            "                     /product=\"16S ribosomal",
+            "                     RNA\"",
+            "     rRNA            1..10",
+            "                     /product=\"5S ribosomal RNA\"",
+            "     misc_feature    70..74",
+            "ORIGIN",
+            &origin.join("\n"),
+            "//",
+        ];
+        let path = std::env::temp_dir().join("ostir_16s_test.gb");
+        std::fs::write(&path, genbank.join("\n")).unwrap();
+        let sequences = fileparser::parse_file(path.to_str().unwrap(), 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let [sequence] = &sequences[..] else {
+            panic!("Expected one record")
+        };
+        assert_eq!(sequence.description, "TEST");
+        assert!(sequence.iscircular);
+        assert_eq!(sequence.features.len(), 4);
+        let rrna = &sequence.features[1];
+        assert_eq!((rrna.start, rrna.end, rrna.complement), (19, 64, true));
+        assert_eq!(rrna.description, "/product=\"16S ribosomal RNA\"");
+        assert!(rrna
+            .sequence(&sequence.record.to_uppercase())
+            .starts_with("GTCGTAACAAGG"));
+        assert_eq!(asds_from_16s(sequence), vec!["ACCTCCTTA"]);
+
+        // Without annotations, the conserved 3' end is found on both strands
+        let genome = format!("{}AAAA{}", genome, RRNA.replace("CCTTAG", "CCTTTG"));
+        let sequence = DNASequence::new(genome, 1, false).unwrap();
+        assert_eq!(asds_from_16s(&sequence), vec!["ACCTCCTTA", "ACCTCCTTT"]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::factory::{rounding, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use polars::prelude::*;
+use std::error::Error;
+
+/// A start codon predicted at a low and at a high temperature
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermometerPrediction {
+    pub low_temperature: f32,
+    pub high_temperature: f32,
+    pub low: Prediction,
+    pub high: Prediction,
+    pub fold_change: f64, // Expression at the high temperature over the low temperature
+    pub is_thermometer: bool,
+}
+
+impl Ostir {
+    /// Predicts every start codon at both temperatures (in C) and flags RNA thermometers: RBSs
+    /// whose expression changes by at least `min_fold_change` in either direction. Start codons
+    /// without a binding site at one of the temperatures are left out.
+    pub fn predict_thermometers(
+        &self,
+        sequence: &DNASequence,
+        low_temperature: f32,
+        high_temperature: f32,
+        min_fold_change: f64,
+    ) -> Result<Vec<ThermometerPrediction>, Box<dyn Error>> {
+        let mut sweep = self
+            .predict_temperature_sweep(sequence, &[low_temperature, high_temperature])?
+            .into_iter();
+        let (Some((_, low)), Some((_, high))) = (sweep.next(), sweep.next()) else {
+            return Ok(vec![]);
+        };
+
+        let mut thermometers = vec![];
+        for low in low {
+            let Some(high) = high.iter().find(|p| p.start_position == low.start_position) else {
+                continue;
+            };
+            let fold_change = high.expression / low.expression;
+            thermometers.push(ThermometerPrediction {
+                low_temperature,
+                high_temperature,
+                fold_change,
+                is_thermometer: fold_change >= min_fold_change
+                    || fold_change <= 1.0 / min_fold_change,
+                low,
+                high: high.clone(),
+            });
+        }
+        Ok(thermometers)
+    }
+}
+
+/// Collects thermometer predictions into a table with the expression and structures at both
+/// temperatures.
+pub fn thermometers_to_dataframe(
+    thermometers: &[ThermometerPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let (low_temperature, high_temperature) = thermometers
+        .first()
+        .map_or((0.0, 0.0), |t| (t.low_temperature, t.high_temperature));
+    let low = |name: &str| format!("{}_{}C", name, low_temperature);
+    let high = |name: &str| format!("{}_{}C", name, high_temperature);
+    let strings = |f: fn(&ThermometerPrediction) -> &str| -> Vec<&str> {
+        thermometers.iter().map(f).collect()
+    };
+    let numbers = |f: fn(&ThermometerPrediction) -> f64| -> Vec<f64> {
+        thermometers.iter().map(|t| round(f(t))).collect()
+    };
+
+    DataFrame::new(vec![
+        Series::new("name", strings(|t| &t.low.name)),
+        Series::new("start_codon", strings(|t| &t.low.start_codon)),
+        Series::new(
+            "start_position",
+            thermometers
+                .iter()
+                .map(|t| t.low.start_position as i64)
+                .collect::<Vec<i64>>(),
+        ),
+        Series::new(&low("expression"), numbers(|t| t.low.expression)),
+        Series::new(&high("expression"), numbers(|t| t.high.expression)),
+        Series::new("fold_change", numbers(|t| t.fold_change)),
+        Series::new(
+            "thermometer",
+            thermometers
+                .iter()
+                .map(|t| t.is_thermometer)
+                .collect::<Vec<bool>>(),
+        ),
+        Series::new(&low("mRNA_structure"), strings(|t| &t.low.mrna_structure)),
+        Series::new(&high("mRNA_structure"), strings(|t| &t.high.mrna_structure)),
+        Series::new(
+            &low("mRNA:rRNA_structure"),
+            strings(|t| &t.low.mrna_rrna_structure),
+        ),
+        Series::new(
+            &high("mRNA:rRNA_structure"),
+            strings(|t| &t.high.mrna_rrna_structure),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence};
+
+    #[test]
+    fn flags_expression_changes_between_temperatures() {
+        let backend = rbs_backend();
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let sequence = rbs_sequence();
+
+        // Only BETA changes with temperature in the mock, which lowers expression when warmer
+        let thermometers = ostir
+            .predict_thermometers(&sequence, 30.0, 42.0, 1.01)
+            .unwrap();
+        assert_eq!(thermometers.len(), 1);
+        let thermometer = &thermometers[0];
+        assert!(thermometer.fold_change < 1.0);
+        assert!(thermometer.is_thermometer);
+        assert!(
+            !ostir
+                .predict_thermometers(&sequence, 30.0, 42.0, 4.0)
+                .unwrap()[0]
+                .is_thermometer
+        );
+
+        let df = thermometers_to_dataframe(&thermometers, None).unwrap();
+        assert!(df.column("expression_30C").is_ok());
+        assert!(df.column("mRNA:rRNA_structure_42C").is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::constants::SWITCH_MIN_RATIO;
+use crate::factory::{rounding, Ostir, Prediction};
+use crate::file_parser::fileparser::DNASequence;
+use polars::prelude::*;
+use std::error::Error;
+
+/// A start codon of a toehold switch predicted without (OFF) and with (ON) its trigger RNA
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwitchPrediction {
+    pub off: Prediction,
+    pub on: Prediction,
+    pub on_off_ratio: f64,
+    pub leakage: Vec<String>, // Warnings about expression in the OFF state
+}
+
+impl Ostir {
+    /// Predicts every start codon of a toehold switch mRNA in its repressed state and with the
+    /// trigger RNA bound. The trigger pairs with its reverse complement in the switch, which keeps
+    /// those nucleotides out of the mRNA and mRNA:rRNA structures. The trigger duplex is formed
+    /// both before and after the ribosome binds, so its energy cancels out of dG_total.
+    pub fn predict_switch(
+        &self,
+        switch: &DNASequence,
+        trigger: &str,
+    ) -> Result<Vec<SwitchPrediction>, Box<dyn Error>> {
+        let mrna = switch
+            .record
+            .replace(" ", "")
+            .to_uppercase()
+            .replace('U', "T");
+        let site = trigger_site(&mrna, trigger)
+            .ok_or("The trigger is not complementary to any part of the switch")?;
+
+        // The trigger-bound switch keeps the trigger binding site unpaired
+        let mut on = DNASequence::new(
+            switch.record.clone(),
+            switch.max_iter_size,
+            switch.iscircular,
+        )?;
+        on.description = switch.description.clone();
+        on.reactivities = switch.reactivities.clone();
+        let mut constraints: Vec<char> = switch.constraints.chars().collect();
+        constraints.resize(mrna.len(), '.');
+        constraints[site.clone()].fill('x');
+        on.constraints = constraints.into_iter().collect();
+
+        let off = self.predict(switch)?;
+        let on = self.predict(&on)?;
+
+        let mut switches = vec![];
+        for off in off {
+            let Some(on) = on.iter().find(|p| p.start_position == off.start_position) else {
+                continue;
+            };
+            let on_off_ratio = on.expression / off.expression;
+
+            let mut leakage = vec![];
+            if on_off_ratio < SWITCH_MIN_RATIO {
+                leakage.push(format!("ON/OFF ratio below {}", SWITCH_MIN_RATIO));
+            }
+            let start_in_window = off.start_position - off.folding_window.0;
+            if off.mrna_structure.get(start_in_window..start_in_window + 3) == Some("...") {
+                leakage.push("start codon unpaired in the OFF state".to_string());
+            }
+
+            switches.push(SwitchPrediction {
+                off,
+                on: on.clone(),
+                on_off_ratio,
+                leakage,
+            });
+        }
+        Ok(switches)
+    }
+}
+
+/// Positions of the switch mRNA that pair with the trigger, which has to be fully complementary
+fn trigger_site(mrna: &str, trigger: &str) -> Option<std::ops::Range<usize>> {
+    let binding_site: String = trigger
+        .to_uppercase()
+        .chars()
+        .rev()
+        .map(|c| match c {
+            'A' => 'T',
+            'C' => 'G',
+            'G' => 'C',
+            'T' | 'U' => 'A',
+            _ => 'N',
+        })
+        .collect();
+    if binding_site.is_empty() {
+        return None;
+    }
+    mrna.find(&binding_site)
+        .map(|site| site..site + binding_site.len())
+}
+
+/// Collects toehold switch predictions into a table with the expression, energies and structures
+/// of both states.
+pub fn switches_to_dataframe(
+    switches: &[SwitchPrediction],
+    decimal_places: Option<u32>,
+) -> PolarsResult<DataFrame> {
+    let round = rounding(decimal_places);
+    let strings =
+        |f: fn(&SwitchPrediction) -> &str| -> Vec<&str> { switches.iter().map(f).collect() };
+    let numbers = |f: fn(&SwitchPrediction) -> f64| -> Vec<f64> {
+        switches.iter().map(|s| round(f(s))).collect()
+    };
+
+    DataFrame::new(vec![
+        Series::new("name", strings(|s| &s.off.name)),
+        Series::new("start_codon", strings(|s| &s.off.start_codon)),
+        Series::new(
+            "start_position",
+            switches
+                .iter()
+                .map(|s| s.off.start_position as i64)
+                .collect::<Vec<i64>>(),
+        ),
+        Series::new("expression_OFF", numbers(|s| s.off.expression)),
+        Series::new("expression_ON", numbers(|s| s.on.expression)),
+        Series::new("ON/OFF", numbers(|s| s.on_off_ratio)),
+        Series::new("dG_total_OFF", numbers(|s| s.off.dg_total)),
+        Series::new("dG_total_ON", numbers(|s| s.on.dg_total)),
+        Series::new("dG_mRNA_OFF", numbers(|s| s.off.dg_mrna)),
+        Series::new("dG_mRNA_ON", numbers(|s| s.on.dg_mrna)),
+        Series::new("mRNA_structure_OFF", strings(|s| &s.off.mrna_structure)),
+        Series::new("mRNA_structure_ON", strings(|s| &s.on.mrna_structure)),
+        Series::new(
+            "leakage",
+            switches
+                .iter()
+                .map(|s| s.leakage.join("; "))
+                .collect::<Vec<String>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::folding::fixtures::{rbs_backend, rbs_sequence, RBS_MRNA};
+
+    #[test]
+    fn trigger_opens_the_switch_hairpin() {
+        let mrna = RBS_MRNA;
+        let backend = rbs_backend()
+            .with_structure(&[mrna], -6.0, "(((.............)))")
+            .with_structure(&[mrna], 0.0, "...................");
+        let ostir = Ostir::builder().backend(backend).build().unwrap();
+        let switch = rbs_sequence();
+
+        // The trigger pairs with the first three nt, which the repressing hairpin needs
+        let switches = ostir.predict_switch(&switch, "UUA").unwrap();
+        assert_eq!(switches.len(), 1);
+        let prediction = &switches[0];
+        assert_eq!(prediction.off.dg_mrna, -6.0);
+        assert_eq!(prediction.on.dg_mrna, 0.0);
+        assert!(prediction.on_off_ratio > SWITCH_MIN_RATIO);
+        assert_eq!(
+            prediction.leakage,
+            vec!["start codon unpaired in the OFF state"]
+        );
+
+        let df = switches_to_dataframe(&switches, None).unwrap();
+        assert!(df.column("ON/OFF").is_ok());
+        assert!(ostir.predict_switch(&switch, "GGGGG").is_err());
+    }
+}
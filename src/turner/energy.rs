@@ -0,0 +1,296 @@
+use super::parameters::{encode, pair_type, reverse_type, Parameters, INF, MAXLOOP, NBPAIRS};
+use std::sync::Arc;
+
+/// Loop energies of one or two strands, following the ViennaRNA conventions for dangles and
+/// co-folding. Positions are 1-indexed over the concatenated strands.
+pub(crate) struct EnergyModel {
+    pub params: Arc<Parameters>,
+    pub n: usize,
+    pub cut: usize, // First nucleotide of the second strand, n + 1 for a single strand
+    pub dangles: bool, // Mismatch and dangle energies on every stem (d2) or none at all (d0)
+    bases: Vec<u8>, // RNA sequence with padding at both ends
+    encoded: Vec<usize>,
+    stack_bonus: Vec<i32>, // Soft constraint on every nucleotide of a stacked pair, padded
+}
+
+impl EnergyModel {
+    pub fn new(
+        sequences: &[&str],
+        params: Arc<Parameters>,
+        dangles: bool,
+    ) -> Result<EnergyModel, String> {
+        if sequences.is_empty() || sequences.len() > 2 {
+            return Err(format!(
+                "The Turner backend folds one or two strands, not {}",
+                sequences.len()
+            ));
+        }
+
+        let sequence = sequences.concat().to_uppercase().replace('T', "U");
+        let n = sequence.len();
+        let cut = match sequences.len() {
+            2 => sequences[0].len() + 1,
+            _ => n + 1,
+        };
+        if cut == 1 || (sequences.len() == 2 && cut == n + 1) {
+            return Err("Co-folded strands can not be empty".to_string());
+        }
+
+        let mut bases = vec![b'N'];
+        bases.extend(sequence.bytes());
+        bases.push(b'N');
+        let encoded = bases.iter().map(|&base| encode(base)).collect();
+
+        Ok(EnergyModel {
+            params,
+            n,
+            cut,
+            dangles,
+            bases,
+            encoded,
+            stack_bonus: vec![0; n + 2],
+        })
+    }
+
+    /// Adds pseudo-energies in kcal/mol to every nucleotide of a stacked pair, one per position
+    pub fn with_stack_pseudo_energies(mut self, pseudo_energies: &[f64]) -> EnergyModel {
+        for (i, energy) in pseudo_energies.iter().take(self.n).enumerate() {
+            self.stack_bonus[i + 1] = (energy * 100.0).round() as i32;
+        }
+        self
+    }
+
+    pub fn strands(&self) -> usize {
+        if self.cut <= self.n {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether positions a <= b lie on different strands
+    pub fn spans_cut(&self, a: usize, b: usize) -> bool {
+        a < self.cut && self.cut <= b
+    }
+
+    /// Pair type of (i, j), 0 if the nucleotides can not pair
+    pub fn pair_type(&self, i: usize, j: usize) -> usize {
+        pair_type(self.encoded[i], self.encoded[j])
+    }
+
+    /// Whether (i, j) is a canonical pair that leaves room for a hairpin
+    pub fn can_pair(&self, i: usize, j: usize) -> bool {
+        i < j && self.pair_type(i, j) != 0 && (j - i > 3 || self.spans_cut(i, j))
+    }
+
+    // Non-canonical pairs are only scored when evaluating given structures
+    fn loop_type(&self, i: usize, j: usize) -> usize {
+        match self.pair_type(i, j) {
+            0 => NBPAIRS,
+            t => t,
+        }
+    }
+
+    fn s(&self, i: usize) -> usize {
+        self.encoded[i]
+    }
+
+    fn extrapolate(&self, table: &[i32; MAXLOOP + 1], size: usize) -> i32 {
+        if size <= MAXLOOP {
+            table[size]
+        } else {
+            table[MAXLOOP] + (self.params.lxc * (size as f64 / MAXLOOP as f64).ln()) as i32
+        }
+    }
+
+    /// Hairpin loop closed by (i, j)
+    pub fn hairpin(&self, i: usize, j: usize) -> i32 {
+        let p = &self.params;
+        let size = j - i - 1;
+        let pair = self.loop_type(i, j);
+        let energy = self.extrapolate(&p.hairpin, size);
+        if size < 3 {
+            return energy;
+        }
+
+        let special = match size {
+            3 => &p.triloops,
+            4 => &p.tetraloops,
+            6 => &p.hexaloops,
+            _ => return energy + p.mismatch_hairpin[pair][self.s(i + 1)][self.s(j - 1)],
+        };
+        let sequence = std::str::from_utf8(&self.bases[i..=j]).unwrap_or_default();
+        if let Some(&bonus) = special.get(sequence) {
+            return bonus;
+        }
+        if size == 3 {
+            return energy + if pair > 2 { p.terminal_au } else { 0 };
+        }
+        energy + p.mismatch_hairpin[pair][self.s(i + 1)][self.s(j - 1)]
+    }
+
+    /// Stack, bulge or interior loop between the outer pair (i, j) and the inner pair (k, l)
+    pub fn interior(&self, i: usize, j: usize, k: usize, l: usize) -> i32 {
+        let p = &self.params;
+        let n1 = k - i - 1;
+        let n2 = j - l - 1;
+        let (nl, ns) = if n1 > n2 { (n1, n2) } else { (n2, n1) };
+        let t1 = self.loop_type(i, j);
+        let t2 = reverse_type(self.loop_type(k, l));
+        let (si, sj, sk, sl) = (self.s(i + 1), self.s(j - 1), self.s(k - 1), self.s(l + 1));
+
+        if nl == 0 {
+            let bonus = &self.stack_bonus;
+            return p.stack[t1][t2] + bonus[i] + bonus[j] + bonus[k] + bonus[l];
+        }
+
+        if ns == 0 {
+            let mut energy = self.extrapolate(&p.bulge, nl);
+            if nl == 1 {
+                energy += p.stack[t1][t2];
+            } else {
+                if t1 > 2 {
+                    energy += p.terminal_au;
+                }
+                if t2 > 2 {
+                    energy += p.terminal_au;
+                }
+            }
+            return energy;
+        }
+
+        match (ns, nl) {
+            (1, 1) => return p.int11[t1][t2][si][sj],
+            (1, 2) if n1 == 1 => return p.int21[t1][t2][si][sl][sj],
+            (1, 2) => return p.int21[t2][t1][sl][si][sk],
+            (1, _) => {
+                return self.extrapolate(&p.interior, nl + 1)
+                    + p.max_ninio.min((nl - ns) as i32 * p.ninio)
+                    + p.mismatch_interior_1n[t1][si][sj]
+                    + p.mismatch_interior_1n[t2][sl][sk];
+            }
+            (2, 2) => return p.int22[t1][t2][si][sk][sl][sj],
+            (2, 3) => {
+                return p.interior[5]
+                    + p.ninio
+                    + p.mismatch_interior_23[t1][si][sj]
+                    + p.mismatch_interior_23[t2][sl][sk];
+            }
+            _ => {}
+        }
+
+        self.extrapolate(&p.interior, nl + ns)
+            + p.max_ninio.min((nl - ns) as i32 * p.ninio)
+            + p.mismatch_interior[t1][si][sj]
+            + p.mismatch_interior[t2][sl][sk]
+    }
+
+    fn exterior_energy(&self, pair: usize, five: Option<usize>, three: Option<usize>) -> i32 {
+        let p = &self.params;
+        let mut energy = if pair > 2 { p.terminal_au } else { 0 };
+        if self.dangles {
+            energy += match (five, three) {
+                (Some(x), Some(y)) => p.mismatch_exterior[pair][x][y],
+                (Some(x), None) => p.dangle5[pair][x],
+                (None, Some(y)) => p.dangle3[pair][y],
+                (None, None) => 0,
+            };
+        }
+        energy
+    }
+
+    /// Stem (i, j) in the exterior loop. Neighbours across the strand break do not dangle.
+    pub fn exterior_stem(&self, i: usize, j: usize) -> i32 {
+        let five = (i > 1 && i != self.cut).then(|| self.s(i - 1));
+        let three = (j < self.n && j + 1 != self.cut).then(|| self.s(j + 1));
+        self.exterior_energy(self.loop_type(i, j), five, three)
+    }
+
+    /// Pair (i, j) closing a loop that contains the strand break, which makes it an exterior loop.
+    /// Every structure that joins the strands has exactly one such loop, so it also carries the
+    /// duplex initiation. Structures in which the strands do not bind each other pay nothing.
+    pub fn exterior_closing(&self, i: usize, j: usize) -> i32 {
+        let five = (j != self.cut).then(|| self.s(j - 1));
+        let three = (i + 1 != self.cut).then(|| self.s(i + 1));
+        self.params.duplex_init
+            + self.exterior_energy(reverse_type(self.loop_type(i, j)), five, three)
+    }
+
+    fn multi_energy(&self, pair: usize, five: usize, three: usize) -> i32 {
+        let p = &self.params;
+        let mut energy = p.ml_intern + if pair > 2 { p.terminal_au } else { 0 };
+        if self.dangles {
+            energy += p.mismatch_multi[pair][five][three];
+        }
+        energy
+    }
+
+    /// Branch (i, j) of a multiloop
+    pub fn multi_stem(&self, i: usize, j: usize) -> i32 {
+        self.multi_energy(self.loop_type(i, j), self.s(i - 1), self.s(j + 1))
+    }
+
+    /// Pair (i, j) closing a multiloop
+    pub fn multi_closing(&self, i: usize, j: usize) -> i32 {
+        let pair = reverse_type(self.loop_type(i, j));
+        self.params.ml_closing + self.multi_energy(pair, self.s(j - 1), self.s(i + 1))
+    }
+
+    /// Free energy of a structure given as a pair table (partner of every position, 0 if
+    /// unpaired), including the duplex initiation if the strands bind each other
+    pub fn eval(&self, pairs: &[usize]) -> i32 {
+        let mut energy = 0;
+        let mut i = 1;
+        while i <= self.n {
+            if pairs[i] > i {
+                energy += self.exterior_stem(i, pairs[i]) + self.eval_loop(pairs, i, pairs[i]);
+                i = pairs[i];
+            }
+            i += 1;
+        }
+        energy.min(INF)
+    }
+
+    fn eval_loop(&self, pairs: &[usize], i: usize, j: usize) -> i32 {
+        let mut branches = vec![];
+        let mut unpaired = 0;
+        let mut k = i + 1;
+        while k < j {
+            if pairs[k] > k {
+                branches.push((k, pairs[k]));
+                k = pairs[k];
+            } else {
+                unpaired += 1;
+            }
+            k += 1;
+        }
+
+        let inner: i32 = branches
+            .iter()
+            .map(|&(k, l)| self.eval_loop(pairs, k, l))
+            .sum();
+        let broken = self.spans_cut(i, j) && !branches.iter().any(|&(k, l)| self.spans_cut(k, l));
+
+        let energy = if broken {
+            self.exterior_closing(i, j)
+                + branches
+                    .iter()
+                    .map(|&(k, l)| self.exterior_stem(k, l))
+                    .sum::<i32>()
+        } else {
+            match branches.as_slice() {
+                [] => self.hairpin(i, j),
+                [(k, l)] => self.interior(i, j, *k, *l),
+                _ => {
+                    self.multi_closing(i, j)
+                        + unpaired * self.params.ml_base
+                        + branches
+                            .iter()
+                            .map(|&(k, l)| self.multi_stem(k, l))
+                            .sum::<i32>()
+                }
+            }
+        };
+        (energy + inner).min(INF)
+    }
+}
@@ -0,0 +1,541 @@
+use super::energy::EnergyModel;
+use super::parameters::{INF, MAXLOOP};
+
+/// Parts of a secondary structure that the recursions decompose. Every structure has exactly one
+/// decomposition, so the same recursions give the MFE, the suboptimal structures and the
+/// partition function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Segment {
+    /// Exterior loop over 1..=j
+    Exterior(usize),
+    /// (i, j) is paired. Without lonely pairs, (i + 1, j - 1) is paired too.
+    Paired(usize, usize),
+    /// Loop closed by the pair (i, j)
+    Loop(usize, usize),
+    /// Part i..=j of a multiloop with at least one branch
+    Multi(usize, usize),
+    /// Part i..=j of a multiloop with a single branch starting at i
+    MultiLast(usize, usize),
+    /// Exterior loop over i..cut at the 3' end of the first strand
+    Left(usize),
+    /// Exterior loop over cut..=j at the 5' end of the second strand
+    Right(usize),
+}
+
+/// Base pair closed by a decomposition, if any, and the segments it leaves to decompose
+type Decomposition = (Option<(usize, usize)>, Vec<Segment>);
+
+/// How decompositions are combined: minimum free energy or Boltzmann sum
+pub(crate) trait Algebra {
+    type Value: Copy;
+    fn zero(&self) -> Self::Value;
+    fn plus(&self, a: Self::Value, b: Self::Value) -> Self::Value;
+    fn times(&self, a: Self::Value, b: Self::Value) -> Self::Value;
+    /// Value of a loop energy that covers `nucleotides` new positions
+    fn weight(&self, energy: i32, nucleotides: usize) -> Self::Value;
+}
+
+pub(crate) struct MinimumFreeEnergy;
+
+impl Algebra for MinimumFreeEnergy {
+    type Value = i32;
+
+    fn zero(&self) -> i32 {
+        INF
+    }
+
+    fn plus(&self, a: i32, b: i32) -> i32 {
+        a.min(b)
+    }
+
+    fn times(&self, a: i32, b: i32) -> i32 {
+        (a + b).min(INF)
+    }
+
+    fn weight(&self, energy: i32, _nucleotides: usize) -> i32 {
+        energy
+    }
+}
+
+/// Boltzmann weights, with every nucleotide scaled so that long sequences do not overflow
+pub(crate) struct PartitionFunction {
+    pub kt: f64,
+    pub scale: f64,
+}
+
+impl Algebra for PartitionFunction {
+    type Value = f64;
+
+    fn zero(&self) -> f64 {
+        0.0
+    }
+
+    fn plus(&self, a: f64, b: f64) -> f64 {
+        a + b
+    }
+
+    fn times(&self, a: f64, b: f64) -> f64 {
+        a * b
+    }
+
+    fn weight(&self, energy: i32, nucleotides: usize) -> f64 {
+        (-(energy as f64) * 10.0 / self.kt).exp() * self.scale.powi(nucleotides as i32)
+    }
+}
+
+/// Filled dynamic programming matrices, one value per segment
+pub(crate) struct Matrices<V> {
+    n: usize,
+    paired: Vec<V>,
+    loops: Vec<V>,
+    multi: Vec<V>,
+    multi_last: Vec<V>,
+    exterior: Vec<V>,
+    left: Vec<V>,
+    right: Vec<V>,
+}
+
+impl<V: Copy> Matrices<V> {
+    fn new(n: usize, zero: V) -> Self {
+        let size = (n + 2) * (n + 2);
+        Matrices {
+            n,
+            paired: vec![zero; size],
+            loops: vec![zero; size],
+            multi: vec![zero; size],
+            multi_last: vec![zero; size],
+            exterior: vec![zero; n + 2],
+            left: vec![zero; n + 2],
+            right: vec![zero; n + 2],
+        }
+    }
+
+    fn slot(&mut self, segment: Segment) -> &mut V {
+        let index = |i: usize, j: usize| i * (self.n + 2) + j;
+        match segment {
+            Segment::Exterior(j) => &mut self.exterior[j],
+            Segment::Paired(i, j) => &mut self.paired[index(i, j)],
+            Segment::Loop(i, j) => &mut self.loops[index(i, j)],
+            Segment::Multi(i, j) => &mut self.multi[index(i, j)],
+            Segment::MultiLast(i, j) => &mut self.multi_last[index(i, j)],
+            Segment::Left(i) => &mut self.left[i],
+            Segment::Right(j) => &mut self.right[j],
+        }
+    }
+
+    pub fn get(&self, segment: Segment) -> V {
+        let index = |i: usize, j: usize| i * (self.n + 2) + j;
+        match segment {
+            Segment::Exterior(j) => self.exterior[j],
+            Segment::Paired(i, j) => self.paired[index(i, j)],
+            Segment::Loop(i, j) => self.loops[index(i, j)],
+            Segment::Multi(i, j) => self.multi[index(i, j)],
+            Segment::MultiLast(i, j) => self.multi_last[index(i, j)],
+            Segment::Left(i) => self.left[i],
+            Segment::Right(j) => self.right[j],
+        }
+    }
+}
+
+/// Folding recursions over an energy model, restricted by hard constraints
+pub(crate) struct Folder<'a> {
+    model: &'a EnergyModel,
+    no_lonely_pairs: bool,
+    allowed: Vec<bool>,
+    must_pair: Vec<usize>, // Prefix counts of positions that have to be paired
+}
+
+impl<'a> Folder<'a> {
+    /// Hard constraints use the ViennaRNA symbols: `x` unpaired, `|` paired, `<` and `>` paired
+    /// downstream and upstream, matching `(` and `)` paired with each other, `.` anything.
+    pub fn new(
+        model: &'a EnergyModel,
+        constraints: &str,
+        no_lonely_pairs: bool,
+    ) -> Result<Folder<'a>, String> {
+        let n = model.n;
+        let mut symbols = vec![b'.'];
+        symbols.extend(constraints.bytes().filter(|&c| c != b'&').take(n));
+        symbols.resize(n + 2, b'.');
+
+        let mut partner = vec![0; n + 2];
+        let mut opened = vec![];
+        for (i, &symbol) in symbols.iter().enumerate().take(n + 1).skip(1) {
+            match symbol {
+                b'(' => opened.push(i),
+                b')' => {
+                    let j = opened
+                        .pop()
+                        .ok_or(format!("Unbalanced constraint at position {}", i))?;
+                    partner[i] = j;
+                    partner[j] = i;
+                }
+                b'.' | b'x' | b'|' | b'<' | b'>' => {}
+                _ => return Err(format!("Unknown constraint symbol {}", symbol as char)),
+            }
+        }
+        if let Some(i) = opened.pop() {
+            return Err(format!("Unbalanced constraint at position {}", i));
+        }
+
+        let free = |k: usize, downstream: bool| match symbols[k] {
+            b'x' => false,
+            b'<' => downstream,
+            b'>' => !downstream,
+            _ => true,
+        };
+        let forced = partner.iter().any(|&k| k != 0);
+        let mut allowed = vec![false; (n + 2) * (n + 2)];
+        for i in 1..=n {
+            for j in i + 1..=n {
+                let mut ok = model.can_pair(i, j) && free(i, true) && free(j, false);
+                if forced && ok {
+                    // Forced pairs can not be replaced or crossed
+                    ok = (partner[i] == 0 && partner[j] == 0 || partner[i] == j)
+                        && (i + 1..j).all(|k| partner[k] == 0 || (i..=j).contains(&partner[k]));
+                }
+                allowed[i * (n + 2) + j] = ok;
+            }
+        }
+
+        let mut must_pair = vec![0; n + 2];
+        for i in 1..=n {
+            let paired = partner[i] != 0 || matches!(symbols[i], b'|' | b'<' | b'>');
+            must_pair[i] = must_pair[i - 1] + paired as usize;
+        }
+
+        Ok(Folder {
+            model,
+            no_lonely_pairs,
+            allowed,
+            must_pair,
+        })
+    }
+
+    /// Forbids base pairs spanning more than `span` nucleotides, for local folding
+    pub fn with_max_span(mut self, span: usize) -> Self {
+        let n = self.model.n;
+        for i in 1..=n {
+            for j in i + span..=n {
+                self.allowed[i * (n + 2) + j] = false;
+            }
+        }
+        self
+    }
+
+    fn allowed(&self, i: usize, j: usize) -> bool {
+        i < j && j <= self.model.n && self.allowed[i * (self.model.n + 2) + j]
+    }
+
+    /// Whether positions a..=b can all stay unpaired
+    fn unpaired(&self, a: usize, b: usize) -> bool {
+        a > b || self.must_pair[b] == self.must_pair[a - 1]
+    }
+
+    /// Calls `f(energy, nucleotides, pair, children)` for every way to decompose a segment
+    pub fn decompose<F>(&self, segment: Segment, mut f: F)
+    where
+        F: FnMut(i32, usize, Option<(usize, usize)>, &[Segment]),
+    {
+        let m = self.model;
+        let cut = m.cut;
+        match segment {
+            Segment::Exterior(0) => f(0, 0, None, &[]),
+            Segment::Exterior(j) => {
+                if self.unpaired(j, j) {
+                    f(0, 1, None, &[Segment::Exterior(j - 1)]);
+                }
+                for k in 1..j {
+                    if self.allowed(k, j) {
+                        let children = [Segment::Exterior(k - 1), Segment::Paired(k, j)];
+                        f(m.exterior_stem(k, j), 0, None, &children);
+                    }
+                }
+            }
+            Segment::Paired(i, j) => {
+                if !self.no_lonely_pairs {
+                    f(0, 0, None, &[Segment::Loop(i, j)]);
+                } else if self.allowed(i + 1, j - 1)
+                    && !m.spans_cut(i, i + 1)
+                    && !m.spans_cut(j - 1, j)
+                {
+                    let children = [Segment::Loop(i + 1, j - 1)];
+                    f(m.interior(i, j, i + 1, j - 1), 2, Some((i, j)), &children);
+                }
+            }
+            Segment::Loop(i, j) => self.decompose_loop(i, j, &mut f),
+            Segment::Multi(i, j) => {
+                if j > i && self.unpaired(j, j) && !m.spans_cut(j - 1, j) {
+                    f(m.params.ml_base, 1, None, &[Segment::Multi(i, j - 1)]);
+                }
+                for k in i..j {
+                    if !self.allowed(k, j) {
+                        continue;
+                    }
+                    if !m.spans_cut(i, k) && self.unpaired(i, k - 1) {
+                        let energy = m.params.ml_base * (k - i) as i32 + m.multi_stem(k, j);
+                        f(energy, k - i, None, &[Segment::Paired(k, j)]);
+                    }
+                    if k > i && !m.spans_cut(k - 1, k) {
+                        let children = [Segment::Multi(i, k - 1), Segment::Paired(k, j)];
+                        f(m.multi_stem(k, j), 0, None, &children);
+                    }
+                }
+            }
+            Segment::MultiLast(i, j) => {
+                if j > i && self.unpaired(j, j) && !m.spans_cut(j - 1, j) {
+                    f(m.params.ml_base, 1, None, &[Segment::MultiLast(i, j - 1)]);
+                }
+                if self.allowed(i, j) {
+                    f(m.multi_stem(i, j), 0, None, &[Segment::Paired(i, j)]);
+                }
+            }
+            Segment::Left(i) if i == cut => f(0, 0, None, &[]),
+            Segment::Left(i) => {
+                if self.unpaired(i, i) {
+                    f(0, 1, None, &[Segment::Left(i + 1)]);
+                }
+                for l in i + 1..cut {
+                    if self.allowed(i, l) {
+                        let children = [Segment::Paired(i, l), Segment::Left(l + 1)];
+                        f(m.exterior_stem(i, l), 0, None, &children);
+                    }
+                }
+            }
+            Segment::Right(j) if j + 1 == cut => f(0, 0, None, &[]),
+            Segment::Right(j) => {
+                if self.unpaired(j, j) {
+                    f(0, 1, None, &[Segment::Right(j - 1)]);
+                }
+                for k in cut..j {
+                    if self.allowed(k, j) {
+                        let children = [Segment::Right(k - 1), Segment::Paired(k, j)];
+                        f(m.exterior_stem(k, j), 0, None, &children);
+                    }
+                }
+            }
+        }
+    }
+
+    fn decompose_loop<F>(&self, i: usize, j: usize, f: &mut F)
+    where
+        F: FnMut(i32, usize, Option<(usize, usize)>, &[Segment]),
+    {
+        let m = self.model;
+        let pair = Some((i, j));
+
+        // A loop that contains the strand break is an exterior loop
+        if m.spans_cut(i, j) {
+            let children = [Segment::Left(i + 1), Segment::Right(j - 1)];
+            f(m.exterior_closing(i, j), 2, pair, &children);
+        } else if self.unpaired(i + 1, j - 1) {
+            f(m.hairpin(i, j), j - i + 1, pair, &[]);
+        }
+
+        for k in i + 1..(i + MAXLOOP + 2).min(j) {
+            if !self.unpaired(i + 1, k - 1) || m.spans_cut(i, k) {
+                break;
+            }
+            for l in (k + 1..j).rev() {
+                if (k - i - 1) + (j - l - 1) > MAXLOOP
+                    || !self.unpaired(l + 1, j - 1)
+                    || m.spans_cut(l, j)
+                {
+                    break;
+                }
+                if !self.allowed(k, l) {
+                    continue;
+                }
+                let inner = match self.no_lonely_pairs && k == i + 1 && l == j - 1 {
+                    true => Segment::Loop(k, l),
+                    false => Segment::Paired(k, l),
+                };
+                f(m.interior(i, j, k, l), (k - i) + (j - l), pair, &[inner]);
+            }
+        }
+
+        if !m.spans_cut(i, i + 1) && !m.spans_cut(j - 1, j) {
+            let closing = m.multi_closing(i, j);
+            for k in i + 2..j {
+                if !m.spans_cut(k - 1, k) {
+                    let children = [Segment::Multi(i + 1, k - 1), Segment::MultiLast(k, j - 1)];
+                    f(closing, 2, pair, &children);
+                }
+            }
+        }
+    }
+
+    fn evaluate<A: Algebra>(
+        &self,
+        algebra: &A,
+        matrices: &Matrices<A::Value>,
+        segment: Segment,
+    ) -> A::Value {
+        let mut total = algebra.zero();
+        self.decompose(segment, |energy, nucleotides, _, children| {
+            if energy >= INF {
+                return;
+            }
+            let mut value = algebra.weight(energy, nucleotides);
+            for &child in children {
+                value = algebra.times(value, matrices.get(child));
+            }
+            total = algebra.plus(total, value);
+        });
+        total
+    }
+
+    /// All segments, each one after the segments it decomposes into
+    fn order(&self) -> Vec<Segment> {
+        let n = self.model.n;
+        let cut = self.model.cut;
+        let cofold = self.model.strands() > 1;
+        let mut order = vec![];
+        for i in (1..=n).rev() {
+            if cofold && i + 1 == cut {
+                order.push(Segment::Left(cut));
+                order.extend((cut - 1..=n).map(Segment::Right));
+            }
+            for j in i..=n {
+                if self.allowed(i, j) {
+                    order.push(Segment::Loop(i, j));
+                    order.push(Segment::Paired(i, j));
+                }
+                order.push(Segment::MultiLast(i, j));
+                order.push(Segment::Multi(i, j));
+            }
+            if cofold && i < cut {
+                order.push(Segment::Left(i));
+            }
+        }
+        order.extend((0..=n).map(Segment::Exterior));
+        order
+    }
+
+    /// Fills the matrices so that every segment is computed after the segments it decomposes into
+    pub fn fill<A: Algebra>(&self, algebra: &A) -> Matrices<A::Value> {
+        let mut matrices = Matrices::new(self.model.n, algebra.zero());
+        for segment in self.order() {
+            let value = self.evaluate(algebra, &matrices, segment);
+            *matrices.slot(segment) = value;
+        }
+        matrices
+    }
+
+    /// Probabilities of all base pairs as (i, j, probability), from the filled partition function.
+    /// Outside values are pushed from every segment to the segments it decomposes into.
+    pub fn pair_probabilities(
+        &self,
+        algebra: &PartitionFunction,
+        inside: &Matrices<f64>,
+    ) -> Vec<(usize, usize, f64)> {
+        let n = self.model.n;
+        let root = Segment::Exterior(n);
+        let z = inside.get(root);
+        let mut outside = Matrices::new(n, 0.0);
+        *outside.slot(root) = 1.0;
+
+        let mut probabilities = vec![0.0; (n + 2) * (n + 2)];
+        for segment in self.order().into_iter().rev() {
+            let parent = outside.get(segment);
+            if parent == 0.0 {
+                continue;
+            }
+            self.decompose(segment, |energy, nucleotides, pair, children| {
+                if energy >= INF {
+                    return;
+                }
+                let weight = parent * algebra.weight(energy, nucleotides);
+                let values: Vec<f64> = children.iter().map(|&child| inside.get(child)).collect();
+                if let Some((i, j)) = pair {
+                    probabilities[i * (n + 2) + j] += weight * values.iter().product::<f64>() / z;
+                }
+                for (k, &child) in children.iter().enumerate() {
+                    let siblings: f64 = values
+                        .iter()
+                        .enumerate()
+                        .filter(|&(m, _)| m != k)
+                        .map(|(_, value)| value)
+                        .product();
+                    *outside.slot(child) += weight * siblings;
+                }
+            });
+        }
+
+        let mut pairs = vec![];
+        for i in 1..=n {
+            for j in i + 1..=n {
+                let probability = probabilities[i * (n + 2) + j];
+                if probability > 0.0 {
+                    pairs.push((i, j, probability.min(1.0)));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Base pairs of the minimum free energy structure
+    pub fn backtrack(&self, matrices: &Matrices<i32>) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+        let mut pending = vec![Segment::Exterior(self.model.n)];
+        while let Some(segment) = pending.pop() {
+            let target = matrices.get(segment);
+            let mut chosen: Option<Decomposition> = None;
+            self.decompose(segment, |energy, _, pair, children| {
+                if chosen.is_some() || energy >= INF {
+                    return;
+                }
+                let total = children.iter().fold(energy, |total, &child| {
+                    (total + matrices.get(child)).min(INF)
+                });
+                if total == target {
+                    chosen = Some((pair, children.to_vec()));
+                }
+            });
+            if let Some((pair, children)) = chosen {
+                pairs.extend(pair);
+                pending.extend(children);
+            }
+        }
+        pairs
+    }
+
+    /// All structures within `delta` of the minimum free energy, as (energy, base pairs)
+    pub fn subopt(&self, matrices: &Matrices<i32>, delta: i32) -> Vec<(i32, Vec<(usize, usize)>)> {
+        let root = Segment::Exterior(self.model.n);
+        let mfe = matrices.get(root);
+        if mfe >= INF {
+            return vec![];
+        }
+        let threshold = mfe + delta;
+
+        // Partial structures with the segments left to decompose and a lower bound of their energy
+        let mut structures = vec![];
+        let mut stack = vec![(vec![root], vec![], mfe)];
+        while let Some((pending, pairs, bound)) = stack.pop() {
+            let Some(&segment) = pending.last() else {
+                structures.push((bound, pairs));
+                continue;
+            };
+            let base = bound - matrices.get(segment);
+            self.decompose(segment, |energy, _, pair, children| {
+                if energy >= INF {
+                    return;
+                }
+                let bound = children.iter().fold(base + energy, |total, &child| {
+                    (total + matrices.get(child)).min(INF)
+                });
+                if bound > threshold {
+                    return;
+                }
+                let mut pending = pending.clone();
+                pending.pop();
+                pending.extend_from_slice(children);
+                let mut pairs = pairs.clone();
+                pairs.extend(pair);
+                stack.push((pending, pairs, bound));
+            });
+        }
+        structures
+    }
+}
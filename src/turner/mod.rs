@@ -0,0 +1,479 @@
+//! Pure-Rust folding with the Turner 2004 nearest neighbor parameters, following the ViennaRNA
+//! energy model (no lonely pairs, dangles on all stems or none, co-folding with a duplex
+//! initiation penalty on structures that join the strands). It reads the same parameter file as
+//! the Python version of OSTIR. Salt only corrects the stacking energies; Mg2+ is converted to an
+//! equivalent Na+ concentration.
+//!
+//! Results match ViennaRNA for the MFE energies, subopt energies and structure evaluation.
+//! Degenerate structures can be chosen differently. Dangles "some" (d1) are not implemented and
+//! fold like "all".
+
+mod energy;
+mod fold;
+mod parameters;
+
+use crate::constants::{HYBRIDIZATION_PENALTY, NO_LONELY_PAIRS};
+use crate::folding::{dots_to_coordinates, round_energy, shape_pseudo_energies, FoldingBackend};
+use crate::types::{DanglesSetting, Ensemble, FoldResult, SaltConditions};
+use energy::EnergyModel;
+use fold::{Folder, MinimumFreeEnergy, PartitionFunction, Segment};
+use parameters::{Parameters, INF};
+use std::error::Error;
+
+/// Number of local folding windows that cover each nucleotide
+const LOCAL_WINDOW_COVERAGE: usize = 8;
+
+/// Folds with the pure-Rust Turner 2004 implementation
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TurnerBackend;
+
+fn model(
+    sequences: &[&str],
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &DanglesSetting,
+) -> Result<EnergyModel, String> {
+    let d2 = dangles.as_int() != Ok(0);
+    EnergyModel::new(sequences, Parameters::at(temp, salt), d2)
+}
+
+/// Dot-bracket notation of base pairs, with `&` between the strands
+fn to_dots(model: &EnergyModel, pairs: &[(usize, usize)]) -> String {
+    let mut dots = vec!['.'; model.n];
+    for &(i, j) in pairs {
+        dots[i - 1] = '(';
+        dots[j - 1] = ')';
+    }
+    if model.strands() > 1 {
+        dots.insert(model.cut - 1, '&');
+    }
+    dots.into_iter().collect()
+}
+
+/// Structures within `energy_gap` of the MFE of the model, scored by `energy` from their dots
+/// and their energy in the model (dcal/mol) and sorted by it
+fn subopt_folds<'a>(
+    model: &EnergyModel,
+    constraints: &str,
+    energy_gap: f32,
+    energy: impl Fn(&str, i32) -> f32,
+) -> Vec<FoldResult<'a>> {
+    let Ok(folder) = Folder::new(model, constraints, NO_LONELY_PAIRS) else {
+        return vec![];
+    };
+    let matrices = folder.fill(&MinimumFreeEnergy);
+
+    // Same energy band as the ViennaRNA backend
+    let delta = ((energy_gap + HYBRIDIZATION_PENALTY as f32) * 100.0) as i32;
+    let mut folds: Vec<FoldResult> = folder
+        .subopt(&matrices, delta)
+        .into_iter()
+        .filter_map(|(model_energy, pairs)| {
+            let dots = to_dots(model, &pairs);
+            let (bp_x, bp_y) = dots_to_coordinates(&dots).ok()?;
+            let d_g = energy(&dots, model_energy);
+            Some(FoldResult::create(None, d_g, dots, bp_x, bp_y))
+        })
+        .collect();
+
+    // Co-folds in which the strands do not bind each other are not reported
+    if model.strands() > 1 {
+        let first_strand_len = model.cut - 1;
+        folds.retain(|fold| fold.get_bp_y().iter().any(|&y| y > first_strand_len));
+    }
+    folds.sort_by(|a, b| {
+        a.get_d_g()
+            .partial_cmp(b.get_d_g())
+            .unwrap()
+            .then_with(|| a.get_dots().cmp(b.get_dots()))
+    });
+    folds
+}
+
+/// Ensemble free energy and base pair probabilities of the model
+fn ensemble(model: &EnergyModel, constraints: &str) -> Result<Ensemble, Box<dyn Error>> {
+    let folder = Folder::new(model, constraints, NO_LONELY_PAIRS)?;
+    let mfe = folder
+        .fill(&MinimumFreeEnergy)
+        .get(Segment::Exterior(model.n));
+    if mfe >= INF {
+        return Err("No structure satisfies the constraints".into());
+    }
+
+    // Scale the Boltzmann factors with the MFE to avoid overflows on long sequences
+    let kt = model.params.kt;
+    let scale = (mfe as f64 * 10.0 / (kt * model.n as f64)).exp();
+    let algebra = PartitionFunction { kt, scale };
+    let matrices = folder.fill(&algebra);
+    let z = matrices.get(Segment::Exterior(model.n));
+    let d_g = -kt * (z.ln() - model.n as f64 * scale.ln()) / 1000.0;
+
+    let pairs = folder.pair_probabilities(&algebra, &matrices);
+    Ok(Ensemble::new(round_energy(d_g as f32, 2), model.n, pairs))
+}
+
+impl FoldingBackend for TurnerBackend {
+    fn mfe<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        let model = model(sequences, temp, salt, dangles)?;
+        let folder = Folder::new(&model, constraints, NO_LONELY_PAIRS)?;
+        let matrices = folder.fill(&MinimumFreeEnergy);
+        let energy = matrices.get(Segment::Exterior(model.n));
+        if energy >= INF {
+            return Err("No structure satisfies the constraints".into());
+        }
+
+        let dots = to_dots(&model, &folder.backtrack(&matrices));
+        let (bp_x, bp_y) = dots_to_coordinates(&dots)?;
+        Ok(FoldResult::create(
+            Some(sequences),
+            round_energy(energy as f32 / 100.0, 2),
+            dots,
+            bp_x,
+            bp_y,
+        ))
+    }
+
+    fn subopt<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        energy_gap: f32,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        let Ok(model) = model(sequences, temp, salt, dangles) else {
+            return vec![];
+        };
+        subopt_folds(&model, constraints, energy_gap, |_, energy| {
+            round_energy(energy as f32 / 100.0, 3)
+        })
+    }
+
+    fn eval_structure(
+        &self,
+        sequences: &[&str],
+        dots: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> f32 {
+        let Ok(model) = model(sequences, temp, salt, dangles) else {
+            return INF as f32 / 100.0;
+        };
+        let dots = dots.replace('&', "");
+        if dots.len() != model.n {
+            return INF as f32 / 100.0;
+        }
+
+        let mut pairs = vec![0; model.n + 2];
+        let Ok((bp_x, bp_y)) = dots_to_coordinates(&dots) else {
+            return INF as f32 / 100.0;
+        };
+        for (&i, &j) in bp_x.iter().zip(bp_y.iter()) {
+            pairs[i] = j;
+            pairs[j] = i;
+        }
+        round_energy(model.eval(&pairs) as f32 / 100.0, 2)
+    }
+
+    fn partition_function(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        ensemble(&model(sequences, temp, salt, dangles)?, constraints)
+    }
+
+    fn local_unpaired_probabilities(
+        &self,
+        sequence: &str,
+        window: usize,
+        span: usize,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        // Every window is folded on its own and each nucleotide averages the windows it is in.
+        // Unlike RNAplfold, windows start every window / LOCAL_WINDOW_COVERAGE nt rather than at
+        // every nt, which keeps genome-scale runs affordable.
+        let n = sequence.len();
+        if n == 0 {
+            return Ok(vec![]);
+        }
+        let window = window.clamp(1, n);
+        let step = (window / LOCAL_WINDOW_COVERAGE).max(1);
+        let mut starts: Vec<usize> = (0..=n - window).step_by(step).collect();
+        if starts.last() != Some(&(n - window)) {
+            starts.push(n - window);
+        }
+        let mut sums = vec![0.0; n];
+        let mut counts = vec![0; n];
+        for start in starts {
+            let strands = [&sequence[start..start + window]];
+            let model = model(&strands, temp, salt, dangles)?;
+            let folder = Folder::new(&model, "", NO_LONELY_PAIRS)?.with_max_span(span);
+            let mfe = folder
+                .fill(&MinimumFreeEnergy)
+                .get(Segment::Exterior(model.n));
+            let kt = model.params.kt;
+            let scale = (mfe as f64 * 10.0 / (kt * model.n as f64)).exp();
+            let algebra = PartitionFunction { kt, scale };
+            let matrices = folder.fill(&algebra);
+            let pairs = folder.pair_probabilities(&algebra, &matrices);
+            let ensemble = Ensemble::new(0.0, model.n, pairs);
+            for (k, unpaired) in ensemble.unpaired_probabilities().into_iter().enumerate() {
+                sums[start + k] += unpaired;
+                counts[start + k] += 1;
+            }
+        }
+        Ok(sums
+            .iter()
+            .zip(&counts)
+            .map(|(sum, &count)| sum / count as f64)
+            .collect())
+    }
+
+    fn mfe_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        let model = model(sequences, temp, salt, dangles)?
+            .with_stack_pseudo_energies(&shape_pseudo_energies(reactivities));
+        let folder = Folder::new(&model, constraints, NO_LONELY_PAIRS)?;
+        let matrices = folder.fill(&MinimumFreeEnergy);
+        if matrices.get(Segment::Exterior(model.n)) >= INF {
+            return Err("No structure satisfies the constraints".into());
+        }
+
+        // The structure is scored without the pseudo-energies
+        let dots = to_dots(&model, &folder.backtrack(&matrices));
+        let energy = self.eval_structure(sequences, &dots, temp, salt, dangles);
+        let (bp_x, bp_y) = dots_to_coordinates(&dots)?;
+        Ok(FoldResult::create(
+            Some(sequences),
+            energy,
+            dots,
+            bp_x,
+            bp_y,
+        ))
+    }
+
+    fn partition_function_with_reactivities(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        let model = model(sequences, temp, salt, dangles)?
+            .with_stack_pseudo_energies(&shape_pseudo_energies(reactivities));
+        ensemble(&model, constraints)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn subopt_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        energy_gap: f32,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        let Ok(model) = model(sequences, temp, salt, dangles) else {
+            return vec![];
+        };
+        let model = model.with_stack_pseudo_energies(&shape_pseudo_energies(reactivities));
+
+        // The structures are scored without the pseudo-energies
+        subopt_folds(&model, constraints, energy_gap, |dots, _| {
+            self.eval_structure(sequences, dots, temp, salt, dangles)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hairpin_energies_match_viennarna() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let sequences = vec!["GGGGAAACCCC"];
+        let fold = TurnerBackend
+            .mfe(&sequences, "", 37.0, &salt, &dangles)
+            .unwrap();
+        assert_eq!(fold.get_dots(), "((((...))))");
+        assert_eq!(*fold.get_d_g(), -4.5);
+        assert_eq!(
+            TurnerBackend.eval_structure(&sequences, "((((...))))", 37.0, &salt, &dangles),
+            -4.5
+        );
+        assert_eq!(
+            TurnerBackend.eval_structure(&sequences, "...........", 37.0, &salt, &dangles),
+            0.0
+        );
+    }
+
+    #[test]
+    fn reactive_nucleotides_stay_unpaired() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let sequences = vec!["GGGGAAACCCC"];
+
+        // Protected nucleotides keep the hairpin, which is reported without the bonus
+        let protected = vec![Some(0.0); 11];
+        let fold = TurnerBackend
+            .mfe_with_reactivities(&sequences, "", &protected, 37.0, &salt, &dangles)
+            .unwrap();
+        assert_eq!(fold.get_dots(), "((((...))))");
+        assert_eq!(*fold.get_d_g(), -4.5);
+
+        let reactive = vec![Some(2.0); 11];
+        let fold = TurnerBackend
+            .mfe_with_reactivities(&sequences, "", &reactive, 37.0, &salt, &dangles)
+            .unwrap();
+        assert_eq!(fold.get_dots(), "...........");
+        assert_eq!(*fold.get_d_g(), 0.0);
+
+        // The hairpin falls out of the subopt band of the guided structures
+        let folds = TurnerBackend
+            .subopt_with_reactivities(&sequences, "", &reactive, 1.0, 37.0, &salt, &dangles);
+        assert!(folds.iter().all(|fold| fold.get_dots() != "((((...))))"));
+        assert_eq!(folds[0].get_dots(), "...........");
+        assert_eq!(*folds[0].get_d_g(), 0.0);
+    }
+
+    #[test]
+    fn local_folding_limits_the_base_pair_span() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let sequence = "GGGGAAACCCC";
+
+        // A window over the whole sequence is its global ensemble
+        let global = TurnerBackend
+            .partition_function(&vec![sequence], "", 37.0, &salt, &dangles)
+            .unwrap()
+            .unpaired_probabilities();
+        let local = TurnerBackend
+            .local_unpaired_probabilities(sequence, 20, 20, 37.0, &salt, &dangles)
+            .unwrap();
+        for (local, global) in local.iter().zip(&global) {
+            assert!((local - global).abs() < 1e-9);
+        }
+        assert!(local[0] < 0.1);
+
+        // Windows too short for the hairpin leave every nucleotide unpaired
+        let local = TurnerBackend
+            .local_unpaired_probabilities(sequence, 6, 6, 37.0, &salt, &dangles)
+            .unwrap();
+        assert_eq!(local, vec![1.0; 11]);
+    }
+
+    #[test]
+    fn subopt_energies_match_eval() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let sequences = vec!["ACUUCUAAUUUAUUCUAUUUAUUCGCGGAUAUGCAUAGGAGUGCUUCGAUGUCAU"];
+        let mfe = TurnerBackend
+            .mfe(&sequences, "", 37.0, &salt, &dangles)
+            .unwrap();
+        let folds = TurnerBackend.subopt(&sequences, "", 1.0, 37.0, &salt, &dangles);
+        assert_eq!(folds[0].get_d_g(), mfe.get_d_g());
+        for fold in &folds {
+            let energy =
+                TurnerBackend.eval_structure(&sequences, fold.get_dots(), 37.0, &salt, &dangles);
+            assert!((energy - fold.get_d_g()).abs() < 0.01);
+            assert!(*fold.get_d_g() <= mfe.get_d_g() + 1.0 + HYBRIDIZATION_PENALTY as f32);
+        }
+        let ensemble = TurnerBackend
+            .partition_function(&sequences, "", 37.0, &salt, &dangles)
+            .unwrap();
+        assert!(ensemble.d_g < *mfe.get_d_g());
+    }
+
+    #[test]
+    fn pair_probabilities_match_enumeration() {
+        let salt = SaltConditions::default();
+        for sequences in [vec!["GGGAAAUCCCAGCGAAAGCUA"], vec!["AGGAGGU", "ACCUCCUU"]] {
+            let dangles = DanglesSetting::new("all").unwrap();
+            let model = model(&sequences, 37.0, &salt, &dangles).unwrap();
+            let folder = Folder::new(&model, "", NO_LONELY_PAIRS).unwrap();
+            let matrices = folder.fill(&MinimumFreeEnergy);
+            let kt = model.params.kt;
+
+            // Boltzmann weights of every structure
+            let mut z = 0.0;
+            let mut expected = vec![vec![0.0; model.n + 1]; model.n + 1];
+            for (energy, pairs) in folder.subopt(&matrices, INF / 2) {
+                let weight = (-energy as f64 * 10.0 / kt).exp();
+                z += weight;
+                for (i, j) in pairs {
+                    expected[i][j] += weight;
+                }
+            }
+
+            let ensemble = TurnerBackend
+                .partition_function(&sequences, "", 37.0, &salt, &dangles)
+                .unwrap();
+            assert!((ensemble.d_g as f64 + kt * z.ln() / 1000.0).abs() < 0.01);
+            for (i, row) in expected.iter().enumerate().skip(1) {
+                for (j, weight) in row.iter().enumerate().skip(i + 1) {
+                    let probability = weight / z;
+                    assert!((ensemble.pair_probability(i, j) - probability).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cofold_includes_duplex_initiation() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let sequences = vec!["AAGGAGGU", "ACCUCCUUA"];
+        let folds = TurnerBackend.subopt(&sequences, "", 3.0, 37.0, &salt, &dangles);
+        assert!(!folds.is_empty());
+        for fold in &folds {
+            assert!(fold.get_dots().contains('&'));
+            let energy =
+                TurnerBackend.eval_structure(&sequences, fold.get_dots(), 37.0, &salt, &dangles);
+            assert!((energy - fold.get_d_g()).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn low_salt_destabilizes_stacks() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let sequences = vec!["GGGGAAACCCC"];
+        let energy = |salt: SaltConditions| {
+            TurnerBackend.eval_structure(&sequences, "((((...))))", 37.0, &salt, &dangles)
+        };
+
+        // Three stacks, each 0.27 kcal/mol less stable at 100 mM NaCl
+        let low_salt = SaltConditions::new(0.1, 0.0).unwrap();
+        assert!((energy(low_salt) - (-4.5 + 3.0 * 0.27)).abs() < 1e-4);
+        let magnesium = SaltConditions::new(0.1, 0.01).unwrap();
+        assert!(energy(magnesium) < energy(low_salt));
+        assert!(energy(magnesium) > energy(SaltConditions::default()));
+    }
+}
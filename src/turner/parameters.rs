@@ -0,0 +1,350 @@
+use crate::constants::DEFAULT_MONOVALENT;
+use crate::types::SaltConditions;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Energies are integers in dcal/mol like in ViennaRNA
+pub(crate) const INF: i32 = 10_000_000;
+pub(crate) const MAXLOOP: usize = 30;
+
+const K0: f64 = 273.15;
+const GAS_CONSTANT: f64 = 1.98717; // cal/(mol K)
+const SALT_ENTROPY: f64 = 0.368; // cal/(mol K) per stack and ln [Na+], SantaLucia (1998)
+const LXC37: f64 = 107.856; // Extrapolation of long loops
+const TURNER_2004: &str = include_str!("../../old_python/rna_turner2004.par");
+
+// Pair types are numbered CG, GC, GU, UG, AU, UA and NS (non-standard) from 1
+pub(crate) const NBPAIRS: usize = 7;
+
+/// Nucleotide code used to index the parameter tables: 0 is N, then A, C, G, U
+pub(crate) fn encode(base: u8) -> usize {
+    match base {
+        b'A' | b'a' => 1,
+        b'C' | b'c' => 2,
+        b'G' | b'g' => 3,
+        b'U' | b'u' | b'T' | b't' => 4,
+        _ => 0,
+    }
+}
+
+/// Pair type of two encoded nucleotides, 0 for pairs that can not form
+pub(crate) fn pair_type(x: usize, y: usize) -> usize {
+    const PAIRS: [[usize; 5]; 5] = [
+        [0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 5],
+        [0, 0, 0, 1, 0],
+        [0, 0, 2, 0, 3],
+        [0, 6, 0, 4, 0],
+    ];
+    PAIRS[x][y]
+}
+
+/// Pair type of the same pair read from the other side
+pub(crate) fn reverse_type(pair_type: usize) -> usize {
+    const REVERSED: [usize; 8] = [0, 2, 1, 4, 3, 6, 5, 7];
+    REVERSED[pair_type]
+}
+
+type Mismatch = [[[i32; 5]; 5]; 8];
+type Dangle = [[i32; 5]; 8];
+type Int11 = [[[[i32; 5]; 5]; 8]; 8];
+type Int21 = [[[[[i32; 5]; 5]; 5]; 8]; 8];
+type Int22 = [[[[[[i32; 5]; 5]; 5]; 5]; 8]; 8];
+type Cache = Mutex<Vec<((u32, u64), Arc<Parameters>)>>;
+
+/// Turner 2004 free energies scaled to a temperature
+pub(crate) struct Parameters {
+    pub stack: [[i32; 8]; 8],
+    pub mismatch_hairpin: Mismatch,
+    pub mismatch_interior: Mismatch,
+    pub mismatch_interior_1n: Mismatch,
+    pub mismatch_interior_23: Mismatch,
+    pub mismatch_multi: Mismatch,
+    pub mismatch_exterior: Mismatch,
+    pub dangle5: Dangle,
+    pub dangle3: Dangle,
+    pub int11: Box<Int11>,
+    pub int21: Box<Int21>,
+    pub int22: Box<Int22>,
+    pub hairpin: [i32; MAXLOOP + 1],
+    pub bulge: [i32; MAXLOOP + 1],
+    pub interior: [i32; MAXLOOP + 1],
+    pub ml_base: i32,
+    pub ml_closing: i32,
+    pub ml_intern: i32,
+    pub ninio: i32,
+    pub max_ninio: i32,
+    pub duplex_init: i32,
+    pub terminal_au: i32,
+    pub lxc: f64,
+    pub triloops: HashMap<String, i32>,
+    pub tetraloops: HashMap<String, i32>,
+    pub hexaloops: HashMap<String, i32>,
+    pub kt: f64, // cal/mol
+}
+
+/// Sections of a ViennaRNA parameter file (v2.0 format)
+struct ParameterFile {
+    tables: HashMap<String, Vec<i32>>,
+    loops: HashMap<String, Vec<(String, i32, i32)>>,
+}
+
+impl ParameterFile {
+    fn parse(contents: &str) -> Result<ParameterFile, String> {
+        let mut tables: HashMap<String, Vec<i32>> = HashMap::new();
+        let mut loops: HashMap<String, Vec<(String, i32, i32)>> = HashMap::new();
+        let mut section = String::new();
+
+        for line in contents.lines() {
+            let line = match line.find("/*") {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            if line.starts_with("##") {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('#') {
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if section.ends_with("loops") {
+                if fields.len() < 3 {
+                    return Err(format!("Malformed line in section {}: {}", section, line));
+                }
+                let energy = fields[1].parse::<i32>().map_err(|e| e.to_string())?;
+                let enthalpy = fields[2].parse::<i32>().map_err(|e| e.to_string())?;
+                loops.entry(section.clone()).or_default().push((
+                    fields[0].to_string(),
+                    energy,
+                    enthalpy,
+                ));
+            } else {
+                let values = tables.entry(section.clone()).or_default();
+                for field in fields {
+                    values.push(match field {
+                        "INF" => INF,
+                        _ => field.parse::<i32>().map_err(|_| {
+                            format!("Invalid value {} in section {}", field, section)
+                        })?,
+                    });
+                }
+            }
+        }
+
+        Ok(ParameterFile { tables, loops })
+    }
+
+    /// Free energies of a section at a temperature, extrapolated with the section enthalpies
+    fn scaled(&self, name: &str, factor: f64) -> std::vec::IntoIter<i32> {
+        let energies = &self.tables[name];
+        let enthalpies = &self.tables[&format!("{}_enthalpies", name)];
+        energies
+            .iter()
+            .zip(enthalpies)
+            .map(|(&dg, &dh)| rescale(dg, dh, factor))
+            .collect::<Vec<i32>>()
+            .into_iter()
+    }
+
+    fn scaled_loops(&self, name: &str, factor: f64) -> HashMap<String, i32> {
+        self.loops
+            .get(name)
+            .map(|loops| {
+                loops
+                    .iter()
+                    .map(|(sequence, dg, dh)| (sequence.clone(), rescale(*dg, *dh, factor)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn rescale(dg: i32, dh: i32, factor: f64) -> i32 {
+    if dg == INF {
+        return INF;
+    }
+    (dh as f64 - (dh - dg) as f64 * factor) as i32
+}
+
+fn mismatch(values: &mut impl Iterator<Item = i32>) -> Mismatch {
+    let mut table = [[[INF; 5]; 5]; 8];
+    for pair in table.iter_mut().skip(1) {
+        for row in pair.iter_mut() {
+            for value in row.iter_mut() {
+                *value = values.next().unwrap_or(INF);
+            }
+        }
+    }
+    table
+}
+
+fn dangle(values: &mut impl Iterator<Item = i32>) -> Dangle {
+    let mut table = [[INF; 5]; 8];
+    for pair in table.iter_mut().skip(1) {
+        for value in pair.iter_mut() {
+            *value = values.next().unwrap_or(INF);
+        }
+    }
+    table
+}
+
+fn loop_lengths(values: &mut impl Iterator<Item = i32>) -> [i32; MAXLOOP + 1] {
+    let mut table = [INF; MAXLOOP + 1];
+    for value in table.iter_mut() {
+        *value = values.next().unwrap_or(INF);
+    }
+    table
+}
+
+impl Parameters {
+    /// Turner 2004 parameters at a temperature in Celsius and ionic conditions. Parameter sets are
+    /// cached per temperature and salt.
+    pub(crate) fn at(temperature: f32, salt: &SaltConditions) -> Arc<Parameters> {
+        static FILE: OnceLock<ParameterFile> = OnceLock::new();
+        static CACHE: OnceLock<Cache> = OnceLock::new();
+
+        let cache = CACHE.get_or_init(|| Mutex::new(vec![]));
+        let mut cache = cache.lock().unwrap();
+        let monovalent = salt.monovalent_equivalent();
+        let key = (temperature.to_bits(), monovalent.to_bits());
+        if let Some((_, parameters)) = cache.iter().find(|(k, _)| *k == key) {
+            return parameters.clone();
+        }
+
+        let file = FILE.get_or_init(|| {
+            ParameterFile::parse(TURNER_2004).expect("The bundled Turner 2004 parameters are valid")
+        });
+        let parameters = Arc::new(Parameters::from_file(file, temperature as f64, monovalent));
+        cache.push((key, parameters.clone()));
+        parameters
+    }
+
+    /// Parameters at a temperature in Celsius and a monovalent salt concentration in mol/L.
+    /// The salt only corrects the stacking energies, with the entropy term of SantaLucia (1998).
+    fn from_file(file: &ParameterFile, temperature: f64, monovalent: f64) -> Parameters {
+        let factor = (temperature + K0) / (37.0 + K0);
+        let salt_entropy = SALT_ENTROPY * (monovalent / DEFAULT_MONOVALENT).ln();
+        let salt_stack = (-(temperature + K0) * salt_entropy / 10.0).round() as i32;
+
+        let mut stack = [[INF; 8]; 8];
+        let mut values = file.scaled("stack", factor);
+        for row in stack.iter_mut().skip(1) {
+            for value in row.iter_mut().skip(1) {
+                *value = match values.next() {
+                    Some(value) if value < INF => value + salt_stack,
+                    _ => INF,
+                };
+            }
+        }
+
+        let mut int11 = Box::new([[[[INF; 5]; 5]; 8]; 8]);
+        let mut values = file.scaled("int11", factor);
+        for outer in int11.iter_mut().skip(1) {
+            for inner in outer.iter_mut().skip(1) {
+                for row in inner.iter_mut() {
+                    for value in row.iter_mut() {
+                        *value = values.next().unwrap_or(INF);
+                    }
+                }
+            }
+        }
+
+        let mut int21 = Box::new([[[[[INF; 5]; 5]; 5]; 8]; 8]);
+        let mut values = file.scaled("int21", factor);
+        for outer in int21.iter_mut().skip(1) {
+            for inner in outer.iter_mut().skip(1) {
+                for x in inner.iter_mut() {
+                    for row in x.iter_mut() {
+                        for value in row.iter_mut() {
+                            *value = values.next().unwrap_or(INF);
+                        }
+                    }
+                }
+            }
+        }
+
+        // 2x2 loops are only tabulated for canonical pairs and A, C, G, U. Anything else
+        // (N or non-standard pairs) gets the least favourable tabulated energy.
+        let mut int22 = Box::new([[[[[[INF; 5]; 5]; 5]; 5]; 8]; 8]);
+        let mut values = file.scaled("int22", factor);
+        let mut highest = -INF;
+        for outer in int22.iter_mut().skip(1).take(NBPAIRS - 1) {
+            for inner in outer.iter_mut().skip(1).take(NBPAIRS - 1) {
+                let mut pair_highest = -INF;
+                for plane in inner.iter_mut().skip(1) {
+                    for matrix in plane.iter_mut().skip(1) {
+                        for row in matrix.iter_mut().skip(1) {
+                            for value in row.iter_mut().skip(1) {
+                                *value = values.next().unwrap_or(INF);
+                                pair_highest = pair_highest.max(*value);
+                            }
+                        }
+                    }
+                }
+                for (i, plane) in inner.iter_mut().enumerate() {
+                    for (j, matrix) in plane.iter_mut().enumerate() {
+                        for (k, row) in matrix.iter_mut().enumerate() {
+                            for (l, value) in row.iter_mut().enumerate() {
+                                if i * j * k * l == 0 {
+                                    *value = pair_highest;
+                                }
+                            }
+                        }
+                    }
+                }
+                highest = highest.max(pair_highest);
+            }
+        }
+        for (t1, outer) in int22.iter_mut().enumerate().skip(1) {
+            for (t2, inner) in outer.iter_mut().enumerate().skip(1) {
+                if t1 == NBPAIRS || t2 == NBPAIRS {
+                    *inner = [[[[highest; 5]; 5]; 5]; 5];
+                }
+            }
+        }
+
+        let ml = &file.tables["ML_params"];
+        let ninio = &file.tables["NINIO"];
+        let misc = &file.tables["Misc"];
+        let lxc = match misc.get(4) {
+            Some(&lxc) => lxc as f64 * factor,
+            None => LXC37 * factor,
+        };
+
+        Parameters {
+            stack,
+            mismatch_hairpin: mismatch(&mut file.scaled("mismatch_hairpin", factor)),
+            mismatch_interior: mismatch(&mut file.scaled("mismatch_interior", factor)),
+            mismatch_interior_1n: mismatch(&mut file.scaled("mismatch_interior_1n", factor)),
+            mismatch_interior_23: mismatch(&mut file.scaled("mismatch_interior_23", factor)),
+            mismatch_multi: mismatch(&mut file.scaled("mismatch_multi", factor)),
+            mismatch_exterior: mismatch(&mut file.scaled("mismatch_exterior", factor)),
+            dangle5: dangle(&mut file.scaled("dangle5", factor)),
+            dangle3: dangle(&mut file.scaled("dangle3", factor)),
+            int11,
+            int21,
+            int22,
+            hairpin: loop_lengths(&mut file.scaled("hairpin", factor)),
+            bulge: loop_lengths(&mut file.scaled("bulge", factor)),
+            interior: loop_lengths(&mut file.scaled("interior", factor)),
+            // ML_params lists energy/enthalpy pairs for unpaired bases, closing and branches
+            ml_base: rescale(ml[0], ml[1], factor),
+            ml_closing: rescale(ml[2], ml[3], factor),
+            ml_intern: rescale(ml[4], ml[5], factor),
+            ninio: rescale(ninio[0], ninio[1], factor),
+            max_ninio: ninio[2],
+            duplex_init: rescale(misc[0], misc[1], factor),
+            terminal_au: rescale(misc[2], misc[3], factor),
+            lxc,
+            triloops: file.scaled_loops("Triloops", factor),
+            tetraloops: file.scaled_loops("Tetraloops", factor),
+            hexaloops: file.scaled_loops("Hexaloops", factor),
+            kt: (temperature + K0) * GAS_CONSTANT,
+        }
+    }
+}
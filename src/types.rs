@@ -1,3 +1,8 @@
+use crate::constants::*;
+use std::cmp::min;
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct DanglesSetting {
     setting: String,
 }
@@ -25,6 +30,389 @@ impl DanglesSetting {
             _ => Err("Must pull default dangle setting"),
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.setting
+    }
+}
+
+/// Ionic conditions of the folding model, in mol/L
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SaltConditions {
+    pub monovalent: f64,
+    pub magnesium: f64,
+}
+
+impl SaltConditions {
+    pub fn new(monovalent: f64, magnesium: f64) -> Result<SaltConditions, &'static str> {
+        if monovalent > 0.0 && magnesium >= 0.0 {
+            Ok(SaltConditions {
+                monovalent,
+                magnesium,
+            })
+        } else {
+            Err("Salt concentrations must be positive")
+        }
+    }
+
+    /// Monovalent concentration with the same stabilizing effect, folding Mg2+ in with the
+    /// approximation of von Ahsen et al. (2001): [Na+] + 120 sqrt([Mg2+]) in mM
+    pub fn monovalent_equivalent(&self) -> f64 {
+        self.monovalent + 0.120 * (self.magnesium * 1000.0).sqrt()
+    }
+
+    /// Whether these are the conditions the Turner parameters were measured at
+    pub fn is_standard(&self) -> bool {
+        *self == SaltConditions::default()
+    }
+}
+
+impl Default for SaltConditions {
+    fn default() -> Self {
+        SaltConditions {
+            monovalent: DEFAULT_MONOVALENT,
+            magnesium: DEFAULT_MAGNESIUM,
+        }
+    }
+}
+
+/// Ligand-binding aptamer of a riboswitch: a hairpin or interior loop motif of the mRNA, in
+/// dot-bracket notation, that is stabilized by `binding_energy` (kcal/mol) when the ligand binds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aptamer {
+    pub sequence: String,
+    pub structure: String,
+    pub binding_energy: f64,
+}
+
+impl Aptamer {
+    pub fn new(
+        sequence: &str,
+        structure: &str,
+        binding_energy: f64,
+    ) -> Result<Aptamer, &'static str> {
+        let sequence = sequence.to_uppercase().replace('U', "T");
+        if sequence.is_empty() || sequence.len() != structure.len() {
+            return Err("The aptamer sequence and structure must have the same length");
+        }
+        if !sequence.chars().all(|c| "ACGT".contains(c)) {
+            return Err("The aptamer sequence contains non-nucleotide characters");
+        }
+        let mut depth = 0;
+        for c in structure.chars() {
+            depth += match c {
+                '(' => 1,
+                ')' => -1,
+                '.' => 0,
+                _ => return Err("The aptamer structure must be in dot-bracket notation"),
+            };
+            if depth < 0 {
+                return Err("The aptamer structure is unbalanced");
+            }
+        }
+        if depth != 0 || !structure.contains('(') {
+            return Err("The aptamer structure must be balanced and contain base pairs");
+        }
+        Ok(Aptamer {
+            sequence,
+            structure: structure.to_string(),
+            binding_energy,
+        })
+    }
+
+    /// 0-indexed positions where the aptamer sequence occurs in the mRNA
+    pub fn sites(&self, mrna: &str) -> Vec<usize> {
+        let mrna = mrna.to_uppercase().replace('U', "T");
+        (0..(mrna.len() + 1).saturating_sub(self.sequence.len()))
+            .filter(|&site| mrna[site..].starts_with(&self.sequence))
+            .collect()
+    }
+
+    /// Ligand binding energy of a structure of the mRNA (the first strand of `dots`): the
+    /// binding energy for every site folded into the aptamer motif
+    pub fn bound_energy(&self, mrna: &str, dots: &str) -> f64 {
+        let dots = dots.split('&').next().unwrap_or("");
+        let folded = self
+            .sites(mrna)
+            .into_iter()
+            .filter(|&site| dots.get(site..site + self.structure.len()) == Some(&self.structure))
+            .count();
+        folded as f64 * self.binding_energy
+    }
+
+    /// Hard constraints of an mRNA of `length` nt that fold the aptamer at `site`, on top of
+    /// `constraints`. None when the two overlap.
+    pub(crate) fn site_constraints(
+        &self,
+        length: usize,
+        site: usize,
+        constraints: &str,
+    ) -> Option<String> {
+        let mut symbols: Vec<char> = constraints.chars().filter(|&c| c != '&').collect();
+        symbols.resize(length.max(site + self.structure.len()), '.');
+        for (symbol, motif) in symbols[site..].iter_mut().zip(self.structure.chars()) {
+            if *symbol != '.' {
+                return None;
+            }
+            // The loops of the motif stay unpaired
+            *symbol = if motif == '.' { 'x' } else { motif };
+        }
+        Some(symbols.into_iter().collect())
+    }
+}
+
+/// Position-specific energies (kcal/mol) of the nucleotides around a start codon, such as the
+/// identity of the second codon or A-rich early codons. Positions are numbered from the start
+/// codon: +1 is its first nucleotide, -1 the nucleotide before it and +4..+6 the second codon.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextTable {
+    pub scores: Vec<(i64, [f64; 4])>, // Position with the energies of A, C, G and T there
+}
+
+impl ContextTable {
+    pub fn new(scores: Vec<(i64, [f64; 4])>) -> Result<ContextTable, &'static str> {
+        if scores.is_empty() {
+            return Err("The context table has no positions");
+        }
+        if scores.iter().any(|(position, _)| *position == 0) {
+            return Err("Context positions start at +1 for the start codon and -1 before it");
+        }
+        if scores
+            .iter()
+            .any(|(_, energies)| energies.iter().any(|e| !e.is_finite()))
+        {
+            return Err("The context table contains invalid energies");
+        }
+        let mut positions: Vec<i64> = scores.iter().map(|(position, _)| *position).collect();
+        positions.sort();
+        positions.dedup();
+        if positions.len() != scores.len() {
+            return Err("The context table lists a position twice");
+        }
+        Ok(ContextTable { scores })
+    }
+
+    /// Context energy of the start codon at start_pos (0-indexed) of the mRNA. Positions past
+    /// either end of the mRNA and ambiguous nucleotides contribute nothing.
+    pub fn energy(&self, mrna: &str, start_pos: usize) -> f64 {
+        let mrna = mrna.as_bytes();
+        self.scores
+            .iter()
+            .filter_map(|(position, energies)| {
+                let offset = if *position > 0 {
+                    position - 1
+                } else {
+                    *position
+                };
+                let nt = mrna.get(usize::try_from(start_pos as i64 + offset).ok()?)?;
+                match nt.to_ascii_uppercase() {
+                    b'A' => Some(energies[0]),
+                    b'C' => Some(energies[1]),
+                    b'G' => Some(energies[2]),
+                    b'T' | b'U' => Some(energies[3]),
+                    _ => None,
+                }
+            })
+            .sum()
+    }
+}
+
+/// anti-Shine-Dalgarno sequence of a host in [`ASD_PRESETS`], by species name ("Bacillus
+/// subtilis") or abbreviation ("B. subtilis"), ignoring case and punctuation
+pub fn asd_preset(name: &str) -> Option<&'static str> {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let name = normalize(name);
+    ASD_PRESETS.iter().find_map(|&(species, asd)| {
+        let words: Vec<&str> = species.split_whitespace().collect();
+        let abbreviation = match words[..] {
+            [genus, ref rest @ ..] if !rest.is_empty() => {
+                normalize(&format!("{}{}", &genus[..1], rest.join("")))
+            }
+            _ => normalize(species),
+        };
+        (name == normalize(species) || name == abbreviation).then_some(asd)
+    })
+}
+
+/// Fewest mismatches between an anti-Shine-Dalgarno sequence and the 16S rRNA 3' end of any
+/// preset host (ASD_FLANK followed by its aSD), over all ungapped placements of the aSD. Parts
+/// of the aSD that run past the 16S 3' end count as mismatches.
+pub fn asd_mismatches(asd: &str) -> usize {
+    let asd = asd.to_uppercase().replace('U', "T").into_bytes();
+    ASD_PRESETS
+        .iter()
+        .map(|(_, preset)| {
+            let tail = format!("{}{}", ASD_FLANK, preset).into_bytes();
+            (0..tail.len())
+                .map(|offset| {
+                    asd.iter()
+                        .enumerate()
+                        .filter(|&(i, &nt)| tail.get(offset + i) != Some(&nt))
+                        .count()
+                })
+                .min()
+                .unwrap_or(asd.len())
+        })
+        .min()
+        .unwrap_or(asd.len())
+}
+
+/// State of the mRNA in one prediction, which the prediction modes set for each call
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MrnaState {
+    pub ligand: Option<Aptamer>, // Aptamer with its ligand bound, folded by the mRNA when stable
+    pub unwound: Option<Range<usize>>, // nt (0-indexed) unwound by a terminating ribosome
+}
+
+impl MrnaState {
+    /// Ligand binding energy of an mRNA structure, zero without a bound ligand
+    pub fn ligand_energy(&self, mrna: &str, dots: &str) -> f64 {
+        self.ligand
+            .as_ref()
+            .map_or(0.0, |aptamer| aptamer.bound_energy(mrna, dots))
+    }
+
+    /// Constraints of the mRNA nucleotides in range (0-indexed), given for them or empty, with the
+    /// ones unwound by a terminating ribosome kept unpaired
+    pub fn unwound_constraints(&self, constraints: &str, range: Range<usize>) -> String {
+        let Some(unwound) = &self.unwound else {
+            return constraints.to_string();
+        };
+        let mut symbols: Vec<char> = constraints.chars().collect();
+        symbols.resize(range.len(), '.');
+        for i in unwound.start.max(range.start)..unwound.end.min(range.end) {
+            if symbols[i - range.start] == '.' {
+                symbols[i - range.start] = 'x';
+            }
+        }
+        symbols.into_iter().collect()
+    }
+}
+
+/// Tunable constants of the OSTIR free energy model. The defaults are the values
+/// from the calibration against the Salis2009 data set (see the calibration directory).
+#[derive(Clone, Debug)]
+pub struct ModelParameters {
+    pub beta: f64,
+    pub log_k: f64,
+    pub optimal_spacing: usize,
+    pub dg_spacing_push: [f64; 4],
+    pub dg_spacing_pull: [f64; 3],
+    pub upstream_cutoff: usize, // Number of nt upstream of the start codon considered for folding
+    pub downstream_cutoff: usize, // Number of nt from the start codon on considered for folding
+    pub full_transcript: bool,  // Fold the whole transcript instead of a window
+    pub standby_site_length: usize,
+    pub footprint: usize,
+    pub energy_cutoff: f32,
+    pub hybridization_penalty: f64,
+    pub max_kinetic_score: f64, // Predictions above it are flagged as slow folding
+    pub local_window: usize,    // Window of local folding over whole records
+    pub local_span: usize,      // Maximum base pair span of local folding
+    pub elongation_rate: f64,   // nt/s, for co-transcriptional folding
+    pub loading_time: f64,      // s from transcription of the start codon to ribosome loading
+    pub auto_dangles: bool,
+    pub dangles: DanglesSetting,
+    pub start_codon_energies: Vec<(String, f64)>,
+    pub temperature: f32, // Folding temperature in C. BETA is calibrated at DEFAULT_TEMP.
+    pub salt: SaltConditions,
+}
+
+impl ModelParameters {
+    /// Ratio of the absolute folding temperature to the calibration temperature
+    fn temperature_scale(&self) -> f64 {
+        (self.temperature as f64 + KELVIN) / (DEFAULT_TEMP as f64 + KELVIN)
+    }
+
+    /// Effective RT of the expression model, with BETA rescaled to the folding temperature
+    pub fn rt_eff(&self) -> f64 {
+        self.temperature_scale() / self.beta
+    }
+
+    /// Physical RT in kcal/mol at the folding temperature, for Boltzmann weighting of structures
+    pub fn rt(&self) -> f64 {
+        RT * self.temperature_scale()
+    }
+
+    pub fn k(&self) -> f64 {
+        self.log_k.exp()
+    }
+
+    /// Energy of the start codon, accepting both DNA and RNA codons
+    pub fn start_codon_energy(&self, codon: &str) -> Option<f64> {
+        let codon = codon.to_uppercase().replace("U", "T");
+        self.start_codon_energies
+            .iter()
+            .find(|(known, _)| *known == codon)
+            .map(|(_, energy)| *energy)
+    }
+
+    /// First nucleotide (0-indexed) of the folding window of the start codon at start_pos
+    pub fn window_start(&self, start_pos: usize) -> usize {
+        if self.full_transcript {
+            0
+        } else {
+            start_pos.saturating_sub(self.upstream_cutoff)
+        }
+    }
+
+    /// Nucleotides of an mRNA of mrna_len nt folded for the start codon at start_pos (0-indexed)
+    pub fn folding_window(&self, start_pos: usize, mrna_len: usize) -> Range<usize> {
+        let end = if self.full_transcript {
+            mrna_len
+        } else {
+            min(mrna_len, start_pos + self.downstream_cutoff)
+        };
+        self.window_start(start_pos)..end
+    }
+
+    /// Dangles used for a start codon, which depend on whether the folding window is truncated
+    pub fn dangles_for(&self, start_pos: usize) -> DanglesSetting {
+        if !self.auto_dangles {
+            return self.dangles.clone();
+        }
+        if self.window_start(start_pos) > 0 {
+            DanglesSetting::new("none").unwrap()
+        } else {
+            DanglesSetting::new("all").unwrap()
+        }
+    }
+}
+
+impl Default for ModelParameters {
+    fn default() -> Self {
+        ModelParameters {
+            beta: BETA,
+            log_k: LOGK,
+            optimal_spacing: OPTIMAL_SPACING,
+            dg_spacing_push: DG_SPACING_PUSH,
+            dg_spacing_pull: DG_SPACING_PULL,
+            upstream_cutoff: CUTOFF,
+            downstream_cutoff: CUTOFF,
+            full_transcript: false,
+            standby_site_length: STANDBY_SITE_LEN,
+            footprint: FOOTPRINT,
+            energy_cutoff: ENERGY_CUTOFF,
+            hybridization_penalty: HYBRIDIZATION_PENALTY,
+            max_kinetic_score: MAX_KINETIC_SCORE,
+            local_window: LOCAL_WINDOW,
+            local_span: LOCAL_SPAN,
+            elongation_rate: ELONGATION_RATE,
+            loading_time: LOADING_TIME,
+            auto_dangles: AUTO_DANGLES,
+            dangles: DanglesSetting::new(DEFAULT_DANGLES).unwrap(),
+            start_codon_energies: START_CODON_ENERGIES
+                .iter()
+                .map(|(codon, energy)| (codon.to_string(), *energy))
+                .collect(),
+            temperature: DEFAULT_TEMP,
+            salt: SaltConditions::default(),
+        }
+    }
 }
 
 // Represents a fold result from ViennaRNA
@@ -35,7 +423,7 @@ pub enum FoldResult<'a> {
 }
 
 pub struct UnknownFoldResult<'a> {
-    pub seqs: Option<&'a Vec<&'a str>>,
+    pub seqs: Option<&'a [&'a str]>,
     pub d_g: f32,
     pub dots: String,
     pub bp_x: Vec<usize>,
@@ -94,7 +482,7 @@ impl<'a> FoldResult<'_> {
     }
 
     pub fn create(
-        sequences: Option<&'a Vec<&'a str>>,
+        sequences: Option<&'a [&'a str]>,
         d_g: f32,
         dots: String,
         bp_x: Vec<usize>,
@@ -141,3 +529,61 @@ impl<'a> FoldResult<'_> {
         }
     }
 }
+
+/// Boltzmann ensemble of a set of strands from the partition function. Positions are 1-indexed
+/// over the concatenated strands.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ensemble {
+    /// Ensemble free energy in kcal/mol
+    pub d_g: f32,
+    /// Probability of every base pair, symmetric with an unused row and column 0
+    pub probabilities: Vec<Vec<f64>>,
+}
+
+impl Ensemble {
+    /// Builds the ensemble of `length` nucleotides from (i, j, probability) base pairs
+    pub fn new(
+        d_g: f32,
+        length: usize,
+        pairs: impl IntoIterator<Item = (usize, usize, f64)>,
+    ) -> Self {
+        let mut probabilities = vec![vec![0.0; length + 1]; length + 1];
+        for (i, j, probability) in pairs {
+            probabilities[i][j] = probability;
+            probabilities[j][i] = probability;
+        }
+        Ensemble { d_g, probabilities }
+    }
+
+    pub fn len(&self) -> usize {
+        self.probabilities.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn pair_probability(&self, i: usize, j: usize) -> f64 {
+        self.probabilities[i][j]
+    }
+
+    /// Probability that each nucleotide is unpaired, starting with position 1
+    pub fn unpaired_probabilities(&self) -> Vec<f64> {
+        self.probabilities
+            .iter()
+            .skip(1)
+            .map(|row| (1.0 - row.iter().sum::<f64>()).clamp(0.0, 1.0))
+            .collect()
+    }
+
+    /// Mean probability that the nucleotides from..=to are unpaired, None when they are not a
+    /// region of the ensemble
+    pub fn accessibility(&self, from: usize, to: usize) -> Option<f64> {
+        if from == 0 || from > to || to > self.len() {
+            return None;
+        }
+        let unpaired = self.unpaired_probabilities();
+        let region = &unpaired[from - 1..to];
+        Some(region.iter().sum::<f64>() / region.len() as f64)
+    }
+}
@@ -1,50 +1,287 @@
-use crate::types::{DanglesSetting, FoldResult};
+use crate::constants::{HYBRIDIZATION_PENALTY, NO_LONELY_PAIRS, SHAPE_INTERCEPT, SHAPE_SLOPE};
+use crate::folding::{dots_to_coordinates, round_energy, FoldingBackend};
+use crate::types::{DanglesSetting, Ensemble, FoldResult, SaltConditions};
 use librna_sys::{
-    vrna_eval_structure, vrna_fold_compound, vrna_fold_compound_t, vrna_md_set_default, vrna_md_t,
-    vrna_mfe, vrna_subopt_cb,
+    vrna_eval_structure, vrna_exp_params_rescale, vrna_fold_compound, vrna_fold_compound_free,
+    vrna_fold_compound_t, vrna_hc_add_from_db, vrna_md_set_default, vrna_md_t, vrna_mfe, vrna_pf,
+    vrna_plist_from_probs, vrna_probs_window, vrna_sc_add_SHAPE_deigan, vrna_subopt_cb,
+    VRNA_ANY_LOOP, VRNA_CONSTRAINT_DB_DEFAULT, VRNA_OPTION_DEFAULT, VRNA_OPTION_MFE,
+    VRNA_OPTION_WINDOW, VRNA_PROBS_WINDOW_UP,
 };
 use std::error::Error;
-use std::ffi::{c_char, c_double, c_float, c_void, CStr};
+use std::ffi::{c_char, c_double, c_float, c_int, c_uint, c_void, CStr, CString};
 use std::mem::MaybeUninit;
+use std::ptr::NonNull;
 
-// Fold compound struct with safeguards --------
-struct FoldCompound {
-    c: *mut vrna_fold_compound_t,
+/// What ViennaRNA reports for structures it can not evaluate, in kcal/mol
+const INVALID_ENERGY: f32 = 100000.0;
+
+/// Base pairs less likely than this are left out of ensembles
+const MIN_PAIR_PROBABILITY: f64 = 1e-6;
+
+extern "C" {
+    // Pair lists are allocated by ViennaRNA and released with the C allocator
+    fn free(ptr: *mut c_void);
 }
 
+// Fold compound ----------------
+
+/// A ViennaRNA fold compound for one set of strands and model settings. The same compound can be
+/// used for any number of folding operations on its sequences, which avoids rebuilding the
+/// energy parameters and DP matrices each time. It is freed when dropped.
+pub struct FoldCompound {
+    c: NonNull<vrna_fold_compound_t>,
+    length: usize,      // Concatenated length with strand separators
+    nucleotides: usize, // Concatenated length without strand separators
+}
+
+// ViennaRNA keeps no thread-local state in a compound, so it can move between threads. All calls
+// take &mut self, so it is never used from two threads at once.
+unsafe impl Send for FoldCompound {}
+
 impl FoldCompound {
-    fn new(sequences: &Vec<&str>, _constraints: &str, dangles: &DanglesSetting, temp: f32) -> Self {
-        let sequence;
-        if sequences.len() > 1 {
-            sequence = sequences.join("&").replace("T", "U").to_uppercase()
-        } else {
-            sequence = sequences[0].replace("T", "U").to_uppercase()
+    /// Creates a compound for the strands, with hard constraints in dot-bracket notation.
+    /// Constraints shorter than the strands are padded with dots.
+    pub fn new(
+        sequences: &[&str],
+        constraints: &str,
+        dangles: &DanglesSetting,
+        temp: f32,
+        salt: &SaltConditions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let sequence = sequences.join("&").replace('T', "U").to_uppercase();
+        let length = sequence.len();
+        let nucleotides = sequences.iter().map(|sequence| sequence.len()).sum();
+        let sequence = CString::new(sequence)?;
+
+        let md = model_details(dangles, temp, salt)?;
+        let c = unsafe { vrna_fold_compound(sequence.as_ptr(), &md, VRNA_OPTION_MFE) };
+        let c = NonNull::new(c).ok_or("ViennaRNA could not create a fold compound")?;
+        let compound = FoldCompound {
+            c,
+            length,
+            nucleotides,
+        };
+
+        if !constraints.is_empty() {
+            let mut constraints = constraints.replace('&', "");
+            if constraints.len() < nucleotides {
+                constraints.push_str(&".".repeat(nucleotides - constraints.len()));
+            }
+            let constraints = CString::new(constraints)?;
+            let added = unsafe {
+                vrna_hc_add_from_db(
+                    compound.c.as_ptr(),
+                    constraints.as_ptr(),
+                    VRNA_CONSTRAINT_DB_DEFAULT,
+                )
+            };
+            if added == 0 {
+                return Err("ViennaRNA rejected the folding constraints".into());
+            }
         }
 
+        Ok(compound)
+    }
+
+    /// Creates a compound for local folding of a single strand, in windows of `window` nt with
+    /// base pairs spanning at most `span` nt
+    pub fn new_local(
+        sequence: &str,
+        window: usize,
+        span: usize,
+        dangles: &DanglesSetting,
+        temp: f32,
+        salt: &SaltConditions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let nucleotides = sequence.len();
+        let sequence = CString::new(sequence.replace('T', "U").to_uppercase())?;
+
+        let mut md = model_details(dangles, temp, salt)?;
+        md.window_size = window.min(nucleotides) as i32;
+        md.max_bp_span = span.min(window).min(nucleotides) as i32;
+
+        let c = unsafe { vrna_fold_compound(sequence.as_ptr(), &md, VRNA_OPTION_WINDOW) };
+        let c = NonNull::new(c).ok_or("ViennaRNA could not create a fold compound")?;
+        Ok(FoldCompound {
+            c,
+            length: nucleotides,
+            nucleotides,
+        })
+    }
+
+    /// Adds SHAPE reactivities of the concatenated strands as Deigan soft constraints. Unmeasured
+    /// nucleotides are passed to ViennaRNA as negative reactivities, which it ignores.
+    pub fn add_shape_reactivities(
+        &mut self,
+        reactivities: &[Option<f64>],
+    ) -> Result<(), Box<dyn Error>> {
+        // ViennaRNA reads the reactivities from position 1
+        let mut values = vec![-999.0 as c_double; self.nucleotides + 1];
+        for (value, reactivity) in values[1..].iter_mut().zip(reactivities) {
+            *value = reactivity.unwrap_or(-999.0);
+        }
+        let added = unsafe {
+            vrna_sc_add_SHAPE_deigan(
+                self.c.as_ptr(),
+                values.as_ptr(),
+                SHAPE_SLOPE,
+                SHAPE_INTERCEPT,
+                VRNA_OPTION_DEFAULT,
+            )
+        };
+        if added == 0 {
+            return Err("ViennaRNA rejected the SHAPE reactivities".into());
+        }
+        Ok(())
+    }
+
+    /// Minimum free energy and its structure
+    pub fn mfe(&mut self) -> Result<(f32, String), Box<dyn Error>> {
+        let mut structure = vec![0u8; self.length + 1];
+        let energy = unsafe { vrna_mfe(self.c.as_ptr(), structure.as_mut_ptr().cast::<c_char>()) };
+        let dots = CStr::from_bytes_until_nul(&structure)?
+            .to_str()?
+            .to_string();
+        Ok((energy, dots))
+    }
+
+    /// Energies and structures within `delta` dcal/mol of the MFE, in the order ViennaRNA
+    /// reports them
+    pub fn subopt(&mut self, delta: i32) -> Vec<(f32, String)> {
+        let mut structures: Vec<(f32, String)> = vec![];
+        let data: *mut c_void = &mut structures as *mut _ as *mut c_void;
         unsafe {
-            let mut md = MaybeUninit::<vrna_md_t>::uninit();
-            let md_ptr = md.as_mut_ptr();
-            vrna_md_set_default(md_ptr);
-            let mut initialized_md = md.assume_init();
+            vrna_subopt_cb(self.c.as_ptr(), delta, Some(subopt_cb_fun as _), data);
+        }
+        structures
+    }
 
-            initialized_md.temperature = temp as c_double;
+    /// Free energy of a structure in dot-bracket notation
+    pub fn eval(&mut self, dots: &str) -> f32 {
+        let Ok(dots) = CString::new(dots.replace('&', "")) else {
+            return INVALID_ENERGY;
+        };
+        unsafe { vrna_eval_structure(self.c.as_ptr(), dots.as_ptr()) }
+    }
 
-            let _dangles_int = dangles.as_int();
-            match dangles.as_int() {
-                Ok(t) => initialized_md.dangles = t,
-                Err(_e) => {}
+    /// Ensemble free energy and base pair probabilities
+    pub fn partition_function(&mut self) -> Ensemble {
+        let mut pairs = vec![];
+        let d_g = unsafe {
+            // Scale the Boltzmann factors with the MFE to avoid overflows on long sequences
+            let mut mfe_energy = vrna_mfe(self.c.as_ptr(), std::ptr::null_mut()) as c_double;
+            vrna_exp_params_rescale(self.c.as_ptr(), &mut mfe_energy);
+            let d_g = vrna_pf(self.c.as_ptr(), std::ptr::null_mut()) as f32;
+
+            // The pair list ends with an entry at position 0
+            let list = vrna_plist_from_probs(self.c.as_ptr(), MIN_PAIR_PROBABILITY);
+            if !list.is_null() {
+                let mut entry = list;
+                while (*entry).i > 0 && (*entry).j > 0 {
+                    let (i, j) = ((*entry).i as usize, (*entry).j as usize);
+                    if i <= self.nucleotides && j <= self.nucleotides {
+                        pairs.push((i, j, (*entry).p as f64));
+                    }
+                    entry = entry.add(1);
+                }
+                free(list as *mut c_void);
             }
+            d_g
+        };
+        Ensemble::new(d_g, self.nucleotides, pairs)
+    }
 
-            let c = vrna_fold_compound(sequence.as_ptr() as *const i8, &initialized_md, 1 as u32);
-            // TODO: Add constraints
-
-            FoldCompound { c }
+    /// Probability that each nucleotide is unpaired, averaged over the windows of a compound
+    /// created with [`FoldCompound::new_local`]
+    pub fn local_unpaired_probabilities(&mut self) -> Result<Vec<f64>, Box<dyn Error>> {
+        // ViennaRNA reports the positions from 1
+        let mut unpaired = vec![0.0; self.nucleotides + 1];
+        let data: *mut c_void = &mut unpaired as *mut _ as *mut c_void;
+        let done = unsafe {
+            vrna_probs_window(
+                self.c.as_ptr(),
+                1,
+                VRNA_PROBS_WINDOW_UP,
+                Some(unpaired_cb_fun as _),
+                data,
+            )
+        };
+        if done == 0 {
+            return Err("ViennaRNA could not compute the local unpaired probabilities".into());
         }
+        unpaired.remove(0);
+        Ok(unpaired)
+    }
+}
+
+impl Drop for FoldCompound {
+    fn drop(&mut self) {
+        unsafe { vrna_fold_compound_free(self.c.as_ptr()) }
+    }
+}
+
+/// Model settings shared by all compounds. Salt corrections need ViennaRNA 2.6 or later.
+fn model_details(
+    dangles: &DanglesSetting,
+    temp: f32,
+    salt: &SaltConditions,
+) -> Result<vrna_md_t, Box<dyn Error>> {
+    let mut md = unsafe {
+        let mut md = MaybeUninit::<vrna_md_t>::uninit();
+        vrna_md_set_default(md.as_mut_ptr());
+        md.assume_init()
+    };
+    md.temperature = temp as c_double;
+    // ViennaRNA only corrects for monovalent ions, so Mg2+ is folded into an equivalent
+    #[cfg(vienna_salt)]
+    {
+        md.salt = salt.monovalent_equivalent() as c_double;
+    }
+    #[cfg(not(vienna_salt))]
+    if !salt.is_standard() {
+        return Err("Salt conditions need ViennaRNA 2.6 or later; rebuild OSTIR against it".into());
+    }
+    md.noLP = NO_LONELY_PAIRS as i32;
+    if let Ok(dangles) = dangles.as_int() {
+        md.dangles = dangles;
+    }
+    Ok(md)
+}
+
+unsafe extern "C" fn unpaired_cb_fun(
+    pr: *mut c_double,
+    pr_size: c_int,
+    i: c_int,
+    _max: c_int,
+    kind: c_uint,
+    data: *mut c_void,
+) {
+    // Only the totals over all loop types are kept, pr[1] being nucleotide i itself
+    if kind & VRNA_PROBS_WINDOW_UP == 0 || kind & VRNA_ANY_LOOP != VRNA_ANY_LOOP || pr_size < 1 {
+        return;
+    }
+    let data: &mut Vec<f64> = unsafe { &mut *(data as *mut Vec<f64>) };
+    if let Some(unpaired) = data.get_mut(i as usize) {
+        *unpaired = unsafe { *pr.add(1) };
+    }
+}
+
+unsafe extern "C" fn subopt_cb_fun(x: *const c_char, y: c_float, z: *mut c_void) {
+    // ViennaRNA signals the end of the list with a null structure
+    if x.is_null() {
+        return;
+    }
+
+    let data: &mut Vec<(f32, String)> = unsafe { &mut *(z as *mut Vec<(f32, String)>) };
+    if let Ok(dots) = unsafe { CStr::from_ptr(x) }.to_str() {
+        data.push((y, dots.to_string()));
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use pyo3::prelude::*;
     use pyo3::types::IntoPyDict;
 
@@ -68,196 +305,339 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn fold_compound_is_reusable() {
+        let dangles = DanglesSetting::new("all").unwrap();
+        let salt = SaltConditions::default();
+        let mut compound = FoldCompound::new(&["GGGGAAACCCC"], "", &dangles, 37.0, &salt).unwrap();
+        let (energy, dots) = compound.mfe().unwrap();
+        assert_eq!(dots, "((((...))))");
+        assert_eq!(round_energy(energy, 2), -4.5);
+        for _ in 0..3 {
+            assert_eq!(round_energy(compound.eval(&dots), 2), -4.5);
+        }
+        assert_eq!(compound.eval("..........."), 0.0);
+
+        // Compounds can be handed to worker threads
+        let energy = std::thread::spawn(move || compound.eval("((((...))))"))
+            .join()
+            .unwrap();
+        assert_eq!(round_energy(energy, 2), -4.5);
+    }
 }
 
 // MFE ------------
 
 pub fn mfe<'a>(
-    sequences: &'a Vec<&'a str>,
+    sequences: &'a [&'a str],
     constraints: &'_ str,
     temp: f32,
+    salt: &SaltConditions,
     dangles: &'_ DanglesSetting,
 ) -> Result<FoldResult<'a>, Box<dyn Error>> {
-    // @TODO: Add constraints option
-
-    let dot_vec = vec![0; sequences.join("&").len() + 1];
-    let dot_ptr = dot_vec.as_ptr() as *mut i8;
-    let fold_compound = FoldCompound::new(sequences, constraints, dangles, temp);
-    let result;
-    unsafe {
-        result = vrna_mfe(fold_compound.c, dot_ptr);
-    }
-    let dot_string = std::str::from_utf8(&dot_vec).expect("TODO: Handle invalid UTF-8");
-    let coordinates = dots_to_coordinates(dot_string);
+    let mut fold_compound = FoldCompound::new(sequences, constraints, dangles, temp, salt)?;
+    let (energy, dots) = fold_compound.mfe()?;
+    let coordinates = dots_to_coordinates(&dots)?;
 
     return Ok(FoldResult::create(
         Some(sequences),
-        result,
-        dot_string.to_string(),
+        round_energy(energy, 2),
+        dots,
         coordinates.0,
         coordinates.1,
     ));
 }
 
-// Subopt ----------------
-unsafe extern "C" fn subopt_cb_fun(x: *const c_char, y: c_float, z: *mut c_void) {
-    let optional_char = x.as_ref(); // Catch a returned null pointer
-    match optional_char {
-        Some(_t) => {}
-        None => return,
-    }
-
-    let data: &mut Vec<FoldResult> = unsafe { &mut *(z as *mut Vec<FoldResult>) };
+/// MFE structure guided by SHAPE reactivities, with the energy of the structure itself
+pub fn mfe_with_reactivities<'a>(
+    sequences: &'a [&'a str],
+    constraints: &'_ str,
+    reactivities: &[Option<f64>],
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &'_ DanglesSetting,
+) -> Result<FoldResult<'a>, Box<dyn Error>> {
+    let mut fold_compound = FoldCompound::new(sequences, constraints, dangles, temp, salt)?;
+    fold_compound.add_shape_reactivities(reactivities)?;
+    let (_, dots) = fold_compound.mfe()?;
+    let energy = eval_structure(sequences, &dots, temp, salt, dangles);
+    let coordinates = dots_to_coordinates(&dots)?;
 
-    let placeholder = CStr::from_ptr(x);
-    let dots_string = placeholder.to_str().unwrap();
-    let coordinates = dots_to_coordinates(dots_string);
-    let result = FoldResult::create(
-        None,
-        y,
-        dots_string.to_string(),
+    Ok(FoldResult::create(
+        Some(sequences),
+        energy,
+        dots,
         coordinates.0,
         coordinates.1,
-    );
-
-    data.push(result);
+    ))
 }
 
+// Subopt ----------------
+
 pub fn subopt<'a>(
-    sequences: &'a Vec<&'a str>,
+    sequences: &'a [&'a str],
     constraints: &'_ str,
     energy_gap: f32,
     temp: f32,
+    salt: &SaltConditions,
     dangles: &'_ DanglesSetting,
 ) -> Vec<FoldResult<'a>> {
-    // error if temp < 0
-    // error if dangles no 'all', 'some', or 'none'
     // energy_gap in kcal/mol
 
-    let fold_compound = FoldCompound::new(sequences, constraints, dangles, temp);
-    let _vienna_temperature = (temp * 100 as f32).round() as i32;
-
-    let mut resultholder: Vec<FoldResult> = vec![];
-    let holder_ptr: *mut c_void = &mut resultholder as *mut _ as *mut c_void;
+    let Ok(mut fold_compound) = FoldCompound::new(sequences, constraints, dangles, temp, salt)
+    else {
+        return vec![];
+    };
+    subopt_folds(sequences, &mut fold_compound, energy_gap, |energy, _| {
+        round_energy(energy, 3)
+    })
+}
 
-    let hybridization_penalty = 2.481 as f32;
+/// Subopt structures guided by SHAPE reactivities, with the energies of the structures themselves
+pub fn subopt_with_reactivities<'a>(
+    sequences: &'a [&'a str],
+    constraints: &'_ str,
+    reactivities: &[Option<f64>],
+    energy_gap: f32,
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &'_ DanglesSetting,
+) -> Vec<FoldResult<'a>> {
+    let Ok(mut fold_compound) = FoldCompound::new(sequences, constraints, dangles, temp, salt)
+    else {
+        return vec![];
+    };
+    if fold_compound.add_shape_reactivities(reactivities).is_err() {
+        return vec![];
+    }
+    subopt_folds(sequences, &mut fold_compound, energy_gap, |_, dots| {
+        eval_structure(sequences, dots, temp, salt, dangles)
+    })
+}
 
-    let _energy_gap_adjusted = (energy_gap + hybridization_penalty) * 100.0;
-    let energy_gap_rounded: i32 = (energy_gap as f32).round() as i32;
+/// Subopt structures of a compound, scored by `energy` from the energy ViennaRNA reports for them
+/// and their dots
+fn subopt_folds<'a>(
+    sequences: &[&str],
+    fold_compound: &mut FoldCompound,
+    energy_gap: f32,
+    energy: impl Fn(f32, &str) -> f32,
+) -> Vec<FoldResult<'a>> {
+    let hybridization_penalty = HYBRIDIZATION_PENALTY as f32;
+
+    let energy_gap_adjusted = ((energy_gap + hybridization_penalty) * 100.0) as i32; // dcal/mol
+
+    let mut resultholder: Vec<FoldResult> = fold_compound
+        .subopt(energy_gap_adjusted)
+        .into_iter()
+        .filter_map(|(vienna_energy, dots)| {
+            let coordinates = dots_to_coordinates(&dots).ok()?;
+            Some(FoldResult::create(
+                None,
+                energy(vienna_energy, &dots),
+                dots,
+                coordinates.0,
+                coordinates.1,
+            ))
+        })
+        .collect();
 
-    unsafe {
-        vrna_subopt_cb(
-            fold_compound.c,
-            energy_gap_rounded,
-            Some(subopt_cb_fun as _),
-            holder_ptr,
-        );
+    // Earlier vienna versions would report co-folds without any actual binding between the strands
+    if sequences.len() > 1 {
+        let first_strand_len = sequences[0].len();
+        resultholder.retain(|fold| fold.get_bp_y().iter().any(|&y| y > first_strand_len));
     }
 
-    resultholder.sort_by(|b, a| b.get_d_g().partial_cmp(&a.get_d_g()).unwrap());
+    // Vienna breaks energy ties differently between platforms, so use the structure as the tiebreaker
+    resultholder.sort_by(|a, b| {
+        a.get_d_g()
+            .partial_cmp(b.get_d_g())
+            .unwrap()
+            .then_with(|| a.get_dots().cmp(b.get_dots()))
+    });
 
     return resultholder;
 }
 
+// Partition function ----------------
+
+/// Ensemble free energy of the sequences in kcal/mol and their base pair probabilities
+pub fn partition_function(
+    sequences: &[&str],
+    constraints: &str,
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &DanglesSetting,
+) -> Result<Ensemble, Box<dyn Error>> {
+    let mut fold_compound = FoldCompound::new(sequences, constraints, dangles, temp, salt)?;
+    let mut ensemble = fold_compound.partition_function();
+    ensemble.d_g = round_energy(ensemble.d_g, 2);
+    Ok(ensemble)
+}
+
+/// Ensemble of the sequences guided by SHAPE reactivities, with the pseudo-energies in its free
+/// energy
+pub fn partition_function_with_reactivities(
+    sequences: &[&str],
+    constraints: &str,
+    reactivities: &[Option<f64>],
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &DanglesSetting,
+) -> Result<Ensemble, Box<dyn Error>> {
+    let mut fold_compound = FoldCompound::new(sequences, constraints, dangles, temp, salt)?;
+    fold_compound.add_shape_reactivities(reactivities)?;
+    let mut ensemble = fold_compound.partition_function();
+    ensemble.d_g = round_energy(ensemble.d_g, 2);
+    Ok(ensemble)
+}
+
+/// Unpaired probability of every nucleotide from RNAplfold-style local folding
+pub fn local_unpaired_probabilities(
+    sequence: &str,
+    window: usize,
+    span: usize,
+    temp: f32,
+    salt: &SaltConditions,
+    dangles: &DanglesSetting,
+) -> Result<Vec<f64>, Box<dyn Error>> {
+    if sequence.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut fold_compound = FoldCompound::new_local(sequence, window, span, dangles, temp, salt)?;
+    fold_compound.local_unpaired_probabilities()
+}
+
 // Evaluate Fold for Energy ----------------
 pub fn eval_structure(
-    sequences: &Vec<&str>,
+    sequences: &[&str],
     dots: &str,
     temp: f32,
+    salt: &SaltConditions,
     dangles: &DanglesSetting,
 ) -> f32 {
-    let adj_dots = dots.replace("&", "");
-    let fold_compound = FoldCompound::new(sequences, "", dangles, temp);
+    let Ok(mut fold_compound) = FoldCompound::new(sequences, "", dangles, temp, salt) else {
+        return INVALID_ENERGY;
+    };
 
-    let energy: c_float;
-    unsafe {
-        energy = vrna_eval_structure(fold_compound.c, adj_dots.as_ptr() as *const i8);
-    }
-
-    return energy;
+    return round_energy(fold_compound.eval(dots), 2);
 }
 
-// Utilities ----------------
-
-pub fn coordinates_to_dots(strands: &Vec<&str>, bp_x: &Vec<usize>, bp_y: &Vec<usize>) -> String {
-    let bp_x: Vec<usize> = bp_x.iter().map(|&pos| pos - 1).collect(); // Shift so that 1st position is 0
-    let bp_y: Vec<usize> = bp_y.iter().map(|&pos| pos - 1).collect(); // Shift so that 1st position is 0
-
-    let mut bracket_notation = Vec::new();
-    let mut counter = 0;
-
-    for (strand_number, &seq) in strands.iter().enumerate() {
-        let seq_len = seq.len();
-        if strand_number > 0 {
-            bracket_notation.push('&');
-        }
-        for pos in counter..(seq_len + counter) {
-            if bp_x.contains(&pos) {
-                bracket_notation.push('(');
-            } else if bp_y.contains(&pos) {
-                bracket_notation.push(')');
-            } else {
-                bracket_notation.push('.');
-            }
-        }
-        counter += seq_len;
+// Backend ----------------
+
+/// Folds with the ViennaRNA library. This is the default backend.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ViennaBackend;
+
+impl FoldingBackend for ViennaBackend {
+    fn mfe<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        mfe(sequences, constraints, temp, salt, dangles)
     }
 
-    bracket_notation.iter().collect::<String>()
-}
-
-pub fn dots_to_coordinates(dots_string: &str) -> (Vec<usize>, Vec<usize>) {
-    let mut bp_x: Vec<usize> = vec![];
-    let _unpaired_x_index: Vec<usize> = vec![];
-    let _unpaired_x_pos: Vec<usize> = vec![];
-    let mut bp_y: Vec<usize> = vec![];
-
-    let _i = 1; // Dot positions are 1 indexed
-    let _x_counter = 0;
-    let _strand_count = 0;
-
-    let mut _last_x_pos: usize;
-    let mut _last_x_index: usize;
+    fn subopt<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        energy_gap: f32,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        subopt(sequences, constraints, energy_gap, temp, salt, dangles)
+    }
 
-    for _ in 0..dots_string.matches(")").count() {
-        bp_y.push(0); // Placeholder value to be replaced later
+    fn eval_structure(
+        &self,
+        sequences: &[&str],
+        dots: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> f32 {
+        eval_structure(sequences, dots, temp, salt, dangles)
     }
 
-    let mut last_nt_x_list: Vec<usize> = Vec::new();
-    let mut num_strands = 0;
+    fn partition_function(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        partition_function(sequences, constraints, temp, salt, dangles)
+    }
 
-    for (pos, letter) in dots_string.chars().enumerate() {
-        match letter {
-            '.' => {}
-            '(' => {
-                bp_x.push((pos - num_strands).try_into().unwrap());
-                last_nt_x_list.push(pos - num_strands);
-            }
-            ')' => {
-                let nt_x = last_nt_x_list.pop().unwrap(); // nt_x is list of "(" except last entry
-                let nt_x_pos = bp_x
-                    .iter()
-                    .position(|&x| x == nt_x.try_into().unwrap())
-                    .unwrap();
-                bp_y[nt_x_pos] = (pos - num_strands + 2).try_into().unwrap();
-            }
-            '&' => {
-                num_strands += 1;
-            }
-            _ => {
-                println!("Error! Invalid character in bracket notation.");
-            }
-        }
+    fn mfe_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<FoldResult<'a>, Box<dyn Error>> {
+        mfe_with_reactivities(sequences, constraints, reactivities, temp, salt, dangles)
     }
 
-    if !last_nt_x_list.is_empty() {
-        println!("Error! Leftover unpaired nucleotides when converting from bracket notation to numbered base pairs.");
+    fn partition_function_with_reactivities(
+        &self,
+        sequences: &[&str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Ensemble, Box<dyn Error>> {
+        partition_function_with_reactivities(
+            sequences,
+            constraints,
+            reactivities,
+            temp,
+            salt,
+            dangles,
+        )
     }
 
-    if bp_y.len() > 1 {
-        bp_x.iter_mut().for_each(|x| *x += 1); // Shift so that 1st position is 1
-        bp_y.iter_mut().for_each(|y| *y += 1); // Shift so that 1st position is 1
+    #[allow(clippy::too_many_arguments)]
+    fn subopt_with_reactivities<'a>(
+        &self,
+        sequences: &'a [&'a str],
+        constraints: &str,
+        reactivities: &[Option<f64>],
+        energy_gap: f32,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Vec<FoldResult<'a>> {
+        subopt_with_reactivities(
+            sequences,
+            constraints,
+            reactivities,
+            energy_gap,
+            temp,
+            salt,
+            dangles,
+        )
     }
 
-    return (bp_x, bp_y);
+    fn local_unpaired_probabilities(
+        &self,
+        sequence: &str,
+        window: usize,
+        span: usize,
+        temp: f32,
+        salt: &SaltConditions,
+        dangles: &DanglesSetting,
+    ) -> Result<Vec<f64>, Box<dyn Error>> {
+        local_unpaired_probabilities(sequence, window, span, temp, salt, dangles)
+    }
 }
@@ -0,0 +1,64 @@
+//! Compares the pure-Rust Turner backend with the ViennaRNA results of the Python version of
+//! OSTIR on the Salis2009 data set.
+#![cfg(feature = "turner")]
+
+use ostir::{DNASequence, Ostir, TurnerBackend};
+use std::collections::HashMap;
+use std::fs;
+
+fn read_csv(path: &str) -> Vec<HashMap<String, String>> {
+    let contents = fs::read_to_string(path).unwrap();
+    let mut lines = contents.lines();
+    let header: Vec<&str> = lines.next().unwrap().trim_end().split(',').collect();
+    lines
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            header
+                .iter()
+                .zip(line.split(','))
+                .map(|(column, value)| (column.to_string(), value.to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn turner_backend_matches_viennarna_on_salis2009() {
+    let inputs = read_csv("tests/input/Salis2009.csv");
+    let expected = read_csv("tests/expected/Salis2009.csv");
+    assert_eq!(inputs.len(), expected.len());
+
+    let mut mismatches = vec![];
+    for (input, expected) in inputs.iter().zip(&expected) {
+        let start: usize = input["start"].parse().unwrap();
+        let ostir = Ostir::builder()
+            .backend(TurnerBackend)
+            .range(start, start)
+            .build()
+            .unwrap();
+        let sequence = DNASequence::new(input["seq"].replace(' ', ""), 1, false).unwrap();
+        let predictions = ostir.predict(&sequence).unwrap();
+        assert_eq!(predictions.len(), 1, "{}", input["name"]);
+
+        let prediction = &predictions[0];
+        let columns = [
+            ("dG_mRNA", prediction.dg_mrna),
+            ("dG_rRNA:mRNA", prediction.dg_rrna_mrna),
+            ("dG_spacing", prediction.dg_spacing),
+            ("dG_standby", prediction.dg_standby),
+            ("dG_total", prediction.dg_total),
+        ];
+        for (column, value) in columns {
+            let reference: f64 = expected[column].parse().unwrap();
+            if (value - reference).abs() > 0.01 {
+                mismatches.push(format!(
+                    "{} {}: {} (ViennaRNA {})",
+                    input["name"], column, value, reference
+                ));
+            }
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}